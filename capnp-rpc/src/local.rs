@@ -300,6 +300,26 @@ impl Clone for Client {
     }
 }
 
+/// Replaces the description of the error with which generated servers fail calls to methods
+/// that they do not implement by one that names the method, so that it means something to the
+/// caller. Other errors are returned unchanged.
+fn name_unimplemented_method(error: Error, interface_id: u64, method_id: u16,
+                             method_name: Option<(&'static str, &'static str)>) -> Error
+{
+    if error.kind != ::capnp::ErrorKind::Unimplemented ||
+        error.description != capability::METHOD_NOT_IMPLEMENTED
+    {
+        return error;
+    }
+    match method_name {
+        Some((interface_name, method_name)) =>
+            Error::unimplemented(format!("Method not implemented: {}.{}", interface_name, method_name)),
+        None =>
+            Error::unimplemented(format!("Method not implemented: @{} of interface {:#x}",
+                                         method_id, interface_id)),
+    }
+}
+
 impl ClientHook for Client {
     fn add_ref(&self) -> Box<ClientHook> {
         Box::new(self.clone())
@@ -324,9 +344,11 @@ impl ClientHook for Client {
         let inner = self.inner.clone();
         let promise = ::futures::future::lazy(move || {
             let server = &mut inner.borrow_mut().server;
+            let method_name = server.method_name(interface_id, method_id);
             server.dispatch_call(interface_id, method_id,
                                  ::capnp::capability::Params::new(params),
                                  ::capnp::capability::Results::new(results))
+                .map_err(move |e| name_unimplemented_method(e, interface_id, method_id, method_name))
         }).attach(self.add_ref());
 
         Promise::from_future(promise)
@@ -1,5 +1,5 @@
 extern crate capnpc;
 
 fn main() {
-    ::capnpc::CompilerCommand::new().file("test.capnp").run().unwrap();
+    ::capnpc::CompilerCommand::new().file("test.capnp").mock_servers(true).run().unwrap();
 }
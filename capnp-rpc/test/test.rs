@@ -861,3 +861,82 @@ fn capability_list() {
         Ok(())
     })
 }
+
+#[test]
+fn mock_server() {
+    let mock = ::test_capnp::test_interface::Mock::new();
+    mock.foo.borrow_mut().respond_with(|params, mut results| {
+        results.set_x(&format!("i = {}", params.get_i()));
+        Ok(())
+    });
+    mock.foo.borrow_mut().respond_with_error(Error::failed("scripted failure".to_string()));
+    let client = ::test_capnp::test_interface::ToClient::new(mock.clone())
+        .into_client::<::capnp_rpc::Server>();
+
+    let mut request = client.foo_request();
+    request.get().set_i(123);
+    request.get().set_j(true);
+    let response = request.send().promise.wait().unwrap();
+    assert_eq!(response.get().unwrap().get_x().unwrap(), "i = 123");
+
+    let mut request = client.foo_request();
+    request.get().set_i(456);
+    match request.send().promise.wait() {
+        Err(e) => assert_eq!(e.description, "scripted failure"),
+        Ok(_) => panic!("expected the scripted error"),
+    }
+
+    // Once the scripted responses are used up, calls fail.
+    match client.bar_request().send().promise.wait() {
+        Err(e) => assert_eq!(e.kind, ::capnp::ErrorKind::Unimplemented),
+        Ok(_) => panic!("expected an unimplemented error"),
+    }
+
+    let foo = mock.foo.borrow();
+    assert_eq!(foo.call_count(), 2);
+    assert_eq!(foo.get_call(0).unwrap().get_i(), 123);
+    assert!(foo.get_call(0).unwrap().get_j());
+    assert_eq!(foo.get_call(1).unwrap().get_i(), 456);
+    match foo.get_call(2) {
+        Err(e) => assert_eq!(e.description, "TestInterface.foo has received 2 calls, so there is no call 2"),
+        Ok(_) => panic!("expected an error"),
+    }
+    assert_eq!(mock.bar.borrow().call_count(), 1);
+    assert_eq!(mock.baz.borrow().call_count(), 0);
+}
+
+#[test]
+fn unimplemented_method_names() {
+    use capnp::capability::FromClientHook;
+
+    struct Unimplemented;
+    impl ::test_capnp::test_interface::Server for Unimplemented {}
+    impl ::test_capnp::test_extends::Server for Unimplemented {}
+
+    let client = ::test_capnp::test_extends::ToClient::new(Unimplemented)
+        .into_client::<::capnp_rpc::Server>();
+
+    match client.qux_request().send().promise.wait() {
+        Err(e) => {
+            assert_eq!(e.kind, ::capnp::ErrorKind::Unimplemented);
+            assert_eq!(e.description, "Method not implemented: test.capnp:TestExtends.qux");
+        }
+        Ok(_) => panic!("expected an unimplemented error"),
+    }
+
+    let base: ::test_capnp::test_interface::Client = FromClientHook::new(client.client.hook.add_ref());
+    match base.bar_request().send().promise.wait() {
+        Err(e) => assert_eq!(e.description, "Method not implemented: test.capnp:TestInterface.bar"),
+        Ok(_) => panic!("expected an unimplemented error"),
+    }
+
+    // The server does not know this interface, so only its ID can be reported.
+    let other: ::test_capnp::test_more_stuff::Client = FromClientHook::new(client.client.hook.add_ref());
+    match other.get_handle_count_request().send().promise.wait() {
+        Err(e) => assert_eq!(
+            e.description,
+            format!("Method not implemented: @11 of interface {:#x}",
+                    ::test_capnp::test_more_stuff::_private::TYPE_ID)),
+        Ok(_) => panic!("expected an unimplemented error"),
+    }
+}
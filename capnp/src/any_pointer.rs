@@ -44,6 +44,16 @@ pub struct Reader<'a> {
     reader: PointerReader<'a>
 }
 
+impl <'a> ::std::fmt::Debug for Reader<'a> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        if self.is_null() {
+            f.write_str("null")
+        } else {
+            f.write_str("<AnyPointer>")
+        }
+    }
+}
+
 impl <'a> Reader<'a> {
     #[inline]
     pub fn new<'b>(reader: PointerReader<'b>) -> Reader<'b> {
@@ -229,11 +229,37 @@ impl Client {
     }
 }
 
+/// Returns the hook of a null capability, such as the value of an interface constant. Calls
+/// made on it fail with "Called null capability.", as in the C++ implementation.
+pub fn new_null_cap() -> Box<ClientHook> {
+    ::private::broken::new_cap(Error::failed("Called null capability.".to_string()))
+}
+
+/// The description of the `Unimplemented` error with which generated servers fail calls to
+/// methods that they do not implement. An RPC system may replace it with a description that
+/// names the method; see `Server::method_name()`.
+pub const METHOD_NOT_IMPLEMENTED: &str = "Method not implemented.";
+
 /// An untyped server.
 pub trait Server {
     fn dispatch_call(&mut self, interface_id: u64, method_id: u16,
                      params: Params<any_pointer::Owned>,
                      results: Results<any_pointer::Owned>)
                      -> Promise<(), Error>;
+
+    /// Returns the display name of the interface with ID `interface_id` and the name of its
+    /// method with ordinal `method_id`, if this server dispatches calls to that interface.
+    fn method_name(&self, _interface_id: u64, _method_id: u16) -> Option<(&'static str, &'static str)> {
+        None
+    }
 }
 
+#[test]
+fn calls_on_a_null_cap_fail() {
+    let client = Client::new(new_null_cap());
+    let request: Request<any_pointer::Owned, any_pointer::Owned> = client.new_call(0x1234, 0, None);
+    match request.hook.send().promise.inner {
+        PromiseInner::Immediate(Err(e)) => assert_eq!(e.description, "Called null capability."),
+        _ => panic!("expected an error"),
+    }
+}
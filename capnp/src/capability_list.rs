@@ -157,6 +157,12 @@ impl <'a, T> ::traits::SetPointerBuilder<Builder<'a, T>> for Reader<'a, T>
     }
 }
 
+impl <'a, T> ::std::fmt::Debug for Reader<'a, T> where T: FromClientHook {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        ::debug::fmt_list(f, self.len(), 0..self.len(), |_, f| f.write_str("<capability>"))
+    }
+}
+
 impl <'a, T> ::std::iter::IntoIterator for Reader<'a, T>
     where T: FromClientHook
 {
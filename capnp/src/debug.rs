@@ -0,0 +1,65 @@
+// Copyright (c) 2013-2015 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Formatting of lists for `Debug` and `Display`.
+
+use std::fmt;
+
+/// The number of elements of a list that are shown before the rest are elided.
+pub const LIST_LIMIT: u32 = 32;
+
+/// Formats a list of `len` elements, which `iter` yields, as `[a, b, c]`. Elements past
+/// `LIST_LIMIT` are summarized as `... and n more`.
+pub fn fmt_list<I, F>(f: &mut fmt::Formatter, len: u32, iter: I, fmt_element: F) -> fmt::Result
+    where I: Iterator, F: Fn(&I::Item, &mut fmt::Formatter) -> fmt::Result
+{
+    struct Element<'b, T: 'b, F: 'b>(&'b T, &'b F);
+
+    impl <'b, T, F> fmt::Debug for Element<'b, T, F>
+        where F: Fn(&T, &mut fmt::Formatter) -> fmt::Result
+    {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            (self.1)(self.0, f)
+        }
+    }
+
+    struct More(u32);
+
+    impl fmt::Debug for More {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "... and {} more", self.0)
+        }
+    }
+
+    let mut list = f.debug_list();
+    for element in iter.take(LIST_LIMIT as usize) {
+        list.entry(&Element(&element, &fmt_element));
+    }
+    if len > LIST_LIMIT {
+        list.entry(&More(len - LIST_LIMIT));
+    }
+    list.finish()
+}
+
+/// Formats an element that could not be read.
+pub fn fmt_error<E: fmt::Display>(error: &E, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "<{}>", error)
+}
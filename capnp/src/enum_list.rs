@@ -26,6 +26,7 @@ use private::layout::{ListReader, ListBuilder, PointerReader, PointerBuilder,
                       TwoBytes, PrimitiveElement};
 use {NotInSchema, Result};
 
+use std::borrow::Borrow;
 use std::marker::PhantomData;
 
 #[derive(Clone, Copy)]
@@ -136,6 +137,52 @@ impl <'a, T> ::traits::SetPointerBuilder<Builder<'a, T>> for Reader<'a, T> {
     }
 }
 
+impl <'a, 'b, T> ::traits::SetPointerBuilder<Builder<'a, T>> for &'b [T]
+    where T: ToU16 + FromU16 + Copy
+{
+    fn set_pointer_builder<'c>(pointer: PointerBuilder<'c>,
+                               value: &'b [T],
+                               _canonicalize: bool) -> Result<()> {
+        let mut list = Builder::<T>::init_pointer(pointer, value.len() as u32);
+        for (index, &element) in value.iter().enumerate() {
+            list.set(index as u32, element);
+        }
+        Ok(())
+    }
+}
+
+impl <'a, T, I> ::traits::SetPointerBuilder<Builder<'a, T>> for ::traits::FromIter<I>
+    where T: ToU16 + FromU16 + Copy, I: IntoIterator, I::Item: Borrow<T>
+{
+    fn set_pointer_builder<'b>(pointer: PointerBuilder<'b>,
+                               value: ::traits::FromIter<I>,
+                               canonicalize: bool) -> Result<()> {
+        let elements: Vec<T> = value.0.into_iter().map(|element| *element.borrow()).collect();
+        <&[T] as ::traits::SetPointerBuilder<Builder<T>>>::set_pointer_builder(
+            pointer, &elements, canonicalize)
+    }
+}
+
+impl <'a, T> ::std::fmt::Debug for Reader<'a, T> where T: FromU16 + ::std::fmt::Debug {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let list: Reader<T> = Reader { marker: PhantomData, reader: self.reader };
+        ::debug::fmt_list(f, self.len(), list.iter(), |v, f| match *v {
+            Ok(ref v) => ::std::fmt::Debug::fmt(v, f),
+            Err(ref e) => ::debug::fmt_error(e, f),
+        })
+    }
+}
+
+impl <'a, T> ::std::fmt::Display for Reader<'a, T> where T: FromU16 + ::std::fmt::Display {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let list: Reader<T> = Reader { marker: PhantomData, reader: self.reader };
+        ::debug::fmt_list(f, self.len(), list.iter(), |v, f| match *v {
+            Ok(ref v) => ::std::fmt::Display::fmt(v, f),
+            Err(ref e) => ::debug::fmt_error(e, f),
+        })
+    }
+}
+
 impl <'a, T: FromU16> ::std::iter::IntoIterator for Reader<'a, T> {
     type Item = ::std::result::Result<T, NotInSchema>;
     type IntoIter = ListIter<Reader<'a, T>, Self::Item>;
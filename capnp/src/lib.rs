@@ -31,6 +31,14 @@ extern crate byteorder;
 #[cfg(any(feature="quickcheck", test))]
 extern crate quickcheck;
 
+/// Re-exported so that code generated with `CompilerCommand::fill_random()` can refer to them.
+#[cfg(feature="quickcheck")]
+pub use quickcheck::{Arbitrary, Gen};
+
+/// Re-exported so that code generated with `CompilerCommand::serde()` can refer to it.
+#[cfg(feature = "serde")]
+pub extern crate serde;
+
 #[cfg(feature = "rpc")]
 extern crate futures;
 
@@ -75,15 +83,20 @@ pub mod data_list;
 pub mod enum_list;
 pub mod list_list;
 pub mod message;
+pub mod mock;
 pub mod primitive_list;
 pub mod private;
 pub mod serialize;
 pub mod serialize_packed;
+#[cfg(feature = "serde")]
+pub mod serde_support;
 pub mod struct_list;
 pub mod text;
 pub mod text_list;
 pub mod traits;
 
+mod debug;
+
 /// Eight bytes of memory with opaque interior. Use [`capnp_word!()`](macro.capnp_word!.html)
 /// to construct one of these.
 ///
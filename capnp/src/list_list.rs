@@ -146,6 +146,30 @@ impl <'a, T> ::traits::SetPointerBuilder<Builder<'a, T>> for Reader<'a, T>
     }
 }
 
+impl <'a, T> ::std::fmt::Debug for Reader<'a, T>
+    where T: for<'b> ::traits::Owned<'b>,
+          <T as ::traits::Owned<'a>>::Reader: ::std::fmt::Debug
+{
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        ::debug::fmt_list(f, self.len(), self.iter(), |v, f| match *v {
+            Ok(ref v) => ::std::fmt::Debug::fmt(v, f),
+            Err(ref e) => ::debug::fmt_error(e, f),
+        })
+    }
+}
+
+impl <'a, T> ::std::fmt::Display for Reader<'a, T>
+    where T: for<'b> ::traits::Owned<'b>,
+          <T as ::traits::Owned<'a>>::Reader: ::std::fmt::Display
+{
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        ::debug::fmt_list(f, self.len(), self.iter(), |v, f| match *v {
+            Ok(ref v) => ::std::fmt::Display::fmt(v, f),
+            Err(ref e) => ::debug::fmt_error(e, f),
+        })
+    }
+}
+
 impl <'a, T> ::std::iter::IntoIterator for Reader<'a, T>
     where T: for<'b> ::traits::Owned<'b>
 {
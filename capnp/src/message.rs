@@ -100,6 +100,16 @@ pub trait ReaderSegments {
         }
         unreachable!()
     }
+
+    /// Gives up the memory of the segments, as a buffer of words and the `(start, end)` range
+    /// of each segment within it, so that `Builder::from_segments()` can take it over instead
+    /// of copying it. Segments that do not own their memory are returned unchanged, which is
+    /// what the default implementation does.
+    fn into_owned(self) -> ::std::result::Result<(Vec<Word>, Vec<(usize, usize)>), Self>
+        where Self: Sized
+    {
+        Err(self)
+    }
 }
 
 /// An array of segments.
@@ -133,6 +143,22 @@ impl <'b> ReaderSegments for [&'b [Word]] {
     }
 }
 
+/// A single segment.
+impl ReaderSegments for Vec<Word> {
+    fn get_segment<'a>(&'a self, id: u32) -> Option<&'a [Word]> {
+        if id == 0 { Some(&self[..]) } else { None }
+    }
+
+    fn len(&self) -> usize {
+        1
+    }
+
+    fn into_owned(self) -> ::std::result::Result<(Vec<Word>, Vec<(usize, usize)>), Self> {
+        let len = self.len();
+        Ok((self, vec![(0, len)]))
+    }
+}
+
 /// A container used to read a message.
 pub struct Reader<S> where S: ReaderSegments {
     arena: ReaderArenaImpl<S>,
@@ -201,6 +227,49 @@ impl <S> Reader<S> where S: ReaderSegments {
         Ok(result)
     }
 
+    /// Writes the bytes of the [canonical](https://capnproto.org/encoding.html#canonicalization)
+    /// form of this message to `sink`, which will typically be a hash function or signer. This
+    /// yields the same bytes as `canonicalize()`, but streams them as the message is walked
+    /// instead of building a canonical copy: when the message is already canonical they are
+    /// written straight from its segment, and otherwise only one word per pointer in the
+    /// message is held in memory.
+    ///
+    /// These walks draw on a read limit of their own, so they neither fail because the
+    /// message has already been read nor count against reading it afterwards.
+    pub fn canonical_digest<W>(&self, sink: &mut W) -> Result<()>
+        where W: ::std::io::Write
+    {
+        if try!(self.arena.with_separate_read_limit(|| self.is_canonical())) {
+            let (segment_start, seg_len) = try!(self.arena.get_segment(0));
+            let words = unsafe { ::std::slice::from_raw_parts(segment_start, seg_len as usize) };
+            try!(sink.write_all(Word::words_to_bytes(words)));
+            Ok(())
+        } else {
+            let (segment_start, _seg_len) = try!(self.arena.get_segment(0));
+            let pointer_reader = try!(layout::PointerReader::get_root(
+                &self.arena, 0, segment_start, self.nesting_limit));
+            let (root, pointers) = try!(self.arena.with_separate_read_limit(|| {
+                pointer_reader.canonical_root_pointers()
+            }));
+            self.arena.with_separate_read_limit(|| {
+                pointer_reader.write_canonical_message(root, &pointers, sink)
+            })
+        }
+    }
+
+    /// Checks that the whole message is valid, so that a `Builder` can hold it, and returns
+    /// whether it has a root pointer.
+    fn validate_for_builder(&self) -> Result<bool> {
+        match self.arena.get_segments().get_segment(0) {
+            Some(segment) if segment.len() > 0 => (),
+            _ => return Ok(false),
+        }
+        try!(self.arena.with_separate_read_limit(|| {
+            try!(self.get_root_internal()).target_size()
+        }));
+        Ok(true)
+    }
+
     pub fn into_typed<T: for<'a> Owned<'a>>(self) -> TypedReader<S, T> {
         TypedReader::new(self)
     }
@@ -303,6 +372,64 @@ impl <A> Builder<A> where A: Allocator {
         }
     }
 
+    /// Constructs a builder that starts out holding a copy of the message in `reader`, so
+    /// that a received message can be modified in place and sent onward. The segments are
+    /// copied verbatim into a single block of memory obtained from `allocator`, because
+    /// `reader` only lends them; unlike `set_root()`, this does not rebuild the message object
+    /// by object, and values that are not modified keep their positions. Objects that are
+    /// replaced or resized are allocated anew, leaving the old space unused, as usual for a
+    /// `Builder`. To take over the segments of a reader without copying them, use
+    /// `from_segments()` with `reader.into_segments()`.
+    ///
+    /// Because a builder does not bounds-check its pointers, the whole message is first
+    /// traversed, and an error is returned if it is not valid. That traversal is limited by
+    /// `reader`'s options on its own, so it succeeds even if `reader` has already used up some
+    /// of its traversal limit.
+    pub fn from_reader<S>(allocator: A, reader: &Reader<S>) -> Result<Self>
+        where S: ReaderSegments
+    {
+        let segments = reader.arena.get_segments();
+        if !try!(reader.validate_for_builder()) {
+            return Ok(Builder::new(allocator));
+        }
+
+        let mut words = Vec::with_capacity(segments.len());
+        for id in 0..(segments.len() as u32) {
+            match segments.get_segment(id) {
+                Some(segment) => words.push(segment),
+                None => return Err(::Error::failed(format!("missing segment {}", id))),
+            }
+        }
+        let arena = BuilderArenaImpl::new(allocator);
+        try!(arena.import_segments(&words));
+        Ok(Builder { arena: arena })
+    }
+
+    /// Like `from_reader()`, but starts from raw segments, which are validated with the limits
+    /// in `options`. Segments that own their memory, such as the `OwnedSegments` returned by
+    /// `serialize::read_message()` or a `Vec<Word>`, are taken over by the builder without
+    /// being copied (see `ReaderSegments::into_owned()`); other segments are copied into
+    /// memory obtained from `allocator`. Either way, `allocator` provides the segments that
+    /// are needed for new objects.
+    pub fn from_segments<S>(allocator: A, segments: S, options: ReaderOptions) -> Result<Self>
+        where S: ReaderSegments
+    {
+        let reader = Reader::new(segments, options);
+        if !try!(reader.validate_for_builder()) {
+            return Ok(Builder::new(allocator));
+        }
+        match reader.into_segments().into_owned() {
+            Ok((words, ranges)) => {
+                let arena = BuilderArenaImpl::new(allocator);
+                try!(arena.adopt_segments(words, &ranges));
+                Ok(Builder { arena: arena })
+            }
+            Err(segments) => {
+                Builder::from_reader(allocator, &Reader::new(segments, options))
+            }
+        }
+    }
+
     fn get_root_internal<'a>(&'a mut self) -> any_pointer::Builder<'a> {
         if self.arena.len() == 0 {
             self.arena.allocate_segment(1).expect("allocate root pointer");
@@ -492,3 +619,108 @@ unsafe impl <'a, 'b: 'a> Allocator for ScratchSpaceHeapAllocator<'a, 'b> {
         self.scratch_space.in_use = false;
     }
 }
+
+#[test]
+fn builder_from_reader_modifies_in_place() {
+    let mut message = Builder::new_default();
+    {
+        let root: any_pointer::Builder = message.init_root();
+        let mut list: ::primitive_list::Builder<u32> = root.initn_as(3);
+        for idx in 0..3 {
+            list.set(idx, idx + 100);
+        }
+    }
+    let words = ::serialize::write_message_to_words(&message);
+    let reader = ::serialize::read_message_from_words(&words, ReaderOptions::new()).unwrap();
+
+    let mut copy = Builder::from_reader(HeapAllocator::new(), &reader).unwrap();
+    assert_eq!(&*copy.get_segments_for_output(), &*message.get_segments_for_output());
+    {
+        let mut list: ::primitive_list::Builder<u32> = copy.get_root().unwrap();
+        assert_eq!(list.get(2), 102);
+        list.set(1, 7);
+    }
+    {
+        let list: ::primitive_list::Reader<u32> = copy.get_root_as_reader().unwrap();
+        assert_eq!(list.get(0), 100);
+        assert_eq!(list.get(1), 7);
+        assert_eq!(list.get(2), 102);
+    }
+    assert_eq!(copy.get_segments_for_output()[0].len(), message.get_segments_for_output()[0].len());
+}
+
+#[test]
+fn builder_from_owned_segments_adopts_them() {
+    let mut message = Builder::new_default();
+    {
+        let root: any_pointer::Builder = message.init_root();
+        let mut list: ::primitive_list::Builder<u32> = root.initn_as(3);
+        list.set(1, 101);
+    }
+    let words = message.get_segments_for_output()[0].to_vec();
+    let start = words.as_ptr();
+
+    let mut copy = Builder::from_segments(HeapAllocator::new(), words, ReaderOptions::new()).unwrap();
+    assert_eq!(copy.get_segments_for_output()[0].as_ptr(), start);
+    {
+        let mut list: ::primitive_list::Builder<u32> = copy.get_root().unwrap();
+        list.set(2, 102);
+    }
+    {
+        // Does not fit into the adopted segment.
+        let root: any_pointer::Builder = copy.init_root();
+        let _: ::primitive_list::Builder<u64> = root.initn_as(100);
+    }
+    assert_eq!(copy.get_segments_for_output()[0].as_ptr(), start);
+    assert_eq!(copy.get_segments_for_output().len(), 2);
+
+    let bytes = ::serialize::write_message_to_words(&message);
+    let owned = ::serialize::read_message(&mut Word::words_to_bytes(&bytes), ReaderOptions::new()).unwrap();
+    let start = owned.arena.get_segments().get_segment(0).unwrap().as_ptr();
+    let copy = Builder::from_segments(HeapAllocator::new(), owned.into_segments(), ReaderOptions::new()).unwrap();
+    assert_eq!(copy.get_segments_for_output()[0].as_ptr(), start);
+}
+
+#[test]
+fn builder_from_invalid_segments() {
+    // A root pointer to a struct that lies past the end of the segment.
+    let segment = [capnp_word!(0x0c, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00)];
+    let segments: &[&[Word]] = &[&segment];
+    assert!(Builder::from_segments(HeapAllocator::new(), SegmentArray::new(segments),
+                                   ReaderOptions::new()).is_err());
+
+    let empty: &[&[Word]] = &[&[]];
+    let mut message = Builder::from_segments(HeapAllocator::new(), SegmentArray::new(empty),
+                                             ReaderOptions::new()).unwrap();
+    let root: any_pointer::Builder = message.init_root();
+    assert!(root.is_null());
+}
+
+#[test]
+fn canonical_digest_matches_canonicalize() {
+    let mut message = Builder::new_default();
+    {
+        let root: any_pointer::Builder = message.init_root();
+        let _: ::primitive_list::Builder<u64> = root.initn_as(100);
+    }
+    {
+        // Leaves garbage behind, so the message is no longer canonical.
+        let root: any_pointer::Builder = message.init_root();
+        let mut list: ::primitive_list::Builder<u16> = root.initn_as(3);
+        list.set(1, 0xabcd);
+    }
+    let reader = message.into_reader();
+    assert!(!reader.is_canonical().unwrap());
+
+    let canonical = reader.canonicalize().unwrap();
+    let mut digest = Vec::new();
+    reader.canonical_digest(&mut digest).unwrap();
+    assert_eq!(&digest[..], Word::words_to_bytes(&canonical));
+
+    let segments: &[&[Word]] = &[&canonical];
+    let canonical_reader = Reader::new(SegmentArray::new(segments), ReaderOptions::new());
+    assert!(canonical_reader.is_canonical().unwrap());
+    let mut digest2 = Vec::new();
+    canonical_reader.canonical_digest(&mut digest2).unwrap();
+    assert_eq!(digest, digest2);
+}
@@ -0,0 +1,98 @@
+// Copyright (c) 2013-2018 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Support for the mock servers generated with `CompilerCommand::mock_servers()`.
+
+use std::collections::VecDeque;
+
+use capability::{Params, Promise, Results};
+use message::{self, HeapAllocator};
+use traits::Owned;
+use {Error, Result};
+
+type Response<P, R> =
+    Box<for<'a, 'b> FnOnce(<P as Owned<'a>>::Reader, <R as Owned<'b>>::Builder) -> Result<()>>;
+
+/// The calls received by one method of a mock server, and the responses that are scripted
+/// for the calls to come.
+pub struct MockMethod<P, R> where P: for<'a> Owned<'a>, R: for<'a> Owned<'a> {
+    name: &'static str,
+    calls: Vec<message::Builder<HeapAllocator>>,
+    responses: VecDeque<Response<P, R>>,
+}
+
+impl <P, R> MockMethod<P, R> where P: for<'a> Owned<'a>, R: for<'a> Owned<'a> {
+    /// Creates a method that has not been called, with no responses. `name` identifies it
+    /// in errors.
+    pub fn new(name: &'static str) -> MockMethod<P, R> {
+        MockMethod { name, calls: Vec::new(), responses: VecDeque::new() }
+    }
+
+    /// Appends a response, which fills in the results of a call given its parameters.
+    pub fn respond_with<F>(&mut self, response: F)
+        where F: for<'a, 'b> FnOnce(<P as Owned<'a>>::Reader, <R as Owned<'b>>::Builder) -> Result<()> + 'static
+    {
+        self.responses.push_back(Box::new(response));
+    }
+
+    /// Appends a response that fails the call with `error`.
+    pub fn respond_with_error(&mut self, error: Error) {
+        self.respond_with(move |_, _| Err(error));
+    }
+
+    /// The number of calls that have been received.
+    pub fn call_count(&self) -> usize {
+        self.calls.len()
+    }
+
+    /// Reads the parameters of the call with the given index, in the order of arrival. Fails
+    /// if fewer calls than that have been received.
+    pub fn get_call<'a>(&'a self, index: usize) -> Result<<P as Owned<'a>>::Reader> {
+        match self.calls.get(index) {
+            Some(call) => call.get_root_as_reader(),
+            None => Err(Error::failed(
+                format!("{} has received {} calls, so there is no call {}",
+                        self.name, self.calls.len(), index))),
+        }
+    }
+
+    /// Records a call and answers it with the next response. A call for which no response
+    /// is left fails as unimplemented.
+    pub fn handle(&mut self, params: Params<P>, mut results: Results<R>) -> Promise<(), Error> {
+        let mut call = message::Builder::new_default();
+        match params.get().and_then(|p| call.set_root(p)) {
+            Ok(()) => (),
+            Err(e) => return Promise::err(e),
+        }
+        self.calls.push(call);
+
+        let response = match self.responses.pop_front() {
+            Some(response) => response,
+            None => return Promise::err(Error::unimplemented(
+                format!("no response is scripted for call {} of {}", self.calls.len(), self.name))),
+        };
+        let call = &self.calls[self.calls.len() - 1];
+        match call.get_root_as_reader().and_then(|p| response(p, results.get())) {
+            Ok(()) => Promise::ok(()),
+            Err(e) => Promise::err(e),
+        }
+    }
+}
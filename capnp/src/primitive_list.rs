@@ -22,6 +22,7 @@
 //! List of primitives.
 
 use std::{marker, ptr};
+use std::borrow::Borrow;
 
 use traits::{FromPointerReader, FromPointerBuilder, IndexMove, ListIter};
 use private::layout::{ListReader, ListBuilder, PointerReader, PointerBuilder,
@@ -138,6 +139,46 @@ impl <'a, T> ::traits::SetPointerBuilder<Builder<'a, T>> for Reader<'a, T>
     }
 }
 
+impl <'a, 'b, T> ::traits::SetPointerBuilder<Builder<'a, T>> for &'b [T]
+    where T: PrimitiveElement + Copy
+{
+    fn set_pointer_builder<'c>(pointer: PointerBuilder<'c>,
+                               value: &'b [T],
+                               _canonicalize: bool) -> Result<()> {
+        let mut list = Builder::<T>::init_pointer(pointer, value.len() as u32);
+        for (index, &element) in value.iter().enumerate() {
+            list.set(index as u32, element);
+        }
+        Ok(())
+    }
+}
+
+impl <'a, T, I> ::traits::SetPointerBuilder<Builder<'a, T>> for ::traits::FromIter<I>
+    where T: PrimitiveElement + Copy, I: IntoIterator, I::Item: Borrow<T>
+{
+    fn set_pointer_builder<'b>(pointer: PointerBuilder<'b>,
+                               value: ::traits::FromIter<I>,
+                               canonicalize: bool) -> Result<()> {
+        let elements: Vec<T> = value.0.into_iter().map(|element| *element.borrow()).collect();
+        <&[T] as ::traits::SetPointerBuilder<Builder<T>>>::set_pointer_builder(
+            pointer, &elements, canonicalize)
+    }
+}
+
+impl <'a, T> ::std::fmt::Debug for Reader<'a, T> where T: PrimitiveElement + ::std::fmt::Debug {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let list: Reader<T> = Reader { marker: marker::PhantomData, reader: self.reader };
+        ::debug::fmt_list(f, self.len(), list.iter(), ::std::fmt::Debug::fmt)
+    }
+}
+
+impl <'a, T> ::std::fmt::Display for Reader<'a, T> where T: PrimitiveElement + ::std::fmt::Display {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let list: Reader<T> = Reader { marker: marker::PhantomData, reader: self.reader };
+        ::debug::fmt_list(f, self.len(), list.iter(), |v, f| ::std::fmt::Display::fmt(v, f))
+    }
+}
+
 impl <'a, T> ::std::iter::IntoIterator for Reader<'a, T>
     where T: PrimitiveElement
 {
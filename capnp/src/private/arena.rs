@@ -64,6 +64,7 @@ pub trait ReaderArena {
 pub struct ReaderArenaImpl<S> {
     segments: S,
     read_limiter: ReadLimiter,
+    traversal_limit_in_words: u64,
 }
 
 impl <S> ReaderArenaImpl <S> where S: ReaderSegments {
@@ -75,12 +76,27 @@ impl <S> ReaderArenaImpl <S> where S: ReaderSegments {
         ReaderArenaImpl {
             segments: segments,
             read_limiter: limiter,
+            traversal_limit_in_words: options.traversal_limit_in_words,
         }
     }
 
+    /// Runs `f` with the full read limit that the arena started out with, and then restores
+    /// the limit that was left, so that a traversal made on the reader's behalf is limited
+    /// on its own and does not use up what is left for the reader's user.
+    pub fn with_separate_read_limit<F, T>(&self, f: F) -> T where F: FnOnce() -> T {
+        let remaining = self.read_limiter.limit.replace(self.traversal_limit_in_words);
+        let result = f();
+        self.read_limiter.limit.set(remaining);
+        result
+    }
+
     pub fn into_segments(self) -> S {
         self.segments
     }
+
+    pub fn get_segments<'a>(&'a self) -> &'a S {
+        &self.segments
+    }
 }
 
 impl <S> ReaderArena for ReaderArenaImpl<S> where S: ReaderSegments {
@@ -143,6 +159,13 @@ pub struct BuilderArenaImplInner<A> where A: Allocator {
     // TODO(perf): Try using smallvec to avoid heap allocations in the single-segment case?
     segments: Vec<(*mut Word, u32)>,
     allocated: Vec<u32>, // number of words allocated for each segment.
+
+    // Memory of the first `adopted_segments` segments, which were not obtained from `allocator`.
+    adopted: Vec<Word>,
+    adopted_segments: usize,
+
+    // Number of segments that share the memory of the first segment obtained from `allocator`.
+    first_allocation_segments: usize,
 }
 
 pub struct BuilderArenaImpl<A> where A: Allocator {
@@ -156,14 +179,47 @@ impl <A> BuilderArenaImpl<A> where A: Allocator {
                 allocator: allocator,
                 segments: Vec::new(),
                 allocated: Vec::new(),
+                adopted: Vec::new(),
+                adopted_segments: 0,
+                first_allocation_segments: 1,
             }),
         }
     }
 
+    /// Takes over `words` as the memory of the arena's first segments, which are the
+    /// `(start, end)` ranges of it in `segments`, all of whose words count as allocated.
+    /// Must be called before any segment is allocated.
+    pub fn adopt_segments(&self, mut words: Vec<Word>, segments: &[(usize, usize)]) -> Result<()> {
+        let mut inner = self.inner.borrow_mut();
+        assert!(inner.segments.is_empty(), "adopt_segments() called on an arena that has segments");
+        let base = words.as_mut_ptr();
+        for &(start, end) in segments {
+            if start > end || end > words.len() {
+                return Err(Error::failed(format!(
+                    "segment {}..{} is out of the bounds of its {} words", start, end, words.len())));
+            }
+            let len = (end - start) as WordCount32;
+            inner.segments.push((unsafe { base.offset(start as isize) }, len));
+            inner.allocated.push(len);
+        }
+        inner.adopted = words;
+        inner.adopted_segments = segments.len();
+        Ok(())
+    }
+
     pub fn allocate_segment(&self, minimum_size: u32) -> Result<()> {
         self.inner.borrow_mut().allocate_segment(minimum_size)
     }
 
+    /// Allocates a new segment and copies `words` into it, marking them as allocated.
+    /// Copies `segments` into a single block of memory obtained from the allocator, and makes
+    /// them the arena's first segments. Must be called before any segment is allocated.
+    pub fn import_segments(&self, segments: &[&[Word]]) -> Result<()> {
+        let mut inner = self.inner.borrow_mut();
+        assert!(inner.segments.is_empty(), "import_segments() called on an arena that has segments");
+        inner.import_segments(segments)
+    }
+
     pub fn get_segments_for_output<'a>(&'a self) -> OutputSegments<'a> {
         let reff = self.inner.borrow();
         if reff.allocated.len() == 1 {
@@ -220,6 +276,32 @@ impl <A> BuilderArenaImplInner<A> where A: Allocator {
         Ok(())
     }
 
+    fn import_segments(&mut self, segments: &[&[Word]]) -> Result<()> {
+        let total = segments.iter().map(|segment| segment.len()).sum::<usize>() as WordCount32;
+        let (ptr, size) = self.allocator.allocate_segment(total);
+        if size < total {
+            self.segments.push((ptr, size));
+            self.allocated.push(0);
+            return Err(Error::failed(format!(
+                "allocator returned a segment of {} words, but {} were requested", size, total)));
+        }
+        let mut start = 0;
+        for (idx, segment) in segments.iter().enumerate() {
+            let len = segment.len() as WordCount32;
+            let seg_ptr = unsafe { ptr.offset(start as isize) };
+            unsafe {
+                ::std::ptr::copy_nonoverlapping(segment.as_ptr(), seg_ptr, segment.len());
+            }
+            // The last segment gets whatever space is left over.
+            let capacity = if idx + 1 == segments.len() { size - start } else { len };
+            self.segments.push((seg_ptr, capacity));
+            self.allocated.push(len);
+            start += len;
+        }
+        self.first_allocation_segments = segments.len();
+        Ok(())
+    }
+
     fn allocate(&mut self, segment_id: u32, amount: WordCount32) -> Option<u32> {
         if amount > self.get_segment_mut(segment_id).1 as u32 - self.allocated[segment_id as usize] {
             None
@@ -273,8 +355,11 @@ impl <A> BuilderArena for BuilderArenaImpl<A> where A: Allocator {
 
 impl <A> Drop for BuilderArenaImplInner<A> where A: Allocator {
     fn drop(&mut self) {
-        if self.allocated.len() > 0 {
-            self.allocator.pre_drop(self.allocated[0]);
+        // The allocator is told how much of the first segment that it allocated is in use.
+        let first = self.adopted_segments;
+        if self.allocated.len() > first {
+            let end = ::std::cmp::min(first + self.first_allocation_segments, self.allocated.len());
+            self.allocator.pre_drop(self.allocated[first..end].iter().sum());
         }
     }
 }
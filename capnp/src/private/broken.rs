@@ -0,0 +1,119 @@
+// Copyright (c) 2013-2015 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+
+//! A capability on which every call fails, roughly corresponding to `newBrokenCap()` in the C++
+//! implementation.
+
+use std::rc::Rc;
+
+use any_pointer;
+use capability::{Promise, RemotePromise, Request};
+use message::{self, HeapAllocator};
+use private::capability::{ClientHook, ParamsHook, PipelineHook, PipelineOp, RequestHook, ResultsHook};
+use {Error, MessageSize};
+
+struct Pipeline {
+    error: Error,
+}
+
+impl PipelineHook for Pipeline {
+    fn add_ref(&self) -> Box<PipelineHook> {
+        Box::new(Pipeline { error: self.error.clone() })
+    }
+
+    fn get_pipelined_cap(&self, _ops: &[PipelineOp]) -> Box<ClientHook> {
+        new_cap(self.error.clone())
+    }
+}
+
+struct BrokenRequest {
+    error: Error,
+    message: message::Builder<HeapAllocator>,
+}
+
+impl RequestHook for BrokenRequest {
+    fn get<'a>(&'a mut self) -> any_pointer::Builder<'a> {
+        self.message.get_root().unwrap()
+    }
+
+    fn get_brand(&self) -> usize {
+        0
+    }
+
+    fn send<'a>(self: Box<Self>) -> RemotePromise<any_pointer::Owned> {
+        let pipeline = Pipeline { error: self.error.clone() };
+        RemotePromise {
+            promise: Promise::err(self.error),
+            pipeline: any_pointer::Pipeline::new(Box::new(pipeline)),
+        }
+    }
+
+    fn tail_send(self: Box<Self>) -> Option<(u32, Promise<(), Error>, Box<PipelineHook>)> {
+        None
+    }
+}
+
+struct Client {
+    error: Rc<Error>,
+}
+
+impl ClientHook for Client {
+    fn add_ref(&self) -> Box<ClientHook> {
+        Box::new(Client { error: self.error.clone() })
+    }
+
+    fn new_call(&self, _interface_id: u64, _method_id: u16, _size_hint: Option<MessageSize>)
+                -> Request<any_pointer::Owned, any_pointer::Owned>
+    {
+        Request::new(Box::new(BrokenRequest {
+            error: (*self.error).clone(),
+            message: message::Builder::new_default(),
+        }))
+    }
+
+    fn call(&self, _interface_id: u64, _method_id: u16,
+            _params: Box<ParamsHook>, _results: Box<ResultsHook>) -> Promise<(), Error>
+    {
+        Promise::err((*self.error).clone())
+    }
+
+    fn get_brand(&self) -> usize {
+        0
+    }
+
+    fn get_ptr(&self) -> usize {
+        (self.error.as_ref()) as *const _ as usize
+    }
+
+    fn get_resolved(&self) -> Option<Box<ClientHook>> {
+        None
+    }
+
+    fn when_more_resolved(&self) -> Option<Promise<Box<ClientHook>, Error>> {
+        None
+    }
+}
+
+/// Returns a capability on which every call fails with `error`.
+pub fn new_cap(error: Error) -> Box<ClientHook> {
+    Box::new(Client { error: Rc::new(error) })
+}
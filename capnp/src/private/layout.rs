@@ -23,6 +23,8 @@ use std::mem;
 use std::ptr;
 use std::cell::Cell;
 
+use byteorder::{ByteOrder, LittleEndian};
+
 use data;
 use text;
 use private::capability::{ClientHook};
@@ -2422,6 +2424,260 @@ impl <'a> PointerReader<'a> {
     }
 }
 
+/// The canonical encoding of a pointer, as computed by `PointerReader::canonical_pointers()`.
+struct CanonicalPointer {
+    /// The pointer, with an offset of zero if `positional`.
+    word: u64,
+
+    /// Whether the pointer's offset depends on where its target is placed.
+    positional: bool,
+
+    /// Size in words of the canonical encoding of the target and the objects reachable
+    /// from it.
+    size: u64,
+}
+
+impl CanonicalPointer {
+    /// Returns the pointer word for a pointer at word `position` whose target starts at word
+    /// `target`, both counted from the same origin.
+    fn at(&self, position: u64, target: u64) -> Result<u64> {
+        if !self.positional {
+            return Ok(self.word)
+        }
+        let offset = target - position - 1;
+        if offset >= 1 << 29 {
+            return Err(::Error::failed("Message is too large to be encoded canonically.".to_string()))
+        }
+        Ok(self.word | (offset << 2))
+    }
+}
+
+/// An empty struct is canonically encoded as if it were placed just before its pointer.
+const EMPTY_STRUCT_POINTER: u64 = 0xfffffffc;
+
+fn struct_pointer_word(data_words: u32, pointer_count: u16) -> u64 {
+    ((data_words as u64) | ((pointer_count as u64) << 16)) << 32 | WirePointerKind::Struct as u64
+}
+
+fn list_pointer_word(element_size: ElementSize, count: u32) -> u64 {
+    (((count as u64) << 3) | element_size as u64) << 32 | WirePointerKind::List as u64
+}
+
+fn write_canonical_words<W: ::std::io::Write>(sink: &mut W, words: &[u64]) -> Result<()> {
+    let mut buf = [0; 8];
+    for &word in words {
+        LittleEndian::write_u64(&mut buf, word);
+        try!(sink.write_all(&buf));
+    }
+    Ok(())
+}
+
+/// Writes `bytes`, followed by zeros up to a total of `words` words.
+fn write_canonical_data<W: ::std::io::Write>(sink: &mut W, bytes: &[u8], words: u32) -> Result<()> {
+    let len = ::std::cmp::min(words as usize * BYTES_PER_WORD, bytes.len());
+    try!(sink.write_all(&bytes[..len]));
+    write_zeros(sink, words as usize * BYTES_PER_WORD - len)
+}
+
+fn write_zeros<W: ::std::io::Write>(sink: &mut W, mut count: usize) -> Result<()> {
+    while count > 0 {
+        let n = ::std::cmp::min(count, BYTES_PER_WORD);
+        try!(sink.write_all(&[0; BYTES_PER_WORD][..n]));
+        count -= n;
+    }
+    Ok(())
+}
+
+/// Takes the next `count` pointer words computed by `PointerReader::canonical_pointers()`.
+fn next_canonical_pointers<'a>(pointers: &'a [u64], next: &mut usize, count: usize) -> Result<&'a [u64]> {
+    match pointers.get(*next..*next + count) {
+        Some(words) => {
+            *next += count;
+            Ok(words)
+        }
+        None => Err(::Error::failed("Message changed while being encoded canonically.".to_string())),
+    }
+}
+
+/// Computes the canonical encodings of `count` pointers, given with their positions, of an
+/// object whose canonical encoding takes `content_size` words, and appends them to `pointers`,
+/// followed by the encodings of the pointers reachable from them. Returns the size of the
+/// object together with the objects reachable from it.
+fn append_canonical_pointers<'a, I>(pointers: &mut Vec<u64>, content_size: u64, count: usize,
+                                    children: I) -> Result<u64>
+    where I: Iterator<Item=(u64, PointerReader<'a>)>
+{
+    let first = pointers.len();
+    pointers.resize(first + count, 0);
+    let mut end = content_size;
+    for (i, (position, child)) in children.enumerate() {
+        let canonical = try!(child.canonical_pointers(pointers));
+        pointers[first + i] = try!(canonical.at(position, end));
+        end += canonical.size;
+    }
+    Ok(end)
+}
+
+impl <'a> PointerReader<'a> {
+    /// Computes the canonical encoding of this pointer, and appends the canonical encodings of
+    /// the pointers in its target and in the objects reachable from there to `pointers`, in
+    /// the order in which `write_canonical()` needs them. Together with the size of each
+    /// object, that is all that canonical encoding needs to know ahead of time, so the message
+    /// itself does not have to be copied.
+    fn canonical_pointers(&self, pointers: &mut Vec<u64>) -> Result<CanonicalPointer> {
+        match try!(self.get_pointer_type()) {
+            PointerType::Null => Ok(CanonicalPointer { word: 0, positional: false, size: 0 }),
+            PointerType::Capability => Err(::Error::failed(
+                "Cannot create a canonical message with a capability".to_string())),
+            PointerType::Struct => {
+                let st = try!(self.get_struct(ptr::null()));
+                let (data_words, pointer_count) = st.canonical_section_sizes();
+                if data_words == 0 && pointer_count == 0 {
+                    return Ok(CanonicalPointer { word: EMPTY_STRUCT_POINTER, positional: false, size: 0 })
+                }
+                let size = try!(append_canonical_pointers(
+                    pointers, data_words as u64 + pointer_count as u64, pointer_count as usize,
+                    (0..pointer_count as usize).map(|i| ((data_words as usize + i) as u64, st.get_pointer_field(i)))));
+                Ok(CanonicalPointer {
+                    word: struct_pointer_word(data_words, pointer_count),
+                    positional: true,
+                    size: size,
+                })
+            }
+            PointerType::List => {
+                let list = try!(self.get_list_any_size(ptr::null()));
+                let count = list.len();
+                match list.element_size {
+                    InlineComposite => {
+                        let (data_words, pointer_count) = list.canonical_element_sizes();
+                        let element_words = data_words as u64 + pointer_count as u64;
+                        let word_count = element_words * count as u64;
+                        if word_count >= 1 << 29 {
+                            return Err(::Error::failed(
+                                "Message is too large to be encoded canonically.".to_string()))
+                        }
+                        let size = try!(append_canonical_pointers(
+                            pointers, 1 + word_count, count as usize * pointer_count as usize,
+                            (0..count).flat_map(|e| (0..pointer_count as usize).map(move |k| {
+                                (1 + e as u64 * element_words + data_words as u64 + k as u64,
+                                 list.get_struct_element(e).get_pointer_field(k))
+                            }))));
+                        Ok(CanonicalPointer {
+                            word: list_pointer_word(InlineComposite, word_count as u32),
+                            positional: true,
+                            size: size,
+                        })
+                    }
+                    Pointer => {
+                        let size = try!(append_canonical_pointers(
+                            pointers, count as u64, count as usize,
+                            (0..count).map(|i| (i as u64, list.get_pointer_element(i)))));
+                        Ok(CanonicalPointer {
+                            word: list_pointer_word(Pointer, count),
+                            positional: true,
+                            size: size,
+                        })
+                    }
+                    element_size => Ok(CanonicalPointer {
+                        word: list_pointer_word(element_size, count),
+                        positional: true,
+                        size: wire_helpers::round_bits_up_to_words(count as u64 * list.step as u64) as u64,
+                    }),
+                }
+            }
+        }
+    }
+
+    /// Writes the canonical encoding of the target of this pointer and of the objects
+    /// reachable from it to `sink`, taking the encodings of their pointers from `pointers`,
+    /// starting at `*next`, as computed by `canonical_pointers()`.
+    fn write_canonical<W>(&self, pointers: &[u64], next: &mut usize, sink: &mut W) -> Result<()>
+        where W: ::std::io::Write
+    {
+        match try!(self.get_pointer_type()) {
+            PointerType::Null | PointerType::Capability => Ok(()),
+            PointerType::Struct => {
+                let st = try!(self.get_struct(ptr::null()));
+                let (data_words, pointer_count) = st.canonical_section_sizes();
+                try!(write_canonical_data(sink, st.get_data_section_as_blob(), data_words));
+                try!(write_canonical_words(sink, try!(next_canonical_pointers(pointers, next, pointer_count as usize))));
+                for i in 0..pointer_count as usize {
+                    try!(st.get_pointer_field(i).write_canonical(pointers, next, sink));
+                }
+                Ok(())
+            }
+            PointerType::List => {
+                let list = try!(self.get_list_any_size(ptr::null()));
+                let count = list.len();
+                match list.element_size {
+                    InlineComposite => {
+                        let (data_words, pointer_count) = list.canonical_element_sizes();
+                        let tag = (((data_words as u64) | ((pointer_count as u64) << 16)) << 32)
+                            | ((count as u64) << 2) | WirePointerKind::Struct as u64;
+                        try!(write_canonical_words(sink, &[tag]));
+                        for e in 0..count {
+                            let element = list.get_struct_element(e);
+                            try!(write_canonical_data(sink, element.get_data_section_as_blob(), data_words));
+                            try!(write_canonical_words(
+                                sink, try!(next_canonical_pointers(pointers, next, pointer_count as usize))));
+                        }
+                        for e in 0..count {
+                            let element = list.get_struct_element(e);
+                            for k in 0..pointer_count as usize {
+                                try!(element.get_pointer_field(k).write_canonical(pointers, next, sink));
+                            }
+                        }
+                        Ok(())
+                    }
+                    Pointer => {
+                        try!(write_canonical_words(sink, try!(next_canonical_pointers(pointers, next, count as usize))));
+                        for i in 0..count {
+                            try!(list.get_pointer_element(i).write_canonical(pointers, next, sink));
+                        }
+                        Ok(())
+                    }
+                    _ => {
+                        let bits = count as u64 * list.step as u64;
+                        let whole_bytes = (bits / BITS_PER_BYTE as u64) as usize;
+                        let bytes = unsafe { ::std::slice::from_raw_parts(list.ptr, whole_bytes) };
+                        let words = wire_helpers::round_bits_up_to_words(bits);
+                        let leftover_bits = bits % BITS_PER_BYTE as u64;
+                        try!(sink.write_all(bytes));
+                        let mut written = whole_bytes;
+                        if leftover_bits > 0 {
+                            // Bits past the end of the list are left out.
+                            let mask: u8 = (1 << leftover_bits as u8) - 1;
+                            let last = mask & unsafe { *list.ptr.offset(whole_bytes as isize) };
+                            try!(sink.write_all(&[last]));
+                            written += 1;
+                        }
+                        write_zeros(sink, words as usize * BYTES_PER_WORD - written)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Computes the canonical encoding of this pointer as the root pointer of a message,
+    /// together with the encodings of the pointers reachable from it, for
+    /// `write_canonical_message()`.
+    pub fn canonical_root_pointers(&self) -> Result<(u64, Vec<u64>)> {
+        let mut pointers = Vec::new();
+        let root = try!(self.canonical_pointers(&mut pointers));
+        Ok((try!(root.at(0, 1)), pointers))
+    }
+
+    /// Writes the canonical encoding of the message whose root is this pointer to `sink`,
+    /// given what `canonical_root_pointers()` returned: the root pointer, followed by the
+    /// encodings of the objects reachable from it.
+    pub fn write_canonical_message<W>(&self, root: u64, pointers: &[u64], sink: &mut W) -> Result<()>
+        where W: ::std::io::Write
+    {
+        try!(write_canonical_words(sink, &[root]));
+        self.write_canonical(pointers, &mut 0, sink)
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct PointerBuilder<'a> {
     arena: &'a BuilderArena,
@@ -2665,6 +2921,24 @@ impl <'a> StructReader<'a> {
         }
     }
 
+    /// Returns the sizes of the data section, in words, and of the pointer section of the
+    /// canonical encoding of this struct, which leaves out trailing zero words and null
+    /// pointers.
+    pub fn canonical_section_sizes(&self) -> (WordCount32, WirePointerCount16) {
+        let data = self.get_data_section_as_blob();
+        let mut data_words = wire_helpers::round_bytes_up_to_words(data.len() as u32);
+        while data_words != 0 &&
+            data[(data_words as usize - 1) * BYTES_PER_WORD..].iter().all(|&b| b == 0)
+        {
+            data_words -= 1;
+        }
+        let mut pointer_count = self.pointer_count;
+        while pointer_count != 0 && self.get_pointer_field(pointer_count as usize - 1).is_null() {
+            pointer_count -= 1;
+        }
+        (data_words, pointer_count)
+    }
+
     #[inline]
     pub fn get_data_field<T: Endian + zero::Zero>(&self, offset: ElementCount) -> T {
         // We need to check the offset because the struct may have
@@ -3022,6 +3296,20 @@ impl <'a> ListReader<'a> {
         }
     }
 
+    /// Returns the sizes of the data section, in words, and of the pointer section of the
+    /// elements of the canonical encoding of this list of structs: the largest sizes of the
+    /// canonical encodings of its elements.
+    pub fn canonical_element_sizes(&self) -> (WordCount32, WirePointerCount16) {
+        let mut data_words = 0;
+        let mut pointer_count = 0;
+        for i in 0..self.element_count {
+            let (d, p) = self.get_struct_element(i).canonical_section_sizes();
+            data_words = ::std::cmp::max(data_words, d);
+            pointer_count = ::std::cmp::max(pointer_count, p);
+        }
+        (data_words, pointer_count)
+    }
+
     #[inline]
     pub fn get_pointer_element(self, index: ElementCount32) -> PointerReader<'a> {
         let offset = (index as u64 * self.step as u64 / BITS_PER_BYTE as u64) as u32;
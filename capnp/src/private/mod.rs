@@ -26,6 +26,7 @@
 use Word;
 
 pub mod arena;
+pub mod broken;
 pub mod capability;
 pub mod endian;
 pub mod layout;
@@ -0,0 +1,401 @@
+// Copyright (c) 2013-2018 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Support for the [serde](https://serde.rs) implementations that are generated with
+//! `CompilerCommand::serde()`. Requires the `serde` feature.
+//!
+//! Lists serialize as sequences, `Data` as bytes, and enums by their enumerant names.
+//! A null pointer serializes as a unit value and deserializes as a field left unset.
+//! Capabilities cannot be serialized.
+//!
+//! Deserialization writes into a builder: a generated struct `Builder` implements
+//! `DeserializeSeed`. Because it needs to tell maps apart from unit values and bare
+//! union member names, it only works with self-describing formats.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{self, Deserialize, DeserializeOwned, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, Serialize, SerializeSeq, Serializer};
+
+use private::layout::PrimitiveElement;
+use traits::{FromPointerBuilder, FromPointerReader, FromU16, IntoInternalStructReader, Owned,
+             OwnedStruct, ToU16};
+use {any_pointer, capability_list, data_list, enum_list, list_list, message, primitive_list,
+     struct_list, text_list, Result};
+use capability::FromClientHook;
+
+/// Serializes a byte slice with `Serializer::serialize_bytes()`, rather than as a sequence.
+pub struct Bytes<'a>(pub &'a [u8]);
+
+impl <'a> Serialize for Bytes<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+/// Bytes that deserialize from either a byte string or a sequence of integers.
+pub struct ByteBuf(pub Vec<u8>);
+
+impl <'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> ::std::result::Result<ByteBuf, D::Error> {
+        struct ByteBufVisitor;
+
+        impl <'de> Visitor<'de> for ByteBufVisitor {
+            type Value = ByteBuf;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("bytes")
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> ::std::result::Result<ByteBuf, E> {
+                Ok(ByteBuf(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> ::std::result::Result<ByteBuf, E> {
+                Ok(ByteBuf(v))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> ::std::result::Result<ByteBuf, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(ByteBuf(bytes))
+            }
+        }
+
+        deserializer.deserialize_byte_buf(ByteBufVisitor)
+    }
+}
+
+/// A value that may only be null, such as an `AnyPointer` field. Deserializing anything
+/// else fails.
+pub struct NullPointer;
+
+impl <'de> Deserialize<'de> for NullPointer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> ::std::result::Result<NullPointer, D::Error> {
+        struct NullVisitor;
+
+        impl <'de> Visitor<'de> for NullVisitor {
+            type Value = NullPointer;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a null pointer")
+            }
+
+            fn visit_unit<E: de::Error>(self) -> ::std::result::Result<NullPointer, E> {
+                Ok(NullPointer)
+            }
+
+            fn visit_none<E: de::Error>(self) -> ::std::result::Result<NullPointer, E> {
+                Ok(NullPointer)
+            }
+        }
+
+        deserializer.deserialize_option(NullVisitor)
+    }
+}
+
+fn serialize_elements<S, I, F>(serializer: S, len: u32, iter: I, mut serialize_element: F)
+                               -> ::std::result::Result<S::Ok, S::Error>
+    where S: Serializer, I: Iterator,
+          F: FnMut(&mut S::SerializeSeq, I::Item) -> ::std::result::Result<(), S::Error>
+{
+    let mut seq = serializer.serialize_seq(Some(len as usize))?;
+    for element in iter {
+        serialize_element(&mut seq, element)?;
+    }
+    seq.end()
+}
+
+impl <'a, T> Serialize for primitive_list::Reader<'a, T> where T: PrimitiveElement + Serialize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        serialize_elements(serializer, self.len(), (0..self.len()).map(|i| self.get(i)),
+                           |seq, v| seq.serialize_element(&v))
+    }
+}
+
+impl <'a, T> Serialize for enum_list::Reader<'a, T> where T: FromU16 + Serialize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        serialize_elements(serializer, self.len(), (0..self.len()).map(|i| self.get(i)), |seq, v| {
+            seq.serialize_element(&v.map_err(ser::Error::custom)?)
+        })
+    }
+}
+
+impl <'a> Serialize for text_list::Reader<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        serialize_elements(serializer, self.len(), self.iter(), |seq, v| {
+            seq.serialize_element(v.map_err(ser::Error::custom)?)
+        })
+    }
+}
+
+impl <'a> Serialize for data_list::Reader<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        serialize_elements(serializer, self.len(), self.iter(), |seq, v| {
+            seq.serialize_element(&Bytes(v.map_err(ser::Error::custom)?))
+        })
+    }
+}
+
+impl <'a, T> Serialize for struct_list::Reader<'a, T>
+    where T: for<'b> OwnedStruct<'b>,
+          <T as OwnedStruct<'a>>::Reader: Serialize
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        serialize_elements(serializer, self.len(), self.iter(), |seq, v| seq.serialize_element(&v))
+    }
+}
+
+impl <'a, T> Serialize for list_list::Reader<'a, T>
+    where T: for<'b> Owned<'b>,
+          <T as Owned<'a>>::Reader: Serialize
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        serialize_elements(serializer, self.len(), self.iter(), |seq, v| {
+            seq.serialize_element(&v.map_err(ser::Error::custom)?)
+        })
+    }
+}
+
+impl <'a, T> Serialize for capability_list::Reader<'a, T> where T: FromClientHook {
+    fn serialize<S: Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        if self.len() > 0 {
+            return Err(ser::Error::custom("cannot serialize a capability"))
+        }
+        serializer.serialize_seq(Some(0))?.end()
+    }
+}
+
+impl <'a> Serialize for any_pointer::Reader<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        if self.is_null() {
+            serializer.serialize_unit()
+        } else {
+            Err(ser::Error::custom("cannot serialize a non-null AnyPointer"))
+        }
+    }
+}
+
+/// Implemented by generated struct builders, to set fields by their schema names.
+pub trait DeserializeFields {
+    /// Sets the field named `name` from the next value of `map`. Unknown names are skipped.
+    fn deserialize_field<'de, A>(&mut self, name: &str, map: &mut A) -> ::std::result::Result<(), A::Error>
+        where A: MapAccess<'de>;
+
+    /// Sets the `Void` union member named `name`.
+    fn set_void_member<E: de::Error>(&mut self, name: &str) -> ::std::result::Result<(), E>;
+}
+
+/// Deserializes a struct into the builder returned by `init`. The struct may be a map of
+/// field names to values, the name of a `Void` union member, or a unit value, in which case
+/// `init` is not called.
+pub fn deserialize_struct<'de, D, F, T>(deserializer: D, init: F) -> ::std::result::Result<(), D::Error>
+    where D: Deserializer<'de>, F: FnOnce() -> T, T: DeserializeFields
+{
+    struct StructVisitor<F>(F);
+
+    impl <'de, F, T> Visitor<'de> for StructVisitor<F> where F: FnOnce() -> T, T: DeserializeFields {
+        type Value = ();
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a struct or the name of a union member")
+        }
+
+        fn visit_unit<E: de::Error>(self) -> ::std::result::Result<(), E> {
+            Ok(())
+        }
+
+        fn visit_none<E: de::Error>(self) -> ::std::result::Result<(), E> {
+            Ok(())
+        }
+
+        fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> ::std::result::Result<(), D::Error> {
+            deserialize_struct(deserializer, self.0)
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> ::std::result::Result<(), E> {
+            (self.0)().set_void_member(v)
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> ::std::result::Result<(), A::Error> {
+            let mut builder = (self.0)();
+            while let Some(name) = map.next_key::<String>()? {
+                builder.deserialize_field(&name, &mut map)?;
+            }
+            Ok(())
+        }
+    }
+
+    deserializer.deserialize_any(StructVisitor(init))
+}
+
+/// Deserializes a struct with `deserialize_struct()`, for use with
+/// `MapAccess::next_value_seed()`.
+pub struct StructSeed<F>(pub F);
+
+impl <'de, F, T> DeserializeSeed<'de> for StructSeed<F> where F: FnOnce() -> T, T: DeserializeFields {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> ::std::result::Result<(), D::Error> {
+        deserialize_struct(deserializer, self.0)
+    }
+}
+
+/// Implemented by the `Owned` types of lists whose elements can be deserialized. Since a
+/// list's length must be known before it is allocated, its elements are first
+/// deserialized into a buffer.
+pub trait DeserializeList: for<'a> Owned<'a> {
+    type Buffer: DeserializeOwned;
+
+    fn buffer_len(buffer: &Self::Buffer) -> u32;
+
+    fn write<'a>(buffer: Self::Buffer, builder: <Self as Owned<'a>>::Builder) -> Result<()>;
+}
+
+/// A list value that has been deserialized, but not yet written to a message. `None`
+/// stands for a null pointer.
+pub struct ListValue<T: DeserializeList>(pub Option<T::Buffer>);
+
+impl <'de, T: DeserializeList> Deserialize<'de> for ListValue<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> ::std::result::Result<ListValue<T>, D::Error> {
+        Ok(ListValue(Deserialize::deserialize(deserializer)?))
+    }
+}
+
+impl <T: DeserializeList> ListValue<T> {
+    /// Writes the list to the builder that `init` returns given the list's length. Does
+    /// nothing for a null list.
+    pub fn write<'a, F>(self, init: F) -> Result<()>
+        where F: FnOnce(u32) -> <T as Owned<'a>>::Builder
+    {
+        match self.0 {
+            None => Ok(()),
+            Some(buffer) => {
+                let builder = init(T::buffer_len(&buffer));
+                T::write(buffer, builder)
+            }
+        }
+    }
+}
+
+impl <T> DeserializeList for primitive_list::Owned<T> where T: PrimitiveElement + DeserializeOwned {
+    type Buffer = Vec<T>;
+
+    fn buffer_len(buffer: &Vec<T>) -> u32 { buffer.len() as u32 }
+
+    fn write<'a>(buffer: Vec<T>, mut builder: primitive_list::Builder<'a, T>) -> Result<()> {
+        for (i, v) in buffer.into_iter().enumerate() {
+            builder.set(i as u32, v);
+        }
+        Ok(())
+    }
+}
+
+impl <T> DeserializeList for enum_list::Owned<T> where T: ToU16 + FromU16 + DeserializeOwned {
+    type Buffer = Vec<T>;
+
+    fn buffer_len(buffer: &Vec<T>) -> u32 { buffer.len() as u32 }
+
+    fn write<'a>(buffer: Vec<T>, mut builder: enum_list::Builder<'a, T>) -> Result<()> {
+        for (i, v) in buffer.into_iter().enumerate() {
+            builder.set(i as u32, v);
+        }
+        Ok(())
+    }
+}
+
+impl DeserializeList for text_list::Owned {
+    type Buffer = Vec<String>;
+
+    fn buffer_len(buffer: &Vec<String>) -> u32 { buffer.len() as u32 }
+
+    fn write<'a>(buffer: Vec<String>, mut builder: text_list::Builder<'a>) -> Result<()> {
+        for (i, v) in buffer.iter().enumerate() {
+            builder.set(i as u32, v);
+        }
+        Ok(())
+    }
+}
+
+impl DeserializeList for data_list::Owned {
+    type Buffer = Vec<ByteBuf>;
+
+    fn buffer_len(buffer: &Vec<ByteBuf>) -> u32 { buffer.len() as u32 }
+
+    fn write<'a>(buffer: Vec<ByteBuf>, mut builder: data_list::Builder<'a>) -> Result<()> {
+        for (i, v) in buffer.iter().enumerate() {
+            builder.set(i as u32, &v.0);
+        }
+        Ok(())
+    }
+}
+
+/// A struct that has been deserialized into a message of its own, from which it is copied
+/// once the list that holds it has been allocated.
+pub struct StructValue<T> {
+    message: message::Builder<message::HeapAllocator>,
+    marker: PhantomData<T>,
+}
+
+impl <'de, T> Deserialize<'de> for StructValue<T>
+    where T: for<'a> OwnedStruct<'a>,
+          for<'a> <T as OwnedStruct<'a>>::Builder: FromPointerBuilder<'a> + DeserializeFields
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> ::std::result::Result<StructValue<T>, D::Error> {
+        let mut message = message::Builder::new_default();
+        deserialize_struct(deserializer, || message.init_root::<<T as OwnedStruct>::Builder>())?;
+        Ok(StructValue { message, marker: PhantomData })
+    }
+}
+
+impl <T> DeserializeList for struct_list::Owned<T>
+    where T: for<'a> OwnedStruct<'a>,
+          for<'a> <T as OwnedStruct<'a>>::Builder: FromPointerBuilder<'a> + DeserializeFields,
+          for<'a> <T as OwnedStruct<'a>>::Reader: FromPointerReader<'a> + IntoInternalStructReader<'a>
+{
+    type Buffer = Vec<StructValue<T>>;
+
+    fn buffer_len(buffer: &Vec<StructValue<T>>) -> u32 { buffer.len() as u32 }
+
+    fn write<'a>(buffer: Vec<StructValue<T>>, builder: struct_list::Builder<'a, T>) -> Result<()> {
+        for (i, v) in buffer.iter().enumerate() {
+            let root: <T as OwnedStruct>::Reader = v.message.get_root_as_reader()?;
+            builder.set_with_caveats(i as u32, root)?;
+        }
+        Ok(())
+    }
+}
+
+impl <T> DeserializeList for list_list::Owned<T> where T: DeserializeList {
+    type Buffer = Vec<ListValue<T>>;
+
+    fn buffer_len(buffer: &Vec<ListValue<T>>) -> u32 { buffer.len() as u32 }
+
+    fn write<'a>(buffer: Vec<ListValue<T>>, mut builder: list_list::Builder<'a, T>) -> Result<()> {
+        for (i, v) in buffer.into_iter().enumerate() {
+            v.write(|len| builder.reborrow().init(i as u32, len))?;
+        }
+        Ok(())
+    }
+}
@@ -85,6 +85,10 @@ impl ::message::ReaderSegments for OwnedSegments {
     fn len(&self) -> usize {
         self.segment_slices.len()
     }
+
+    fn into_owned(self) -> ::std::result::Result<(Vec<Word>, Vec<(usize, usize)>), Self> {
+        Ok((self.owned_space, self.segment_slices))
+    }
 }
 
 /// Reads a serialized message from a stream with the provided options.
@@ -96,6 +100,19 @@ where R: Read {
     read_segments(read, total_words, segment_slices, options)
 }
 
+/// Reads a serialized message from a stream with the provided options, returning an error
+/// if the message is not in [canonical](https://capnproto.org/encoding.html#canonicalization)
+/// form.
+pub fn read_canonical_message<R>(read: &mut R, options: message::ReaderOptions) -> Result<message::Reader<OwnedSegments>>
+where R: Read {
+    let message = try!(read_message(read, options));
+    if try!(message.is_canonical()) {
+        Ok(message)
+    } else {
+        Err(Error::failed("Message is not canonical.".to_string()))
+    }
+}
+
 /// Reads a segment table from `read` and returns the total number of words across all
 /// segments, as well as the segment offsets.
 ///
@@ -306,8 +323,8 @@ pub mod test {
     use {Word};
     use message;
     use message::ReaderSegments;
-    use super::{read_message, read_message_from_words, flatten_segments,
-                read_segment_table, write_segment_table, write_segments};
+    use super::{read_message, read_message_from_words, read_canonical_message, flatten_segments,
+                read_segment_table, write_segment_table, write_segments, write_message};
 
     /// Writes segments as if they were a Capnproto message.
     pub fn write_message_segments<W>(write: &mut W, segments: &Vec<Vec<Word>>) where W: Write {
@@ -501,4 +518,25 @@ pub mod test {
 
         quickcheck(round_trip as fn(Vec<Vec<Word>>) -> TestResult);
     }
+
+    #[test]
+    fn read_canonical() {
+        let mut message = message::Builder::new_default();
+        {
+            let root: ::any_pointer::Builder = message.init_root();
+            root.set_as("canonical").unwrap();
+        }
+        let mut buf = Vec::new();
+        write_message(&mut buf, &message).unwrap();
+        read_canonical_message(&mut Cursor::new(&buf[..]), message::ReaderOptions::new()).unwrap();
+
+        {
+            let root: ::any_pointer::Builder = message.init_root();
+            root.set_as("no longer canonical").unwrap();
+        }
+        buf.clear();
+        write_message(&mut buf, &message).unwrap();
+        assert!(read_canonical_message(&mut Cursor::new(&buf[..]),
+                                       message::ReaderOptions::new()).is_err());
+    }
 }
@@ -26,7 +26,7 @@ use std::{io, mem, ptr, slice};
 use std::io::{Read, BufRead, Write};
 
 use serialize;
-use Result;
+use {Error, Result, Word};
 use message;
 
 struct PackedRead<R> where R: BufRead {
@@ -368,6 +368,80 @@ pub fn write_message<W, A>(write: &mut W, message : &::message::Builder<A>) -> i
     serialize::write_message(&mut packed_write, message)
 }
 
+/// Reads a packed message from a slice of bytes using the provided options. On success,
+/// `slice` is advanced past the end of the message.
+pub fn read_message_from_slice(slice: &mut &[u8],
+                               options: message::ReaderOptions)
+                               -> Result<::message::Reader<serialize::OwnedSegments>>
+{
+    let mut packed_read = PackedRead { inner: slice };
+    serialize::read_message(&mut packed_read, options)
+}
+
+/// Packs `input` and appends the result to `output`.
+pub fn pack_into(input: &[Word], output: &mut Vec<u8>) {
+    if input.is_empty() { return; }
+    let mut packed_write = PackedWrite { inner: output };
+    packed_write.write_all(Word::words_to_bytes(input)).expect("writing to a Vec<u8> cannot fail");
+}
+
+/// Unpacks `packed`, which must consist of a whole number of packed words.
+pub fn unpack_into(packed: &[u8]) -> Result<Vec<Word>> {
+    let mut result = Word::allocate_zeroed_vec(compute_unpacked_size_in_words(packed)?);
+    let mut packed_read = PackedRead { inner: packed };
+    packed_read.read_exact(Word::words_to_bytes_mut(&mut result[..]))?;
+    Ok(result)
+}
+
+/// Scans the tags of `packed` to determine how many words it unpacks to.
+fn compute_unpacked_size_in_words(packed: &[u8]) -> Result<usize> {
+    let mut idx = 0;
+    let mut words = 0;
+    while idx < packed.len() {
+        let tag = packed[idx];
+        idx += 1 + tag.count_ones() as usize;
+        words += 1;
+        if tag == 0 || tag == 0xff {
+            if idx >= packed.len() {
+                return Err(Error::failed("Premature end of packed input.".to_string()));
+            }
+            let run_length = packed[idx] as usize;
+            idx += 1;
+            words += run_length;
+            if tag == 0xff {
+                idx += run_length * 8;
+            }
+        }
+    }
+    if idx > packed.len() {
+        return Err(Error::failed("Premature end of packed input.".to_string()));
+    }
+    Ok(words)
+}
+
+/// A `Write` that discards its input, keeping track only of how many bytes it was given.
+struct ByteCounter {
+    count: usize,
+}
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.count += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+/// Returns the number of bytes that `write_message()` would write for this message.
+pub fn compute_serialized_packed_size<A>(message: &::message::Builder<A>) -> usize
+    where A: ::message::Allocator
+{
+    let mut counter = ByteCounter { count: 0 };
+    write_message(&mut counter, message).expect("writing to a ByteCounter cannot fail");
+    counter.count
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -381,7 +455,8 @@ mod tests {
     use message::{ReaderOptions};
     use serialize::test::write_message_segments;
     use serialize_packed::{PackedRead, PackedWrite};
-    use super::read_message;
+    use super::{read_message, read_message_from_slice, write_message, pack_into, unpack_into,
+                compute_serialized_packed_size};
 
     pub fn check_unpacks_to(packed: &[u8], unpacked: &[u8]) {
         let mut packed_read = PackedRead { inner: packed };
@@ -535,4 +610,42 @@ mod tests {
         // reading the segment table only one word at a time.
         read_message(&mut cursor, Default::default()).unwrap();
     }
+
+    #[test]
+    fn check_slice_round_trip() {
+        fn round_trip(words: Vec<Word>) -> TestResult {
+            let mut packed = Vec::new();
+            pack_into(&words, &mut packed);
+            TestResult::from_bool(unpack_into(&packed).unwrap() == words)
+        }
+
+        quickcheck(round_trip as fn(Vec<Word>) -> TestResult);
+    }
+
+    #[test]
+    fn unpack_into_premature_end() {
+        assert!(unpack_into(&[0xf0, 1, 2]).is_err());
+        assert!(unpack_into(&[0]).is_err());
+        assert!(unpack_into(&[0xff, 1, 2, 3, 4, 5, 6, 7, 8]).is_err());
+        assert!(unpack_into(&[0xff, 1, 2, 3, 4, 5, 6, 7, 8, 1, 1]).is_err());
+    }
+
+    #[test]
+    fn serialized_packed_size() {
+        let mut message = ::message::Builder::new_default();
+        {
+            let root: ::any_pointer::Builder = message.init_root();
+            root.set_as("hello, packed world").unwrap();
+        }
+        let mut bytes = Vec::new();
+        write_message(&mut bytes, &message).unwrap();
+        assert_eq!(compute_serialized_packed_size(&message), bytes.len());
+
+        bytes.extend([0xde, 0xad].iter().cloned());
+        let mut slice = &bytes[..];
+        let reader = read_message_from_slice(&mut slice, ReaderOptions::new()).unwrap();
+        let text: ::text::Reader = reader.get_root().unwrap();
+        assert_eq!(text, "hello, packed world");
+        assert_eq!(slice, &[0xde, 0xad]);
+    }
 }
@@ -170,6 +170,34 @@ impl <'a, T> ::traits::SetPointerBuilder<Builder<'a, T>> for Reader<'a, T>
     }
 }
 
+/// Copies the structs into the list as `Builder::set_with_caveats()` does, so a struct that is
+/// larger than the structs of the list is truncated.
+impl <'a, 'r, T, I> ::traits::SetPointerBuilder<Builder<'a, T>> for ::traits::FromIter<I>
+    where T: for<'b> ::traits::OwnedStruct<'b>,
+          I: IntoIterator<Item=<T as ::traits::OwnedStruct<'r>>::Reader>,
+          <T as ::traits::OwnedStruct<'r>>::Reader: ::traits::IntoInternalStructReader<'r>
+{
+    fn set_pointer_builder<'b>(pointer: PointerBuilder<'b>,
+                               value: ::traits::FromIter<I>,
+                               _canonicalize: bool) -> Result<()> {
+        let elements: Vec<_> = value.0.into_iter().collect();
+        let list = Builder::<T>::init_pointer(pointer, elements.len() as u32);
+        for (index, element) in elements.into_iter().enumerate() {
+            list.set_with_caveats(index as u32, element)?;
+        }
+        Ok(())
+    }
+}
+
+impl <'a, T> ::std::fmt::Debug for Reader<'a, T>
+    where T: for<'b> ::traits::OwnedStruct<'b>,
+          <T as ::traits::OwnedStruct<'a>>::Reader: ::std::fmt::Debug
+{
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        ::debug::fmt_list(f, self.len(), self.iter(), ::std::fmt::Debug::fmt)
+    }
+}
+
 impl <'a, T> ::std::iter::IntoIterator for Reader<'a, T>
     where T: for<'b> ::traits::OwnedStruct<'b>
 {
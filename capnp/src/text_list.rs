@@ -128,6 +128,48 @@ impl <'a> ::traits::SetPointerBuilder<Builder<'a>> for Reader<'a> {
     }
 }
 
+impl <'a, 'b, 'c> ::traits::SetPointerBuilder<Builder<'a>> for &'b [&'c str] {
+    fn set_pointer_builder<'d>(pointer: PointerBuilder<'d>,
+                               value: &'b [&'c str],
+                               canonicalize: bool) -> Result<()> {
+        <::traits::FromIter<&[&str]> as ::traits::SetPointerBuilder<Builder>>::set_pointer_builder(
+            pointer, ::traits::FromIter(value), canonicalize)
+    }
+}
+
+impl <'a, I> ::traits::SetPointerBuilder<Builder<'a>> for ::traits::FromIter<I>
+    where I: IntoIterator, I::Item: AsRef<str>
+{
+    fn set_pointer_builder<'b>(pointer: PointerBuilder<'b>,
+                               value: ::traits::FromIter<I>,
+                               _canonicalize: bool) -> Result<()> {
+        let elements: Vec<I::Item> = value.0.into_iter().collect();
+        let mut list = Builder::init_pointer(pointer, elements.len() as u32);
+        for (index, element) in elements.iter().enumerate() {
+            list.set(index as u32, element.as_ref());
+        }
+        Ok(())
+    }
+}
+
+impl <'a> ::std::fmt::Debug for Reader<'a> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        ::debug::fmt_list(f, self.len(), self.iter(), |v, f| match *v {
+            Ok(ref v) => ::std::fmt::Debug::fmt(v, f),
+            Err(ref e) => ::debug::fmt_error(e, f),
+        })
+    }
+}
+
+impl <'a> ::std::fmt::Display for Reader<'a> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        ::debug::fmt_list(f, self.len(), self.iter(), |v, f| match *v {
+            Ok(ref v) => ::std::fmt::Display::fmt(v, f),
+            Err(ref e) => ::debug::fmt_error(e, f),
+        })
+    }
+}
+
 impl <'a> ::std::iter::IntoIterator for Reader<'a> {
     type Item = Result<::text::Reader<'a>>;
     type IntoIter = ListIter<Reader<'a>, Self::Item>;
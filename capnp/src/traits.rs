@@ -87,6 +87,15 @@ pub trait SetPointerBuilder<To> {
     fn set_pointer_builder<'a>(PointerBuilder<'a>, Self, canonicalize: bool) -> Result<()>;
 }
 
+/// Wraps the elements of a list, as anything that can be iterated over, so that the list can
+/// be set from them wherever a list reader can be set, as by the `set_foo_from()` methods of
+/// generated code or by `any_pointer::Builder::set_as()`.
+///
+/// Primitive and enum lists accept the elements or references to them, text lists anything
+/// that is `AsRef<str>`, data lists anything that is `AsRef<[u8]>`, and struct lists struct
+/// readers.
+pub struct FromIter<I>(pub I);
+
 pub trait Imbue<'a> {
     fn imbue(&mut self, &'a CapTable);
 }
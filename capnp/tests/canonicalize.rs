@@ -24,6 +24,15 @@ extern crate capnp;
 
 use capnp::{Word, message};
 
+/// Checks that `message.canonical_digest()` streams the same bytes as `canonicalize()` returned.
+fn assert_canonical_digest_matches<S>(message: &message::Reader<S>, canonical: &[Word])
+    where S: message::ReaderSegments
+{
+    let mut digest = Vec::new();
+    message.canonical_digest(&mut digest).unwrap();
+    assert_eq!(&digest[..], Word::words_to_bytes(canonical));
+}
+
 #[test]
 fn canonicalize_succeeds_on_null_message() {
     let segment: &[Word] = &[
@@ -36,6 +45,8 @@ fn canonicalize_succeeds_on_null_message() {
     assert!(message.is_canonical().unwrap());
 
     let canonical_words = message.canonicalize().unwrap();
+
+    assert_canonical_digest_matches(&message, &canonical_words);
     assert_eq!(&canonical_words[..], segment);
 }
 
@@ -62,6 +73,8 @@ fn dont_truncate_struct_too_far() {
 
     let canonicalized = message.canonicalize().unwrap();
 
+    assert_canonical_digest_matches(&message, &canonicalized);
+
     let canonical_segment: &[Word] = &[
         capnp_word!(0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00),
         capnp_word!(0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11),
@@ -100,6 +113,8 @@ fn dont_truncate_struct_list_too_far() {
 
     let canonicalized = message.canonicalize().unwrap();
 
+    assert_canonical_digest_matches(&message, &canonicalized);
+
     let canonical_segment: &[Word] = &[
         capnp_word!(0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00),
         capnp_word!(0x01, 0x00, 0x00, 0x00, 0x17, 0x00, 0x00, 0x00),
@@ -221,6 +236,8 @@ fn simple_multisegment_message() {
     assert!(!message.is_canonical().unwrap());
 
     let canonicalized = message.canonicalize().unwrap();
+
+    assert_canonical_digest_matches(&message, &canonicalized);
     let canonical_segment: &[Word] = &[
         capnp_word!(0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00),
         capnp_word!(0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07),
@@ -247,6 +264,8 @@ fn multisegment_only_first_segment_used() {
     assert!(!message.is_canonical().unwrap());
 
     let canonicalized = message.canonicalize().unwrap();
+
+    assert_canonical_digest_matches(&message, &canonicalized);
     let canonical_segment: &[Word] = &[
         capnp_word!(0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00),
         capnp_word!(0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07),
@@ -309,6 +328,8 @@ fn empty_inline_composite_list_of_0_sized_structs() {
     assert!(message.is_canonical().unwrap());
 
     let canonical_words = message.canonicalize().unwrap();
+
+    assert_canonical_digest_matches(&message, &canonical_words);
     assert_eq!(Word::words_to_bytes(segment), Word::words_to_bytes(&canonical_words));
 }
 
@@ -331,6 +352,8 @@ fn inline_composite_list_with_void_list() {
     assert!(message.is_canonical().unwrap());
 
     let canonical_words = message.canonicalize().unwrap();
+
+    assert_canonical_digest_matches(&message, &canonical_words);
     assert_eq!(Word::words_to_bytes(segment), Word::words_to_bytes(&canonical_words));
 }
 
@@ -382,6 +405,8 @@ fn truncate_data_section_inline_composite() {
 
     let canonical_words = message.canonicalize().unwrap();
 
+    assert_canonical_digest_matches(&message, &canonical_words);
+
     let canonical_segments = &[&canonical_words[..]];
     let canonical_segment_array = message::SegmentArray::new(canonical_segments);
     let canonical_message = message::Reader::new(canonical_segment_array, Default::default());
@@ -406,6 +431,8 @@ fn truncate_pointer_section_inline_composite() {
 
     let canonical_words = message.canonicalize().unwrap();
 
+    assert_canonical_digest_matches(&message, &canonical_words);
+
     let canonical_segments = &[&canonical_words[..]];
     let canonical_segment_array = message::SegmentArray::new(canonical_segments);
     let canonical_message = message::Reader::new(canonical_segment_array, Default::default());
@@ -439,6 +466,8 @@ fn list_padding_must_be_zero() {
 
     let canonical_words = message.canonicalize().unwrap();
 
+    assert_canonical_digest_matches(&message, &canonical_words);
+
     let canonical_segments = &[&canonical_words[..]];
     let canonical_segment_array = message::SegmentArray::new(canonical_segments);
     let canonical_message = message::Reader::new(canonical_segment_array, Default::default());
@@ -472,6 +501,8 @@ fn bit_list_padding_must_be_zero() {
 
     let canonical_words = message.canonicalize().unwrap();
 
+    assert_canonical_digest_matches(&message, &canonical_words);
+
     let canonical_segments = &[&canonical_words[..]];
     let canonical_segment_array = message::SegmentArray::new(canonical_segments);
     let canonical_message = message::Reader::new(canonical_segment_array, Default::default());
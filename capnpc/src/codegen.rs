@@ -19,25 +19,164 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
+use std::cell::RefCell;
 use std::collections;
-use std::collections::HashSet;
+use std::collections::BTreeSet;
 
 use capnp;
 use capnp::Error;
 
+use debug_impls::generate_debug_impls;
+use diagnostics::{self, Diagnostic, Severity};
+use fill_random::generate_fill_random;
+use serde_impls::{generate_client_serde_impl, generate_enum_serde_impls, generate_serde_impls};
+use native_types::{generate_native_type, has_native_type};
 use pointer_constants::generate_pointer_constant;
 use schema_capnp;
 use codegen_types::{ Leaf, RustTypeInfo, RustNodeInfo, TypeParameterTexts, do_branding };
 use self::FormattedText::{Indent, Line, Branch, BlankLine};
-use RustEdition;
+use {OutputLayout, RustEdition};
 
-fn root_scope(rust_edition: RustEdition, root_name: String) -> Vec<String> {
+/// ID of the `name` annotation declared in `rust.capnp`.
+pub const NAME_ANNOTATION_ID: u64 = 0xc2fe4c6d100166d0;
+
+/// ID of the `parentModule` annotation declared in `rust.capnp`.
+pub const PARENT_MODULE_ANNOTATION_ID: u64 = 0xabee386cd1450364;
+
+/// Returns the path of the module generated for a file. A file generated by another crate
+/// is at the root of that crate, whatever parent module this crate would give it.
+fn root_scope(rust_edition: RustEdition, external_crate: Option<&String>,
+              mut parent_module: Vec<String>, root_name: String) -> Vec<String> {
+    if let Some(crate_name) = external_crate {
+        return vec![format!("::{}", crate_name), root_name];
+    }
+    parent_module.push(root_name);
     match rust_edition {
         RustEdition::Rust2015 => {
-            vec![format!("::{}", root_name)]
+            parent_module[0] = format!("::{}", parent_module[0]);
+            parent_module
         }
         RustEdition::Rust2018 => {
-            vec!["crate".into(), root_name]
+            parent_module.insert(0, "crate".into());
+            parent_module
+        }
+    }
+}
+
+fn text_annotation_value<'a>(annotation: schema_capnp::annotation::Reader<'a>) -> ::capnp::Result<&'a str> {
+    match annotation.get_value()?.which()? {
+        schema_capnp::value::Text(t) => t,
+        _ => Err(Error::failed(format!("expected annotation {} to have a Text value", annotation.get_id()))),
+    }
+}
+
+/// Returns the value of the `$Rust.name` annotation among `annotations`, if there is one.
+pub fn get_name_annotation<'a>(
+    annotations: ::capnp::struct_list::Reader<'a, schema_capnp::annotation::Owned>)
+    -> ::capnp::Result<Option<&'a str>>
+{
+    for annotation in annotations.iter() {
+        if annotation.get_id() == NAME_ANNOTATION_ID {
+            return Ok(Some(text_annotation_value(annotation)?));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the name of `field` to use in generated code, taking `$Rust.name` into account.
+pub fn get_field_name<'a>(field: schema_capnp::field::Reader<'a>) -> ::capnp::Result<&'a str> {
+    match get_name_annotation(field.get_annotations()?)? {
+        Some(name) => Ok(name),
+        None => field.get_name(),
+    }
+}
+
+/// Returns the name of `enumerant` to use in generated code, taking `$Rust.name` into account.
+pub fn get_enumerant_name<'a>(enumerant: schema_capnp::enumerant::Reader<'a>) -> ::capnp::Result<&'a str> {
+    match get_name_annotation(enumerant.get_annotations()?)? {
+        Some(name) => Ok(name),
+        None => enumerant.get_name(),
+    }
+}
+
+/// Returns the module path given by the `$Rust.parentModule` annotation of the file
+/// with ID `file_id`, or `default` if there is no such annotation.
+fn get_parent_module(node_map: &collections::hash_map::HashMap<u64, schema_capnp::node::Reader>,
+                     file_id: u64, default: &[String]) -> ::capnp::Result<Vec<String>> {
+    if let Some(node) = node_map.get(&file_id) {
+        for annotation in node.get_annotations()?.iter() {
+            if annotation.get_id() == PARENT_MODULE_ANNOTATION_ID {
+                return Ok(parse_module_path(text_annotation_value(annotation)?));
+            }
+        }
+    }
+    Ok(default.to_vec())
+}
+
+/// Splits a module path like `foo::bar` into its components. An empty path has none.
+pub fn parse_module_path(path: &str) -> Vec<String> {
+    path.split("::").filter(|s| !s.is_empty()).map(|s| s.to_string()).collect()
+}
+
+/// Settings that control what code gets generated.
+#[derive(Clone)]
+pub struct GeneratorOptions {
+    /// The Rust edition that the generated code targets.
+    pub edition: RustEdition,
+
+    /// Maps the IDs of schema files whose generated code lives in another crate to the
+    /// names of those crates. Such files are referred to through the crate's root
+    /// module instead of being generated again.
+    pub crates_provide_map: collections::hash_map::HashMap<u64, String>,
+
+    /// If true, each struct module gets a `fill_random()` function that fills a `Builder`
+    /// with arbitrary valid contents. The generated code requires the `quickcheck` feature
+    /// of the `capnp` crate.
+    pub fill_random: bool,
+
+    /// If true, each non-generic struct module gets a `Native` type: an owned mirror of
+    /// the struct's contents, with `from_reader()` and `write_to_builder()` conversions.
+    pub native_types: bool,
+
+    /// If true, struct readers and builders implement `Debug`, showing their fields by
+    /// name.
+    pub debug_impls: bool,
+
+    /// If true, each interface module gets a `Mock` server for tests, which records the
+    /// calls it receives and answers them with scripted responses.
+    pub mock_servers: bool,
+
+    /// If true, struct readers implement serde's `Serialize` and struct builders its
+    /// `DeserializeSeed`. The generated code requires the `serde` feature of the `capnp`
+    /// crate.
+    pub serde: bool,
+
+    /// Where generated files are placed in the output directory.
+    pub output_layout: OutputLayout,
+
+    /// The module in which code is generated for schema files without a
+    /// `$Rust.parentModule` annotation. Empty for the crate root.
+    pub default_parent_module: Vec<String>,
+
+    /// If set, a file with this path in the output directory declares the modules of all
+    /// generated files, nested according to their parent modules, so that including it in
+    /// the module named by `default_parent_module` wires up the whole schema tree.
+    pub modules_file: Option<::std::path::PathBuf>,
+}
+
+impl GeneratorOptions {
+    pub fn new() -> GeneratorOptions {
+        GeneratorOptions {
+            edition: RustEdition::Rust2015,
+            crates_provide_map: collections::hash_map::HashMap::new(),
+            fill_random: false,
+            native_types: false,
+            debug_impls: false,
+            mock_servers: false,
+            serde: false,
+            output_layout: OutputLayout::Tree,
+            default_parent_module: Vec::new(),
+            modules_file: None,
         }
     }
 }
@@ -46,6 +185,19 @@ pub struct GeneratorContext<'a> {
     pub request: schema_capnp::code_generator_request::Reader<'a>,
     pub node_map: collections::hash_map::HashMap<u64, schema_capnp::node::Reader<'a>>,
     pub scope_map: collections::hash_map::HashMap<u64, Vec<String>>,
+    pub source_info_map: collections::hash_map::HashMap<u64, schema_capnp::node::source_info::Reader<'a>>,
+    pub options: GeneratorOptions,
+
+    /// Names of the implicit parameters of the interface method whose types are being
+    /// generated.
+    implicit_method_params: RefCell<Vec<String>>,
+
+    /// The problems found so far.
+    pub diagnostics: RefCell<Vec<Diagnostic>>,
+
+    /// The ID of the node whose code is being generated, and the member of it, if any, that
+    /// is being generated.
+    location: RefCell<(u64, Option<String>)>,
 }
 
 impl <'a> GeneratorContext<'a> {
@@ -60,17 +212,36 @@ impl <'a> GeneratorContext<'a> {
         rust_edition: RustEdition)
         -> ::capnp::Result<GeneratorContext<'a>>
     {
+        let mut options = GeneratorOptions::new();
+        options.edition = rust_edition;
+        Self::new_with_options(message, options)
+    }
 
+    pub fn new_with_options(
+        message:&'a capnp::message::Reader<capnp::serialize::OwnedSegments>,
+        options: GeneratorOptions)
+        -> ::capnp::Result<GeneratorContext<'a>>
+    {
+        let rust_edition = options.edition;
         let mut gen = GeneratorContext {
             request : try!(message.get_root()),
             node_map: collections::hash_map::HashMap::<u64, schema_capnp::node::Reader<'a>>::new(),
             scope_map: collections::hash_map::HashMap::<u64, Vec<String>>::new(),
+            source_info_map: collections::hash_map::HashMap::new(),
+            options: options,
+            implicit_method_params: RefCell::new(Vec::new()),
+            diagnostics: RefCell::new(Vec::new()),
+            location: RefCell::new((0, None)),
         };
 
         for node in try!(gen.request.get_nodes()).iter() {
             gen.node_map.insert(node.get_id(), node);
         }
 
+        for source_info in gen.request.get_source_info()?.iter() {
+            gen.source_info_map.insert(source_info.get_id(), source_info);
+        }
+
         for requested_file in try!(gen.request.get_requested_files()).iter() {
              let id = requested_file.get_id();
 
@@ -80,23 +251,110 @@ impl <'a> GeneratorContext<'a> {
                 let root_name: String = format!(
                     "{}_capnp",
                     try!(path_to_stem_string(importpath)).replace("-", "_"));
+                let parent_module = get_parent_module(&gen.node_map, import.get_id(),
+                                                      &gen.options.default_parent_module)?;
+                let external_crate = gen.options.crates_provide_map.get(&import.get_id());
                 try!(populate_scope_map(&gen.node_map,
                                         &mut gen.scope_map,
-                                        root_scope(rust_edition, root_name),
+                                        root_scope(rust_edition, external_crate, parent_module, root_name),
                                         import.get_id()));
             }
 
             let root_name = try!(path_to_stem_string(try!(requested_file.get_filename())));
             let root_mod = format!("{}_capnp", root_name.replace("-", "_"));
+            let parent_module = get_parent_module(&gen.node_map, id, &gen.options.default_parent_module)?;
+            let external_crate = gen.options.crates_provide_map.get(&id);
             populate_scope_map(&gen.node_map,
                                &mut gen.scope_map,
-                               root_scope(rust_edition, root_mod),
+                               root_scope(rust_edition, external_crate, parent_module, root_mod),
                                id)?;
         }
+
+        // Files imported only by imported files are not listed in `requested_files`, but their
+        // types can still be used through a `using` of the file that imports them.
+        for node in gen.request.get_nodes()?.iter() {
+            let id = node.get_id();
+            if let schema_capnp::node::File(()) = node.which()? {
+                if gen.scope_map.contains_key(&id) {
+                    continue;
+                }
+                let root_name = path_to_stem_string(node.get_display_name()?)?;
+                let root_mod = format!("{}_capnp", root_name.replace("-", "_"));
+                let parent_module = get_parent_module(&gen.node_map, id, &gen.options.default_parent_module)?;
+                let external_crate = gen.options.crates_provide_map.get(&id);
+                populate_scope_map(&gen.node_map,
+                                   &mut gen.scope_map,
+                                   root_scope(rust_edition, external_crate, parent_module, root_mod),
+                                   id)?;
+            }
+        }
         Ok(gen)
     }
 
 
+    /// Returns the doc comment of the node with ID `id` as rustdoc lines.
+    pub fn node_doc_comment(&self, id: u64) -> ::capnp::Result<FormattedText> {
+        match self.source_info_map.get(&id) {
+            Some(source_info) => Ok(doc_comment_lines(source_info.get_doc_comment()?)),
+            None => Ok(Branch(Vec::new())),
+        }
+    }
+
+    /// Returns the doc comment of the `index`th member (field, enumerant or method) of the
+    /// node with ID `id` as rustdoc lines.
+    pub fn member_doc_comment(&self, id: u64, index: u32) -> ::capnp::Result<FormattedText> {
+        if let Some(source_info) = self.source_info_map.get(&id) {
+            let members = source_info.get_members()?;
+            if index < members.len() {
+                return Ok(doc_comment_lines(members.get(index).get_doc_comment()?));
+            }
+        }
+        Ok(Branch(Vec::new()))
+    }
+
+    /// Returns the Rust name of the implicit parameter with index `index` of the interface
+    /// method being generated, or `None` outside of a method.
+    pub fn implicit_method_param_name(&self, index: u16) -> Option<String> {
+        self.implicit_method_params.borrow().get(index as usize).cloned()
+    }
+
+    /// Sets the member of the node being generated that later diagnostics concern.
+    pub fn set_member(&self, member: Option<&str>) {
+        self.location.borrow_mut().1 = member.map(|m| m.to_string());
+    }
+
+    /// Records a diagnostic about the node being generated, and about the member set by
+    /// `set_member()`, if any.
+    pub fn report(&self, severity: Severity, description: String) {
+        let (node_id, ref member) = *self.location.borrow();
+        let node_name = match self.node_map.get(&node_id).map(|node| node.get_display_name()) {
+            Some(Ok(name)) => name.to_string(),
+            _ => format!("@0x{:x}", node_id),
+        };
+        self.diagnostics.borrow_mut().push(Diagnostic {
+            severity,
+            node_name,
+            member: member.clone(),
+            description,
+        });
+    }
+
+    /// Returns the node with ID `id`, or an error if the request does not contain it.
+    pub fn get_node(&self, id: u64) -> ::capnp::Result<schema_capnp::node::Reader<'a>> {
+        match self.node_map.get(&id) {
+            Some(&node) => Ok(node),
+            None => Err(Error::failed(format!("node not found: @0x{:x}", id))),
+        }
+    }
+
+    /// Returns the path of the Rust module, or of the enum, generated for the node with ID `id`.
+    pub fn get_scope(&self, id: u64) -> ::capnp::Result<&[String]> {
+        match self.scope_map.get(&id) {
+            Some(scope) => Ok(scope),
+            None => Err(Error::failed(format!("no scope for node @0x{:x}", id))),
+        }
+    }
+
     fn get_last_name<'b>(&'b self, id: u64) -> ::capnp::Result<&'b str> {
         match self.scope_map.get(&id) {
             None => Err(Error::failed(format!("node not found: {}", id))),
@@ -108,6 +366,16 @@ impl <'a> GeneratorContext<'a> {
     }
 }
 
+fn doc_comment_lines(doc_comment: &str) -> FormattedText {
+    Branch(doc_comment.lines().map(|line| {
+        if line.is_empty() {
+            Line("///".to_string())
+        } else {
+            Line(format!("/// {}", line))
+        }
+    }).collect())
+}
+
 fn path_to_stem_string<P: AsRef<::std::path::Path>>(path: P) -> ::capnp::Result<String> {
     match path.as_ref().file_stem() {
         None => Err(Error::failed(format!("file has no stem: {:?}", path.as_ref()))),
@@ -135,7 +403,7 @@ fn snake_to_upper_case(s: &str) -> String {
     result_chars.into_iter().collect()
 }
 
-fn camel_to_snake_case(s: &str) -> String {
+pub fn camel_to_snake_case(s: &str) -> String {
     let mut result_chars: Vec<char> = Vec::new();
     let mut first_char = true;
     for c in s.chars() {
@@ -151,7 +419,7 @@ fn camel_to_snake_case(s: &str) -> String {
     result_chars.into_iter().collect()
 }
 
-fn capitalize_first_letter(s: &str) -> String {
+pub fn capitalize_first_letter(s: &str) -> String {
     let mut result_chars: Vec<char> = Vec::new();
     for c in s.chars() { result_chars.push(c) }
     result_chars[0] = result_chars[0].to_ascii_uppercase();
@@ -242,7 +510,7 @@ const RUST_KEYWORDS : [&'static str; 53] =
      "typeof", "unsafe", "unsized", "use", "virtual",
      "where", "while", "yield"];
 
-fn module_name(camel_case : &str) -> String {
+pub fn module_name(camel_case : &str) -> String {
     let mut name = camel_to_snake_case(camel_case);
     if RUST_KEYWORDS.contains(&&*name) {
         name.push('_');
@@ -267,13 +535,17 @@ fn populate_scope_map(node_map: &collections::hash_map::HashMap<u64, schema_capn
         match node_map.get(&nested_node_id) {
             None => {}
             Some(node_reader) => {
+                let name = match get_name_annotation(node_reader.get_annotations()?)? {
+                    Some(name) => name,
+                    None => nested_node.get_name()?,
+                };
                 match node_reader.which() {
                     Ok(schema_capnp::node::Enum(_enum_reader)) => {
-                        scope_names.push(try!(nested_node.get_name()).to_string());
+                        scope_names.push(name.to_string());
                         try!(populate_scope_map(node_map, scope_map, scope_names, nested_node_id));
                     }
                     _ => {
-                        scope_names.push(module_name(try!(nested_node.get_name())));
+                        scope_names.push(module_name(name));
                         try!(populate_scope_map(node_map, scope_map, scope_names, nested_node_id));
 
                     }
@@ -288,7 +560,16 @@ fn populate_scope_map(node_map: &collections::hash_map::HashMap<u64, schema_capn
             for field in fields.iter() {
                 match field.which() {
                     Ok(schema_capnp::field::Group(group)) => {
-                        let name = module_name(try!(field.get_name()));
+                        // A `$Rust.name` on a group or named union may be attached either to
+                        // the field or to the group's node.
+                        let group_annotation = match node_map.get(&group.get_type_id()) {
+                            Some(node) => get_name_annotation(node.get_annotations()?)?,
+                            None => None,
+                        };
+                        let name = match get_name_annotation(field.get_annotations()?)? {
+                            Some(name) => module_name(name),
+                            None => module_name(group_annotation.unwrap_or(field.get_name()?)),
+                        };
                         let mut scope_names = scope_names.clone();
                         scope_names.push(name);
                         try!(populate_scope_map(node_map, scope_map, scope_names, group.get_type_id()));
@@ -342,7 +623,7 @@ pub fn getter_text(gen: &GeneratorContext,
 
     match try!(field.which()) {
         field::Group(group) => {
-            let the_mod = gen.scope_map[&group.get_type_id()].join("::");
+            let the_mod = gen.get_scope(group.get_type_id())?.join("::");
 
             let mut result_type = if is_reader {
                 format!("{}::Reader<'a>", the_mod)
@@ -442,17 +723,17 @@ pub fn getter_text(gen: &GeneratorContext,
 
                 (type_::Text(()), value::Text(t)) => {
                     if default_value.has_text() && try!(t).len() > 0 && is_reader {
-                        println!("cargo:warning=[UNSUPPORTED] Ignoring default value {:?} for text field {}. \
-                                  See https://github.com/dwrensha/capnpc-rust/issues/38",
-                                 try!(t), try!(field.get_name()));
+                        gen.report(Severity::Warning, format!(
+                            "[UNSUPPORTED] Ignoring default value {:?} for text field. \
+                             See https://github.com/dwrensha/capnpc-rust/issues/38", try!(t)));
                     }
                     Line(format!("self.{}.get_pointer_field({}).get_text(::std::ptr::null(), 0)", member, offset))
                 }
                 (type_::Data(()), value::Data(d)) => {
                     if default_value.has_data() && try!(d).len() > 0 && is_reader {
-                        println!("cargo:warning=[UNSUPPORTED] Ignoring default value {:?} for data field {}. \
-                                  See https://github.com/dwrensha/capnpc-rust/issues/38",
-                                 try!(d), try!(field.get_name()));
+                        gen.report(Severity::Warning, format!(
+                            "[UNSUPPORTED] Ignoring default value {:?} for data field. \
+                             See https://github.com/dwrensha/capnpc-rust/issues/38", try!(d)));
                     }
 
                     Line(format!("self.{}.get_pointer_field({}).get_data(::std::ptr::null(), 0)", member, offset))
@@ -460,9 +741,9 @@ pub fn getter_text(gen: &GeneratorContext,
                 (type_::List(_), value::List(_)) => {
                     if default_value.has_list() && is_reader {
                         // TODO: Don't emit warning if the list if of length zero.
-                        println!("cargo:warning=[UNSUPPORTED] Ignoring default for list field {}. \
-                                  See https://github.com/dwrensha/capnpc-rust/issues/38",
-                                 try!(field.get_name()));
+                        gen.report(Severity::Warning,
+                                   "[UNSUPPORTED] Ignoring default for list field. \
+                                    See https://github.com/dwrensha/capnpc-rust/issues/38".to_string());
                     }
 
                     if is_reader {
@@ -478,9 +759,9 @@ pub fn getter_text(gen: &GeneratorContext,
                 (type_::Struct(_), value::Struct(_)) => {
                     if default_value.has_struct() && is_reader {
                         // TODO: Don't emit warning if the struct is zero-sized.
-                        println!("cargo:warning=[UNSUPPORTED] Ignoring default for struct field {}. \
-                                  See https://github.com/dwrensha/capnpc-rust/issues/38",
-                                 try!(field.get_name()));
+                        gen.report(Severity::Warning,
+                                   "[UNSUPPORTED] Ignoring default for struct field. \
+                                    See https://github.com/dwrensha/capnpc-rust/issues/38".to_string());
                     }
 
                     if is_reader {
@@ -506,7 +787,7 @@ pub fn getter_text(gen: &GeneratorContext,
                         }
                     }
                 }
-                _ => return Err(Error::failed(format!("default value was of wrong type"))),
+                _ => return Err(Error::failed(diagnostics::mismatch("the default value", raw_type, default_value)?)),
             };
             Ok((result_type, getter_code))
         }
@@ -515,7 +796,7 @@ pub fn getter_text(gen: &GeneratorContext,
 
 fn zero_fields_of_group(gen: &GeneratorContext, node_id: u64) -> ::capnp::Result<FormattedText> {
     use schema_capnp::{node, field, type_};
-    match try!(gen.node_map[&node_id].which()) {
+    match gen.get_node(node_id)?.which()? {
         node::Struct(st) => {
             let mut result = Vec::new();
             if st.get_discriminant_count() != 0 {
@@ -575,9 +856,10 @@ fn zero_fields_of_group(gen: &GeneratorContext, node_id: u64) -> ::capnp::Result
     }
 }
 
-fn generate_setter(gen: &GeneratorContext, discriminant_offset: u32,
+fn generate_setter(gen: &GeneratorContext,
                    styled_name: &str,
-                   field: &schema_capnp::field::Reader) -> ::capnp::Result<FormattedText> {
+                   field: &schema_capnp::field::Reader,
+                   doc_comment: &FormattedText) -> ::capnp::Result<FormattedText> {
 
     use schema_capnp::*;
 
@@ -589,15 +871,10 @@ fn generate_setter(gen: &GeneratorContext, discriminant_offset: u32,
 
     let discriminant_value = field.get_discriminant_value();
     if discriminant_value != field::NO_DISCRIMINANT {
-        setter_interior.push(
-            Line(format!("self.builder.set_data_field::<u16>({}, {});",
-                         discriminant_offset as usize,
-                         discriminant_value as usize)));
-        let init_discrim = Line(format!("self.builder.set_data_field::<u16>({}, {});",
-                                        discriminant_offset as usize,
-                                        discriminant_value as usize));
-        initter_interior.push(init_discrim.clone());
-        initn_interior.push(init_discrim);
+        let set_discrim = Line(format!("self.switch_which({});", discriminant_value as usize));
+        setter_interior.push(set_discrim.clone());
+        initter_interior.push(set_discrim.clone());
+        initn_interior.push(set_discrim);
     }
 
     let mut setter_generic_param = String::new();
@@ -606,7 +883,7 @@ fn generate_setter(gen: &GeneratorContext, discriminant_offset: u32,
 
     let (maybe_reader_type, maybe_builder_type) : (Option<String>, Option<String>) = match try!(field.which()) {
         field::Group(group) => {
-            let scope = &gen.scope_map[&group.get_type_id()];
+            let scope = gen.get_scope(group.get_type_id())?;
             let the_mod = scope.join("::");
 
             initter_interior.push(try!(zero_fields_of_group(gen, group.get_type_id())));
@@ -618,7 +895,7 @@ fn generate_setter(gen: &GeneratorContext, discriminant_offset: u32,
         field::Slot(reg_field) => {
             let offset = reg_field.get_offset() as usize;
             let typ = try!(reg_field.get_type());
-            match typ.which().ok().expect("unrecognized type") {
+            match try!(typ.which()) {
                 type_::Void(()) => {
                     setter_param = "_value".to_string();
                     (Some("()".to_string()), None)
@@ -668,6 +945,7 @@ fn generate_setter(gen: &GeneratorContext, discriminant_offset: u32,
                 }
                 type_::List(ot1) => {
                     return_result = true;
+                    let mut from_interior = setter_interior.clone();
                     setter_interior.push(
                         Line(format!("::capnp::traits::SetPointerBuilder::set_pointer_builder(self.builder.get_pointer_field({}), value, false)",
                                      offset)));
@@ -676,20 +954,44 @@ fn generate_setter(gen: &GeneratorContext, discriminant_offset: u32,
                     initter_interior.push(
                         Line(format!("::capnp::traits::FromPointerBuilder::init_pointer(self.builder.get_pointer_field({}), size)", offset)));
 
+                    let builder_type = try!(try!(reg_field.get_type()).type_string(gen, Leaf::Builder("'a")));
+                    let (settable_from_slice, settable_from_iter) = match try!(try!(ot1.get_element_type()).which()) {
+                        type_::List(_) | type_::Interface(_) | type_::AnyPointer(_) => (false, false),
+                        type_::Struct(_) => (false, true),
+                        _ => (true, true),
+                    };
+                    if settable_from_iter {
+                        from_interior.push(Line(format!(
+                            "<::capnp::traits::FromIter<I> as ::capnp::traits::SetPointerBuilder<{}>>::set_pointer_builder(self.builder.get_pointer_field({}), ::capnp::traits::FromIter(values), false)",
+                            builder_type, offset)));
+                        result.push(doc_comment.clone());
+                        result.push(Line("#[inline]".to_string()));
+                        result.push(Line(format!(
+                            "pub fn set_{}_from<I>(&mut self, values: I) -> ::capnp::Result<()> where I: IntoIterator, ::capnp::traits::FromIter<I>: ::capnp::traits::SetPointerBuilder<{}> {{",
+                            styled_name, builder_type)));
+                        result.push(Indent(Box::new(Branch(from_interior))));
+                        result.push(Line("}".to_string()));
+                    }
+
                     match try!(try!(ot1.get_element_type()).which()) {
                         type_::List(_) => {
                             setter_generic_param = "<'b>".to_string();
                             (Some(try!(try!(reg_field.get_type()).type_string(gen, Leaf::Reader("'b")))),
-                             Some(try!(try!(reg_field.get_type()).type_string(gen, Leaf::Builder("'a")))))
+                             Some(builder_type))
+                        }
+                        _ if settable_from_slice => {
+                            // Besides a reader, the setter accepts a slice of the elements.
+                            setter_generic_param = format!("<SPB: ::capnp::traits::SetPointerBuilder<{}>>", builder_type);
+                            (Some("SPB".to_string()), Some(builder_type))
                         }
                         _ =>
                             (Some(try!(try!(reg_field.get_type()).type_string(gen, Leaf::Reader("'a")))),
-                             Some(try!(try!(reg_field.get_type()).type_string(gen, Leaf::Builder("'a")))))
+                             Some(builder_type))
                     }
                 }
                 type_::Enum(e) => {
                     let id = e.get_type_id();
-                    let the_mod = gen.scope_map[&id].join("::");
+                    let the_mod = gen.get_scope(id)?.join("::");
                     setter_interior.push(
                         Line(format!("self.builder.set_data_field::<u16>({}, value as u16)",
                                      offset)));
@@ -734,6 +1036,7 @@ fn generate_setter(gen: &GeneratorContext, discriminant_offset: u32,
 
                         let builder_type = try!(typ.type_string(gen, Leaf::Builder("'a")));
 
+                        result.push(doc_comment.clone());
                         result.push(Line("#[inline]".to_string()));
                         result.push(Line(format!("pub fn initn_{}(self, length: u32) -> {} {{",
                                                  styled_name, builder_type)));
@@ -752,7 +1055,7 @@ fn generate_setter(gen: &GeneratorContext, discriminant_offset: u32,
                         (None, Some("::capnp::any_pointer::Builder<'a>".to_string()))
                     }
                 }
-                _ => return Err(Error::failed(format!("unrecognized type"))),
+                _ => return Err(Error::failed(format!("unsupported field type {}", diagnostics::type_kind(typ)?))),
             }
         }
     };
@@ -760,6 +1063,7 @@ fn generate_setter(gen: &GeneratorContext, discriminant_offset: u32,
     match maybe_reader_type {
         Some(ref reader_type) => {
             let return_type = if return_result { "-> ::capnp::Result<()>" } else { "" };
+            result.push(doc_comment.clone());
             result.push(Line("#[inline]".to_string()));
             result.push(Line(format!("pub fn set_{}{}(&mut self, {}: {}) {} {{",
                                      styled_name, setter_generic_param, setter_param,
@@ -771,6 +1075,7 @@ fn generate_setter(gen: &GeneratorContext, discriminant_offset: u32,
     }
     match maybe_builder_type {
         Some(builder_type) => {
+            result.push(doc_comment.clone());
             result.push(Line("#[inline]".to_string()));
             let args = initter_params.join(", ");
             result.push(Line(format!("pub fn init_{}(self, {}) -> {} {{",
@@ -784,6 +1089,35 @@ fn generate_setter(gen: &GeneratorContext, discriminant_offset: u32,
 }
 
 
+/// Appends the offsets of the pointer fields that `field` occupies, including those of
+/// all members of a group, to `offsets`.
+fn pointer_offsets(gen: &GeneratorContext,
+                   field: &schema_capnp::field::Reader,
+                   offsets: &mut Vec<u32>) -> ::capnp::Result<()> {
+    use schema_capnp::{node, field, type_};
+    match field.which()? {
+        field::Group(group) => {
+            if let node::Struct(st) = gen.get_node(group.get_type_id())?.which()? {
+                for member in st.get_fields()?.iter() {
+                    pointer_offsets(gen, &member, offsets)?;
+                }
+            }
+        }
+        field::Slot(slot) => {
+            match slot.get_type()?.which()? {
+                type_::Text(()) | type_::Data(()) | type_::List(_) | type_::Struct(_) |
+                type_::Interface(_) | type_::AnyPointer(_) => {
+                    if !offsets.contains(&slot.get_offset()) {
+                        offsets.push(slot.get_offset());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
 // return (the 'Which' enum, the 'which()' accessor, typedef)
 fn generate_union(gen: &GeneratorContext,
                   discriminant_offset: u32,
@@ -813,7 +1147,7 @@ fn generate_union(gen: &GeneratorContext,
 
         let dvalue = field.get_discriminant_value() as usize;
 
-        let field_name = try!(field.get_name());
+        let field_name = get_field_name(*field)?;
         let enumerant_name = capitalize_first_letter(field_name);
 
         let (ty, get) = try!(getter_text(gen, field, is_reader, false));
@@ -888,7 +1222,44 @@ fn generate_union(gen: &GeneratorContext,
                         Line("}".to_string()))))),
                     Line("}".to_string())));
 
-    // TODO set_which() for builders?
+    let getter_result = if is_reader {
+        getter_result
+    } else {
+        // Switching to another member of the union clears the pointers of the old one, so
+        // that they do not linger in the message.
+        let mut clear_arms = Vec::new();
+        for field in fields.iter() {
+            let mut offsets = Vec::new();
+            pointer_offsets(gen, field, &mut offsets)?;
+            if !offsets.is_empty() {
+                clear_arms.push(Line(format!("{} => {{", field.get_discriminant_value())));
+                for offset in offsets {
+                    clear_arms.push(Indent(Box::new(
+                        Line(format!("self.builder.get_pointer_field({}).clear();", offset)))));
+                }
+                clear_arms.push(Line("}".to_string()));
+            }
+        }
+        let mut switch_interior = Vec::new();
+        if !clear_arms.is_empty() {
+            clear_arms.push(Line("_ => {}".to_string()));
+            switch_interior.push(Branch(vec![
+                Line(format!("let old = self.builder.get_data_field::<u16>({});", doffset)),
+                Line("if old != discriminant {".to_string()),
+                Indent(Box::new(Branch(vec![
+                    Line("match old {".to_string()),
+                    Indent(Box::new(Branch(clear_arms))),
+                    Line("}".to_string())]))),
+                Line("}".to_string())]));
+        }
+        switch_interior.push(Line(format!("self.builder.set_data_field::<u16>({}, discriminant);", doffset)));
+        Branch(vec![
+            getter_result,
+            Line("#[inline]".to_string()),
+            Line("fn switch_which(&self, discriminant: u16) {".to_string()),
+            Indent(Box::new(Branch(switch_interior))),
+            Line("}".to_string())])
+    };
 
     Ok((result, getter_result, typedef))
 }
@@ -939,11 +1310,11 @@ fn generate_pipeline_getter(gen: &GeneratorContext,
                             field: schema_capnp::field::Reader) -> ::capnp::Result<FormattedText> {
     use schema_capnp::{field, type_};
 
-    let name = try!(field.get_name());
+    let name = get_field_name(field)?;
 
     match try!(field.which()) {
         field::Group(group) => {
-            let the_mod = gen.scope_map[&group.get_type_id()].join("::");
+            let the_mod = gen.get_scope(group.get_type_id())?.join("::");
             Ok(Branch(vec!(
                 Line(format!("pub fn get_{}(&self) -> {}::Pipeline {{",
                              camel_to_snake_case(name),
@@ -983,16 +1354,35 @@ fn generate_pipeline_getter(gen: &GeneratorContext,
     }
 }
 
+/// Appends `extra` to the comma-separated type parameters `ty_params`.
+fn join_ty_params(ty_params: &str, extra: &[String]) -> String {
+    if extra.is_empty() {
+        ty_params.to_string()
+    } else if ty_params.is_empty() {
+        extra.join(",")
+    } else {
+        format!("{},{}", ty_params, extra.join(","))
+    }
+}
+
 // We need this to work around the fact that Rust does not allow typedefs
 // with unused type parameters.
 fn get_ty_params_of_brand(gen: &GeneratorContext,
                           brand: ::schema_capnp::brand::Reader<>) -> ::capnp::Result<String>
 {
-    let mut acc = HashSet::new();
+    let mut acc = BTreeSet::new();
     try!(get_ty_params_of_brand_helper(gen, &mut acc, brand));
     let mut result = String::new();
     for (scope_id, parameter_index) in acc.into_iter() {
-        let node = gen.node_map[&scope_id];
+        if scope_id == 0 {
+            // An implicit parameter of the method being generated.
+            if let Some(name) = gen.implicit_method_param_name(parameter_index) {
+                result.push_str(&name);
+                result.push_str(",");
+            }
+            continue;
+        }
+        let node = gen.get_node(scope_id)?;
         let p = try!(node.get_parameters()).get(parameter_index as u32);
         result.push_str(try!(p.get_name()));
         result.push_str(",");
@@ -1002,7 +1392,7 @@ fn get_ty_params_of_brand(gen: &GeneratorContext,
 }
 
 fn get_ty_params_of_type_helper(gen: &GeneratorContext,
-                                accumulator: &mut HashSet<(u64, u16)>,
+                                accumulator: &mut BTreeSet<(u64, u16)>,
                                 typ: ::schema_capnp::type_::Reader<>)
     -> ::capnp::Result<()>
 {
@@ -1021,8 +1411,8 @@ fn get_ty_params_of_type_helper(gen: &GeneratorContext,
                 type_::any_pointer::Parameter(p) => {
                     accumulator.insert((p.get_scope_id(), p.get_parameter_index()));
                 }
-                type_::any_pointer::ImplicitMethodParameter(_) => {
-                    // XXX
+                type_::any_pointer::ImplicitMethodParameter(p) => {
+                    accumulator.insert((0, p.get_parameter_index()));
                 }
             }
         }
@@ -1048,7 +1438,7 @@ fn get_ty_params_of_type_helper(gen: &GeneratorContext,
 }
 
 fn get_ty_params_of_brand_helper(gen: &GeneratorContext,
-                         accumulator: &mut HashSet<(u64, u16)>,
+                         accumulator: &mut BTreeSet<(u64, u16)>,
                          brand: ::schema_capnp::brand::Reader<>)
                          -> ::capnp::Result<()>
 {
@@ -1066,7 +1456,7 @@ fn get_ty_params_of_brand_helper(gen: &GeneratorContext,
                 }
             }
             ::schema_capnp::brand::scope::Inherit(()) => {
-                let parameters = try!(gen.node_map[&scope_id].get_parameters());;
+                let parameters = gen.get_node(scope_id)?.get_parameters()?;
                 for idx in 0..parameters.len() {
                     accumulator.insert((scope_id, idx as u16));
                 }
@@ -1076,34 +1466,103 @@ fn get_ty_params_of_brand_helper(gen: &GeneratorContext,
     Ok(())
 }
 
+/// Appends the `Server` traits of the superclasses of the interface with ID `node_id`,
+/// including indirect ones, to `servers`. A server for the interface must implement them all.
+fn collect_superclass_servers(gen: &GeneratorContext,
+                              node_id: u64,
+                              servers: &mut Vec<String>) -> ::capnp::Result<()> {
+    if let schema_capnp::node::Interface(interface) = gen.get_node(node_id)?.which()? {
+        for superclass in interface.get_superclasses()?.iter() {
+            let type_id = superclass.get_id();
+            let the_mod = gen.get_scope(type_id)?.join("::");
+            let server = do_branding(gen, type_id, superclass.get_brand()?, Leaf::Server, the_mod, None)?;
+            if !servers.contains(&server) {
+                servers.push(server);
+                collect_superclass_servers(gen, type_id, servers)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Generates the code for the node with ID `node_id` and its nested nodes. If that fails, the
+/// error is recorded as a diagnostic and the node is left out, so that generation can go on
+/// to find the errors in other nodes.
 fn generate_node(gen: &GeneratorContext,
                  node_id: u64,
                  node_name: &str,
-                 // Ugh. We need this to deal with the anonymous Params and Results
-                 // structs that go with RPC methods.
-                 parent_node_id: Option<u64>,
-                 ) -> ::capnp::Result<FormattedText> {
+                 parent_node_id: Option<u64>) -> FormattedText {
+    let outer = gen.location.replace((node_id, None));
+    let text = match generate_node_contents(gen, node_id, node_name, parent_node_id) {
+        Ok(text) => text,
+        Err(e) => {
+            gen.report(Severity::Error, e.description);
+            Branch(Vec::new())
+        }
+    };
+    gen.location.replace(outer);
+    text
+}
+
+fn generate_node_contents(gen: &GeneratorContext,
+                          node_id: u64,
+                          node_name: &str,
+                          // Ugh. We need this to deal with the anonymous Params and Results
+                          // structs that go with RPC methods.
+                          parent_node_id: Option<u64>,
+                          ) -> ::capnp::Result<FormattedText> {
     use schema_capnp::*;
 
     let mut output: Vec<FormattedText> = Vec::new();
     let mut nested_output: Vec<FormattedText> = Vec::new();
 
-    let node_reader = &gen.node_map[&node_id];
+    let node_reader = gen.get_node(node_id)?;
     let nested_nodes = try!(node_reader.get_nested_nodes());
     for nested_node in nested_nodes.iter() {
         let id = nested_node.get_id();
-        nested_output.push(try!(generate_node(gen, id, try!(gen.get_last_name(id)), None)));
+        nested_output.push(generate_node(gen, id, try!(gen.get_last_name(id)), None));
     }
 
     match try!(node_reader.which()) {
 
         node::File(()) => {
             output.push(Branch(nested_output));
+
+            // Every type declared in the file, including groups and the implicit parameter and
+            // result structs of methods, has a display name that starts with the file's.
+            let prefix = format!("{}:", node_reader.get_display_name()?);
+            let mut types = Vec::new();
+            for (&id, node) in gen.node_map.iter() {
+                match node.which()? {
+                    node::Struct(_) | node::Enum(_) | node::Interface(_) => {
+                        let display_name = node.get_display_name()?;
+                        if display_name.starts_with(&prefix) {
+                            types.push((id, display_name));
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            types.sort();
+            let arms = types.iter().map(|&(id, display_name)| {
+                Line(format!("{} => Some({:?}),", format_u64(id), display_name))
+            }).collect();
+            output.push(BlankLine);
+            output.push(Line("/// Returns the display name of the struct, enum or interface with type ID `id`, if it".to_string()));
+            output.push(Line("/// is declared in this file.".to_string()));
+            output.push(Line("pub fn type_name(id: u64) -> Option<&'static str> {".to_string()));
+            output.push(Indent(Box::new(Branch(vec![
+                Line("match id {".to_string()),
+                Indent(Box::new(Branch(arms))),
+                Indent(Box::new(Line("_ => None,".to_string()))),
+                Line("}".to_string())]))));
+            output.push(Line("}".to_string()));
         }
         node::Struct(struct_reader) => {
-            let params = node_reader.parameters_texts(gen, parent_node_id);
+            let params = node_reader.parameters_texts(gen, parent_node_id)?;
             output.push(BlankLine);
 
+            output.push(try!(gen.node_doc_comment(node_id)));
             let is_generic = node_reader.get_is_generic();
             if is_generic {
                 output.push(Line(format!("pub mod {} {{ /* {} */", node_name, params.expanded_list.join(","))));
@@ -1126,9 +1585,11 @@ fn generate_node(gen: &GeneratorContext,
             let discriminant_offset = struct_reader.get_discriminant_offset();
 
             let fields = try!(struct_reader.get_fields());
-            for field in fields.iter() {
-                let name = try!(field.get_name());
+            for (index, field) in fields.iter().enumerate() {
+                gen.set_member(Some(field.get_name()?));
+                let name = get_field_name(field)?;
                 let styled_name = camel_to_snake_case(name);
+                let doc_comment = try!(gen.member_doc_comment(node_id, index as u32));
 
                 let discriminant_value = field.get_discriminant_value();
                 let is_union_field = discriminant_value != field::NO_DISCRIMINANT;
@@ -1138,6 +1599,7 @@ fn generate_node(gen: &GeneratorContext,
                     let (ty, get) = try!(getter_text(gen, &field, true, true));
                     reader_members.push(
                         Branch(vec!(
+                            doc_comment.clone(),
                             Line("#[inline]".to_string()),
                             Line(format!("pub fn get_{}(self) {} {{", styled_name, ty)),
                             Indent(Box::new(get)),
@@ -1146,6 +1608,7 @@ fn generate_node(gen: &GeneratorContext,
                     let (ty_b, get_b) = try!(getter_text(gen, &field, false, true));
                     builder_members.push(
                         Branch(vec!(
+                            doc_comment.clone(),
                             Line("#[inline]".to_string()),
                             Line(format!("pub fn get_{}(self) {} {{", styled_name, ty_b)),
                             Indent(Box::new(get_b)),
@@ -1155,8 +1618,7 @@ fn generate_node(gen: &GeneratorContext,
                     union_fields.push(field);
                 }
 
-                builder_members.push(try!(generate_setter(gen, discriminant_offset,
-                                                          &styled_name, &field)));
+                builder_members.push(try!(generate_setter(gen, &styled_name, &field, &doc_comment)));
 
                 reader_members.push(try!(generate_haser(discriminant_offset, &styled_name, &field, true)));
                 builder_members.push(try!(generate_haser(discriminant_offset, &styled_name, &field, false)));
@@ -1164,14 +1626,14 @@ fn generate_node(gen: &GeneratorContext,
                 match field.which() {
                     Ok(field::Group(group)) => {
                         let id = group.get_type_id();
-                        let text = try!(generate_node(gen, id,
-                                                      try!(gen.get_last_name(id)), None));
+                        let text = generate_node(gen, id, try!(gen.get_last_name(id)), None);
                         nested_output.push(text);
                     }
                     _ => { }
                 }
 
             }
+            gen.set_member(None);
 
             if discriminant_count > 0 {
                 let (which_enums1, union_getter, typedef) =
@@ -1189,7 +1651,7 @@ fn generate_node(gen: &GeneratorContext,
                 reexports.push_str("pub use self::Which::{");
                 let mut whichs = Vec::new();
                 for f in union_fields.iter(){
-                    whichs.push(capitalize_first_letter(try!(f.get_name())));
+                    whichs.push(capitalize_first_letter(get_field_name(*f)?));
                 }
                 reexports.push_str(&whichs.join(","));
                 reexports.push_str("};");
@@ -1234,6 +1696,14 @@ fn generate_node(gen: &GeneratorContext,
                     Line("}".to_string()),
                     BlankLine]);
 
+            let mut field_names = Vec::new();
+            for field in fields.iter() {
+                field_names.push(format!("{:?}", field.get_name()?));
+            }
+            preamble.push(Line("/// The names of the fields as the schema spells them, indexed like the fields of the".to_string()));
+            preamble.push(Line("/// struct's schema node.".to_string()));
+            preamble.push(Line(format!("pub const FIELD_NAMES: &'static [&'static str] = &[{}];", field_names.join(", "))));
+
             let accessors = vec![
                 Branch(preamble),
                 (if !is_generic {
@@ -1429,8 +1899,36 @@ fn generate_node(gen: &GeneratorContext,
                 Line("}".to_string()),
             ];
 
+            let fill_random = if gen.options.fill_random {
+                try!(generate_fill_random(gen, node_id, &params))
+            } else {
+                Branch(Vec::new())
+            };
+
+            let native_type = if gen.options.native_types && try!(has_native_type(gen, node_id)) {
+                try!(generate_native_type(gen, node_id))
+            } else {
+                Branch(Vec::new())
+            };
+
+            let debug_impls = if gen.options.debug_impls {
+                generate_debug_impls(gen, node_id, &params)?
+            } else {
+                Branch(Vec::new())
+            };
+
+            let serde_impls = if gen.options.serde {
+                generate_serde_impls(gen, node_id, &params)?
+            } else {
+                Branch(Vec::new())
+            };
+
             output.push(Indent(Box::new(Branch(vec!(Branch(accessors),
+                                                    debug_impls,
+                                                    serde_impls,
                                                     Branch(which_enums),
+                                                    fill_random,
+                                                    native_type,
                                                     Branch(nested_output))))));
             output.push(Line("}".to_string()));
         }
@@ -1441,18 +1939,31 @@ fn generate_node(gen: &GeneratorContext,
 
             let mut members = Vec::new();
             let mut match_branches = Vec::new();
+            let mut variants = Vec::new();
+            let mut name_branches = Vec::new();
+            let mut from_str_branches = Vec::new();
             let enumerants = try!(enum_reader.get_enumerants());
             for ii in 0..enumerants.len() {
-                let enumerant = capitalize_first_letter(try!(enumerants.get(ii).get_name()));
+                let enumerant = capitalize_first_letter(get_enumerant_name(enumerants.get(ii))?);
+                let schema_name = enumerants.get(ii).get_name()?;
+                members.push(try!(gen.member_doc_comment(node_id, ii)));
                 members.push(Line(format!("{} = {},", enumerant, ii)));
                 match_branches.push(
                     Line(format!("{} => ::std::result::Result::Ok({}::{}),", ii, last_name, enumerant)));
+                variants.push(Line(format!("{}::{},", last_name, enumerant)));
+                name_branches.push(Line(format!("{}::{} => \"{}\",", last_name, enumerant, schema_name)));
+                from_str_branches.push(
+                    Line(format!("\"{}\" => ::std::result::Result::Ok({}::{}),", schema_name, last_name, enumerant)));
             }
             match_branches.push(Line("n => ::std::result::Result::Err(::capnp::NotInSchema(n)),".to_string()));
+            from_str_branches.push(Line(format!(
+                "_ => ::std::result::Result::Err(::capnp::Error::failed(format!(\"{} has no enumerant named {{:?}}\", s))),",
+                last_name)));
 
             output.push(Branch(vec!(
+                try!(gen.node_doc_comment(node_id)),
                 Line("#[repr(u16)]".to_string()),
-                Line("#[derive(Clone, Copy, PartialEq)]".to_string()),
+                Line("#[derive(Clone, Copy, Debug, PartialEq)]".to_string()),
                 Line(format!("pub enum {} {{", last_name)),
                 Indent(Box::new(Branch(members))),
                 Line("}".to_string()))));
@@ -1487,15 +1998,53 @@ fn generate_node(gen: &GeneratorContext,
                     Indent(
                         Box::new(Line(format!("fn type_id() -> u64 {{ {}u64 }}", format_u64(node_id)).to_string()))),
                     Line("}".to_string()))));
+
+            output.push(
+                Branch(vec!(
+                    Line(format!("impl {} {{", last_name)),
+                    Indent(Box::new(Branch(vec![
+                        Line("/// All enumerants, in the order of their numbers.".to_string()),
+                        Line(format!("pub const ALL_VARIANTS: &'static [{}] = &[", last_name)),
+                        Indent(Box::new(Branch(variants))),
+                        Line("];".to_string()),
+                        BlankLine,
+                        Line("/// Returns the name of the enumerant in the schema.".to_string()),
+                        Line("pub fn name(self) -> &'static str {".to_string()),
+                        Indent(Box::new(Branch(vec![
+                            Line("match self {".to_string()),
+                            Indent(Box::new(Branch(name_branches))),
+                            Line("}".to_string())]))),
+                        Line("}".to_string())]))),
+                    Line("}".to_string()),
+                    Line(format!("impl ::std::fmt::Display for {} {{", last_name)),
+                    Indent(Box::new(Branch(vec![
+                        Line("fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {".to_string()),
+                        Indent(Box::new(Line("f.write_str(self.name())".to_string()))),
+                        Line("}".to_string())]))),
+                    Line("}".to_string()),
+                    Line(format!("impl ::std::str::FromStr for {} {{", last_name)),
+                    Indent(Box::new(Branch(vec![
+                        Line("type Err = ::capnp::Error;".to_string()),
+                        Line(format!("fn from_str(s: &str) -> ::std::result::Result<{}, ::capnp::Error> {{", last_name)),
+                        Indent(Box::new(Branch(vec![
+                            Line("match s {".to_string()),
+                            Indent(Box::new(Branch(from_str_branches))),
+                            Line("}".to_string())]))),
+                        Line("}".to_string())]))),
+                    Line("}".to_string()))));
+
+            if gen.options.serde {
+                output.push(generate_enum_serde_impls(&last_name));
+            }
         }
 
         node::Interface(interface) => {
-            let params = node_reader.parameters_texts(gen, parent_node_id);
+            let params = node_reader.parameters_texts(gen, parent_node_id)?;
             output.push(BlankLine);
 
             let is_generic = node_reader.get_is_generic();
 
-            let names = &gen.scope_map[&node_id];
+            let names = gen.get_scope(node_id)?;
             let mut client_impl_interior = Vec::new();
             let mut server_interior = Vec::new();
             let mut mod_interior = Vec::new();
@@ -1508,22 +2057,53 @@ fn generate_node(gen: &GeneratorContext,
 
             mod_interior.push(Line ("#![allow(unused_variables)]".to_string()));
 
+            let display_name = node_reader.get_display_name()?;
+            let interface_name = &display_name[node_reader.get_display_name_prefix_length() as usize..];
+            let mut mock_fields = Vec::new();
+            let mut mock_inits = Vec::new();
+            let mut mock_clones = Vec::new();
+            let mut mock_methods = Vec::new();
+            let mut method_names = Vec::new();
+
             let methods = try!(interface.get_methods());
             for ordinal in 0..methods.len() {
                 let method = methods.get(ordinal);
                 let name = try!(method.get_name());
+                gen.set_member(Some(name));
+                method_names.push(format!("{:?}", name));
 
                 method.get_code_order();
+
+                // Implicit parameters become type parameters of the generated methods. They
+                // are renamed if they would shadow a parameter of the interface.
+                let mut implicit_params = Vec::new();
+                for param in method.get_implicit_parameters()?.iter() {
+                    let mut param_name = param.get_name()?.to_string();
+                    while params.expanded_list.contains(&param_name) {
+                        param_name.push('_');
+                    }
+                    implicit_params.push(param_name);
+                }
+                *gen.implicit_method_params.borrow_mut() = implicit_params.clone();
+                let (method_ty_params, method_where_clause) = if implicit_params.is_empty() {
+                    (String::new(), String::new())
+                } else {
+                    let bounds: Vec<String> = implicit_params.iter().map(|param| {
+                        format!("{}: for<'c> ::capnp::traits::Owned<'c>", param)
+                    }).collect();
+                    (format!("<{}>", implicit_params.join(",")), format!(" where {}", bounds.join(", ")))
+                };
+
                 let param_id = method.get_param_struct_type();
-                let param_node = &gen.node_map[&param_id];
+                let param_node = gen.get_node(param_id)?;
                 let (param_scopes, params_ty_params) = if param_node.get_scope_id() == 0 {
-                    let mut names = names.clone();
+                    let mut names = names.to_vec();
                     let local_name = module_name(&format!("{}Params", name));
-                    nested_output.push(try!(generate_node(gen, param_id, &*local_name, Some(node_id))));
+                    nested_output.push(generate_node(gen, param_id, &*local_name, Some(node_id)));
                     names.push(local_name);
-                    (names, params.params.clone())
+                    (names, join_ty_params(&params.params, &implicit_params))
                 } else {
-                    (gen.scope_map[&param_node.get_id()].clone(),
+                    (gen.get_scope(param_node.get_id())?.to_vec(),
                      try!(get_ty_params_of_brand(gen, try!(method.get_param_brand()))))
                 };
                 let param_type = try!(do_branding(&gen, param_id, try!(method.get_param_brand()),
@@ -1531,24 +2111,31 @@ fn generate_node(gen: &GeneratorContext,
 
 
                 let result_id = method.get_result_struct_type();
-                let result_node = &gen.node_map[&result_id];
+                let result_node = gen.get_node(result_id)?;
                 let (result_scopes, results_ty_params) = if result_node.get_scope_id() == 0 {
-                    let mut names = names.clone();
+                    let mut names = names.to_vec();
                     let local_name = module_name(&format!("{}Results", name));
-                    nested_output.push(try!(generate_node(gen, result_id, &*local_name, Some(node_id))));
+                    nested_output.push(generate_node(gen, result_id, &*local_name, Some(node_id)));
                     names.push(local_name);
-                    (names, params.params.clone())
+                    (names, join_ty_params(&params.params, &implicit_params))
                 } else {
-                    (gen.scope_map[&result_node.get_id()].clone(),
+                    (gen.get_scope(result_node.get_id())?.to_vec(),
                      try!(get_ty_params_of_brand(gen, try!(method.get_result_brand()))))
                 };
                 let result_type = try!(do_branding(&gen, result_id, try!(method.get_result_brand()),
                                                    Leaf::Owned, result_scopes.join("::"), Some(node_id)));
 
+                // A server sees the values of implicit parameters as AnyPointers.
+                let dispatch_ty_params = if implicit_params.is_empty() {
+                    String::new()
+                } else {
+                    let any_pointers: Vec<&str> = implicit_params.iter().map(|_| "::capnp::any_pointer::Owned").collect();
+                    format!("::<{}>", any_pointers.join(","))
+                };
                 dispatch_arms.push(
                     Line(format!(
-                        "{} => server.{}(::capnp::private::capability::internal_get_typed_params(params), ::capnp::private::capability::internal_get_typed_results(results)),",
-                        ordinal, module_name(name))));
+                        "{} => server.{}{}(::capnp::private::capability::internal_get_typed_params(params), ::capnp::private::capability::internal_get_typed_results(results)),",
+                        ordinal, module_name(name), dispatch_ty_params)));
                 mod_interior.push(
                     Line(format!(
                         "pub type {}Params<{}> = ::capnp::capability::Params<{}>;",
@@ -1557,17 +2144,38 @@ fn generate_node(gen: &GeneratorContext,
                     Line(format!(
                         "pub type {}Results<{}> = ::capnp::capability::Results<{}>;",
                         capitalize_first_letter(name), results_ty_params, result_type)));
+                let doc_comment = try!(gen.member_doc_comment(node_id, ordinal));
+                server_interior.push(doc_comment.clone());
                 server_interior.push(
                     Line(format!(
-                        "fn {}(&mut self, _: {}Params<{}>, _: {}Results<{}>) -> ::capnp::capability::Promise<(), ::capnp::Error> {{ ::capnp::capability::Promise::err(::capnp::Error::unimplemented(\"method not implemented\".to_string())) }}",
-                        module_name(name),
+                        "fn {}{}(&mut self, _: {}Params<{}>, _: {}Results<{}>) -> ::capnp::capability::Promise<(), ::capnp::Error>{} {{ ::capnp::capability::Promise::err(::capnp::Error::unimplemented(::capnp::capability::METHOD_NOT_IMPLEMENTED.to_string())) }}",
+                        module_name(name), method_ty_params,
                         capitalize_first_letter(name), params_ty_params,
-                        capitalize_first_letter(name), results_ty_params
+                        capitalize_first_letter(name), results_ty_params,
+                        method_where_clause
                     )));
 
+                // A mock cannot script the results of a method with implicit parameters,
+                // whose types are only known to the caller.
+                if implicit_params.is_empty() {
+                    let field_name = module_name(name);
+                    mock_fields.push(Line(format!(
+                        "pub {}: ::std::rc::Rc<::std::cell::RefCell<::capnp::mock::MockMethod<{},{}>>>,",
+                        field_name, param_type, result_type)));
+                    mock_inits.push(Line(format!(
+                        "{}: ::std::rc::Rc::new(::std::cell::RefCell::new(::capnp::mock::MockMethod::new(\"{}.{}\"))),",
+                        field_name, interface_name, name)));
+                    mock_clones.push(Line(format!("{0}: self.{0}.clone(),", field_name)));
+                    mock_methods.push(Line(format!(
+                        "fn {0}(&mut self, params: {1}Params<{2}>, results: {1}Results<{3}>) -> ::capnp::capability::Promise<(), ::capnp::Error> {{ self.{0}.borrow_mut().handle(params, results) }}",
+                        field_name, capitalize_first_letter(name), params_ty_params, results_ty_params)));
+                }
+
+                client_impl_interior.push(doc_comment);
                 client_impl_interior.push(
-                    Line(format!("pub fn {}_request(&self) -> ::capnp::capability::Request<{},{}> {{",
-                                 camel_to_snake_case(name), param_type, result_type)));
+                    Line(format!("pub fn {}_request{}(&self) -> ::capnp::capability::Request<{},{}>{} {{",
+                                 camel_to_snake_case(name), method_ty_params, param_type, result_type,
+                                 method_where_clause)));
 
                 client_impl_interior.push(Indent(
                     Box::new(Line(format!("self.client.new_call(_private::TYPE_ID, {}, None)", ordinal)))));
@@ -1575,21 +2183,28 @@ fn generate_node(gen: &GeneratorContext,
 
                 try!(method.get_annotations());
             }
+            gen.implicit_method_params.borrow_mut().clear();
+            gen.set_member(None);
 
             let mut base_dispatch_arms = Vec::new();
+            let mut method_name_arms = vec![
+                Line(format!("_private::TYPE_ID => method_name(method_id).map(|name| ({:?}, name)),", display_name))];
             let server_base = {
                 let mut base_traits = Vec::new();
                 let extends = try!(interface.get_superclasses());
                 for ii in 0..extends.len() {
                     let type_id = extends.get(ii).get_id();
                     let brand = try!(extends.get(ii).get_brand());
-                    let the_mod = gen.scope_map[&type_id].join("::");
+                    let the_mod = gen.get_scope(type_id)?.join("::");
 
                     base_dispatch_arms.push(Line(format!(
                         "0x{:x} => {}::dispatch_call_internal(&mut *self.server, method_id, params, results),",
                         type_id,
                         try!(do_branding(
                             gen, type_id, brand, Leaf::ServerDispatch, the_mod.clone(), None)))));
+                    method_name_arms.push(Line(format!(
+                        "0x{:x} => {}::method_name(method_id).map(|name| ({:?}, name)),",
+                        type_id, the_mod, gen.get_node(type_id)?.get_display_name()?)));
                     base_traits.push(try!(
                         do_branding(gen, type_id, brand, Leaf::Server, the_mod, None)));
                 }
@@ -1597,6 +2212,13 @@ fn generate_node(gen: &GeneratorContext,
                 else { "".to_string() }
             };
 
+            mod_interior.push(BlankLine);
+            mod_interior.push(Line("/// The names of the methods as the schema spells them, indexed by ordinal.".to_string()));
+            mod_interior.push(Line(format!("pub const METHOD_NAMES: &'static [&'static str] = &[{}];", method_names.join(", "))));
+            mod_interior.push(Line("/// Returns the name of the method with ordinal `method_id`, if there is one.".to_string()));
+            mod_interior.push(Line("pub fn method_name(method_id: u16) -> Option<&'static str> {".to_string()));
+            mod_interior.push(Indent(Box::new(Line("METHOD_NAMES.get(method_id as usize).cloned()".to_string()))));
+            mod_interior.push(Line("}".to_string()));
             mod_interior.push(BlankLine);
             mod_interior.push(Line(format!("pub struct Client{} {{", bracketed_params)));
             mod_interior.push(Indent(Box::new(Line("pub client: ::capnp::capability::Client,".to_string()))));
@@ -1612,6 +2234,19 @@ fn generate_node(gen: &GeneratorContext,
                     Indent(Box::new(Line("}".to_string()))),
                     Line("}".to_string()))));
 
+            if gen.options.debug_impls {
+                mod_interior.push(
+                    Branch(vec!(
+                        Line(format!("impl {} ::std::fmt::Debug for Client{} {{", bracketed_params, bracketed_params)),
+                        Indent(Box::new(Line("fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {".to_string()))),
+                        Indent(Box::new(Indent(Box::new(Line(format!("write!(f, \"<{} capability>\")", interface_name)))))),
+                        Indent(Box::new(Line("}".to_string()))),
+                        Line("}".to_string()))));
+            }
+
+            if gen.options.serde {
+                mod_interior.push(generate_client_serde_impl(&bracketed_params));
+            }
 
             mod_interior.push(if !is_generic {
                 Branch(vec!(
@@ -1755,7 +2390,13 @@ fn generate_node(gen: &GeneratorContext,
                     Indent(Box::new(Indent(Box::new(Indent(
                         Box::new(Line(format!("_private::TYPE_ID => ServerDispatch::<_T, {}>::dispatch_call_internal(&mut *self.server, method_id, params, results),",params.params)))))))),
                     Indent(Box::new(Indent(Box::new(Indent(Box::new(Branch(base_dispatch_arms))))))),
-                    Indent(Box::new(Indent(Box::new(Indent(Box::new(Line("_ => { ::capnp::capability::Promise::err(::capnp::Error::unimplemented(\"Method not implemented.\".to_string())) }".to_string()))))))),
+                    Indent(Box::new(Indent(Box::new(Indent(Box::new(Line("_ => { ::capnp::capability::Promise::err(::capnp::Error::unimplemented(::capnp::capability::METHOD_NOT_IMPLEMENTED.to_string())) }".to_string()))))))),
+                    Indent(Box::new(Indent(Box::new(Line("}".to_string()))))),
+                    Indent(Box::new(Line("}".to_string()))),
+                    Indent(Box::new(Line("fn method_name(&self, interface_id: u64, method_id: u16) -> Option<(&'static str, &'static str)> {".to_string()))),
+                    Indent(Box::new(Indent(Box::new(Line("match interface_id {".to_string()))))),
+                    Indent(Box::new(Indent(Box::new(Indent(Box::new(Branch(method_name_arms))))))),
+                    Indent(Box::new(Indent(Box::new(Indent(Box::new(Line("_ => None,".to_string()))))))),
                     Indent(Box::new(Indent(Box::new(Line("}".to_string()))))),
                     Indent(Box::new(Line("}".to_string()))),
                     Line("}".to_string()))));
@@ -1770,11 +2411,64 @@ fn generate_node(gen: &GeneratorContext,
                     Indent(Box::new(Line("pub fn dispatch_call_internal(server: &mut _T, method_id: u16, params: ::capnp::capability::Params<::capnp::any_pointer::Owned>, results: ::capnp::capability::Results<::capnp::any_pointer::Owned>) -> ::capnp::capability::Promise<(), ::capnp::Error> {".to_string()))),
                     Indent(Box::new(Indent(Box::new(Line("match method_id {".to_string()))))),
                     Indent(Box::new(Indent(Box::new(Indent(Box::new(Branch(dispatch_arms))))))),
-                    Indent(Box::new(Indent(Box::new(Indent(Box::new(Line("_ => { ::capnp::capability::Promise::err(::capnp::Error::unimplemented(\"Method not implemented.\".to_string())) }".to_string()))))))),
+                    Indent(Box::new(Indent(Box::new(Indent(Box::new(Line("_ => { ::capnp::capability::Promise::err(::capnp::Error::unimplemented(::capnp::capability::METHOD_NOT_IMPLEMENTED.to_string())) }".to_string()))))))),
                     Indent(Box::new(Indent(Box::new(Line("}".to_string()))))),
                     Indent(Box::new(Line("}".to_string()))),
                     Line("}".to_string()))));
 
+            if gen.options.mock_servers {
+                let mut superclasses = Vec::new();
+                collect_superclass_servers(gen, node_id, &mut superclasses)?;
+                let phantom_field = if is_generic {
+                    vec![Line(format!("_phantom: ::std::marker::PhantomData<({})>,", params.params))]
+                } else {
+                    Vec::new()
+                };
+                let phantom_init = if is_generic {
+                    vec![Line("_phantom: ::std::marker::PhantomData,".to_string())]
+                } else {
+                    Vec::new()
+                };
+                let mut mock = vec![
+                    Line("/// A `Server` for tests, which records the parameters of the calls to each method".to_string()),
+                    Line("/// and answers them with the responses scripted for the method. Clones share their".to_string()),
+                    Line("/// state, so a clone can be kept to inspect calls while another is converted into a".to_string()),
+                    Line("/// client with `ToClient`.".to_string()),
+                    Line(format!("pub struct Mock{} {} {{", bracketed_params, params.where_clause)),
+                    Indent(Box::new(Branch(mock_fields))),
+                    Indent(Box::new(Branch(phantom_field))),
+                    Line("}".to_string()),
+                    Line(format!("impl {0} Mock{0} {1} {{", bracketed_params, params.where_clause)),
+                    Indent(Box::new(Branch(vec![
+                        Line(format!("pub fn new() -> Mock{} {{", bracketed_params)),
+                        Indent(Box::new(Branch(vec![
+                            Line("Mock {".to_string()),
+                            Indent(Box::new(Branch(mock_inits))),
+                            Indent(Box::new(Branch(phantom_init.clone()))),
+                            Line("}".to_string())]))),
+                        Line("}".to_string())]))),
+                    Line("}".to_string()),
+                    Line(format!("impl {0} Clone for Mock{0} {1} {{", bracketed_params, params.where_clause)),
+                    Indent(Box::new(Branch(vec![
+                        Line(format!("fn clone(&self) -> Mock{} {{", bracketed_params)),
+                        Indent(Box::new(Branch(vec![
+                            Line("Mock {".to_string()),
+                            Indent(Box::new(Branch(mock_clones))),
+                            Indent(Box::new(Branch(phantom_init))),
+                            Line("}".to_string())]))),
+                        Line("}".to_string())]))),
+                    Line("}".to_string()),
+                    Line(format!("impl {0} Server{0} for Mock{0} {1} {{", bracketed_params, params.where_clause)),
+                    Indent(Box::new(Branch(mock_methods))),
+                    Line("}".to_string()),
+                ];
+                for superclass in superclasses {
+                    mock.push(Line(format!("impl {0} {1} for Mock{0} {2} {{}}",
+                                           bracketed_params, superclass, params.where_clause)));
+                }
+                mod_interior.push(Branch(mock));
+            }
+
             mod_interior.push(
                 Branch(vec!(
                     Line("pub mod _private {".to_string()),
@@ -1785,6 +2479,7 @@ fn generate_node(gen: &GeneratorContext,
             mod_interior.push(Branch(vec!(Branch(nested_output))));
 
             output.push(BlankLine);
+            output.push(try!(gen.node_doc_comment(node_id)));
             if is_generic {
                 output.push(Line(format!("pub mod {} {{ /* ({}) */", node_name, params.expanded_list.join(","))));
             } else {
@@ -1823,7 +2518,7 @@ fn generate_node(gen: &GeneratorContext,
                                 let enumerants = try!(e.get_enumerants());
                                 if (v as u32) < enumerants.len() {
                                     let variant =
-                                        capitalize_first_letter(try!(enumerants.get(v as u32).get_name()));
+                                        capitalize_first_letter(get_enumerant_name(enumerants.get(v as u32))?);
                                     let type_string = try!(typ.type_string(gen, Leaf::Owned));
                                     Line(format!("pub const {}: {} = {}::{};",
                                                  styled_name,
@@ -1851,31 +2546,30 @@ fn generate_node(gen: &GeneratorContext,
                 (type_::List(_), value::List(v)) => try!(generate_pointer_constant(gen, &styled_name, typ, v)),
                 (type_::Struct(_), value::Struct(v)) => try!(generate_pointer_constant(gen, &styled_name, typ, v)),
 
-                (type_::Interface(_t), value::Interface(())) => {
-                    return Err(Error::unimplemented(format!("interface constants")));
-                }
-                (type_::AnyPointer(_), value::AnyPointer(_pr)) => {
-                    return Err(Error::unimplemented(format!("anypointer constants")));
+                (type_::Interface(_), value::Interface(())) => {
+                    // The only valid value for an interface constant is null, so this returns
+                    // a client on which every call fails. It has to be a function, because a
+                    // client cannot be built in a constant expression.
+                    Branch(vec![
+                        Line(format!("pub fn {}() -> {} {{",
+                                     try!(gen.get_last_name(node_id)),
+                                     try!(typ.type_string(gen, Leaf::Client)))),
+                        Indent(Box::new(Line(
+                            "::capnp::capability::FromClientHook::new(::capnp::capability::new_null_cap())".to_string()))),
+                        Line("}".to_string())])
                 }
+                (type_::AnyPointer(_), value::AnyPointer(v)) => try!(generate_pointer_constant(gen, &styled_name, typ, v)),
 
-                _ => { return Err(Error::failed(format!("type does not match value"))); }
+                _ => return Err(Error::failed(diagnostics::mismatch("the constant", typ, c.get_value()?)?)),
             };
 
+            output.push(try!(gen.node_doc_comment(node_id)));
             output.push(formatted_text);
         }
 
-        node::Annotation( annotation_reader ) => {
-            println!("  annotation node:");
-            if annotation_reader.get_targets_file() {
-                println!("  targets file");
-            }
-            if annotation_reader.get_targets_const() {
-                println!("  targets const");
-            }
-            // ...
-            if annotation_reader.get_targets_annotation() {
-                println!("  targets annotation");
-            }
+        node::Annotation(_) => {
+            // Annotation declarations do not generate any code. Uses of the annotations
+            // from `rust.capnp` are interpreted when we compute names and scopes.
         }
     }
 
@@ -1890,23 +2584,65 @@ pub fn main<T>(inp: T, out_dir: &::std::path::Path) -> ::capnp::Result<()>
 }
 
 /// Generates Rust code according to a `schema_capnp::code_generator_request` read from `inp`. Supports rust edition specification.
-pub fn generate_code<T>(mut inp: T, out_dir: &::std::path::Path, edition: RustEdition) -> ::capnp::Result<()>
+pub fn generate_code<T>(inp: T, out_dir: &::std::path::Path, edition: RustEdition) -> ::capnp::Result<()>
+    where T: ::std::io::Read
+{
+    let mut options = GeneratorOptions::new();
+    options.edition = edition;
+    generate_code_with_options(inp, out_dir, options)
+}
+
+/// Generates Rust code according to a `schema_capnp::code_generator_request` read from `inp`,
+/// as configured by `options`.
+pub fn generate_code_with_options<T>(inp: T, out_dir: &::std::path::Path, options: GeneratorOptions)
+                                     -> ::capnp::Result<()>
+    where T: ::std::io::Read
+{
+    let files = generate_code_in_memory(inp, options)?;
+    write_generated_files(out_dir, &files)
+}
+
+/// Generates Rust code according to a `schema_capnp::code_generator_request` read from `inp`,
+/// as configured by `options`, and returns it instead of writing it to disk. Each element of
+/// the result holds the path of a generated file, relative to the output directory, and its
+/// contents.
+///
+/// If code cannot be generated for some nodes, the error describes each of them, one per
+/// line, as a `diagnostics::Diagnostic`. Warnings are dropped; use
+/// `generate_code_with_diagnostics()` to get them.
+pub fn generate_code_in_memory<T>(inp: T, options: GeneratorOptions)
+                                  -> ::capnp::Result<Vec<(::std::path::PathBuf, String)>>
+    where T: ::std::io::Read
+{
+    let (files, diagnostics) = generate_code_with_diagnostics(inp, options)?;
+    diagnostics::check(&diagnostics)?;
+    Ok(files)
+}
+
+/// Like `generate_code_in_memory()`, but also returns the warnings and errors found. The code
+/// of the nodes with errors is left out of the returned files, so this only fails if the
+/// request cannot be read.
+pub fn generate_code_with_diagnostics<T>(mut inp: T, options: GeneratorOptions)
+    -> ::capnp::Result<(Vec<(::std::path::PathBuf, String)>, Vec<Diagnostic>)>
     where T: ::std::io::Read
 {
     use capnp::serialize;
-    use std::io::Write;
 
     let message = serialize::read_message(&mut inp, capnp::message::ReaderOptions::new())?;
 
-    let gen = GeneratorContext::new_with_edition(&message, edition)?;
+    let gen = GeneratorContext::new_with_options(&message, options)?;
 
+    let mut result = Vec::new();
+    let mut modules = ModuleTree::new();
     for requested_file in gen.request.get_requested_files()?.iter() {
         let id = requested_file.get_id();
-        let mut filepath = out_dir.to_path_buf();
-        let requested = ::std::path::PathBuf::from(requested_file.get_filename()?);
-        filepath.push(requested);
-        if let Some(parent) = filepath.parent() {
-            ::std::fs::create_dir_all(parent)?;
+        if gen.options.crates_provide_map.contains_key(&id) {
+            // This file's code is generated by another crate.
+            continue;
+        }
+        let mut filepath = ::std::path::PathBuf::from(requested_file.get_filename()?);
+        if gen.options.output_layout == OutputLayout::Flat {
+            filepath = ::std::path::PathBuf::from(path_to_stem_string(&filepath)?);
         }
 
         let root_name = path_to_stem_string(&filepath)?.replace("-", "_");
@@ -1917,9 +2653,113 @@ pub fn generate_code<T>(mut inp: T, out_dir: &::std::path::Path, edition: RustEd
             Line("// DO NOT EDIT.".to_string()),
             Line(format!("// source: {}", requested_file.get_filename()?)),
             BlankLine,
-            generate_node(&gen, id, &root_name, None)?));
+            generate_node(&gen, id, &root_name, None)));
+
+        if gen.options.modules_file.is_some() {
+            let mut module_path = get_parent_module(&gen.node_map, id, &gen.options.default_parent_module)?;
+            module_path.push(format!("{}_capnp", root_name));
+            *gen.location.borrow_mut() = (id, None);
+            modules.insert(&gen, &module_path, &filepath);
+        }
+
+        result.push((filepath, stringify(&lines)));
+    }
 
-        let text = stringify(&lines);
+    if let Some(ref modules_file) = gen.options.modules_file {
+        // `include!` paths are relative to the file that contains them.
+        let mut prefix = String::new();
+        if let Some(parent) = modules_file.parent() {
+            for _ in parent.components() {
+                prefix.push_str("../");
+            }
+        }
+        let lines = Branch(vec!(
+            Line("// Generated by the capnpc-rust plugin to the Cap'n Proto schema compiler.".to_string()),
+            Line("// DO NOT EDIT.".to_string()),
+            BlankLine,
+            modules.generate(&prefix)));
+        result.push((modules_file.clone(), stringify(&lines)));
+    }
+
+    let diagnostics = gen.diagnostics.replace(Vec::new());
+    Ok((result, diagnostics))
+}
+
+/// The modules declared by `GeneratorOptions::modules_file`, below the module named by
+/// `GeneratorOptions::default_parent_module`.
+struct ModuleTree {
+    /// The generated file, relative to the output directory, that holds the contents of this
+    /// module, if any.
+    file: Option<::std::path::PathBuf>,
+    children: collections::BTreeMap<String, ModuleTree>,
+}
+
+impl ModuleTree {
+    fn new() -> ModuleTree {
+        ModuleTree { file: None, children: collections::BTreeMap::new() }
+    }
+
+    /// Records that the module with absolute path `module_path` holds the contents of
+    /// `file`, reporting an error at the current location of `gen` if it cannot be declared.
+    fn insert(&mut self, gen: &GeneratorContext, module_path: &[String], file: &::std::path::Path) {
+        let root = &gen.options.default_parent_module;
+        if !module_path.starts_with(root) {
+            gen.report(Severity::Error, format!(
+                "module {} is not inside {}, so it cannot be declared in the modules file",
+                module_path.join("::"),
+                if root.is_empty() { "the crate root".to_string() } else { root.join("::") }));
+            return;
+        }
+        let mut tree = self;
+        for name in &module_path[root.len()..] {
+            tree = tree.children.entry(name.clone()).or_insert_with(ModuleTree::new);
+        }
+        match tree.file {
+            Some(ref other) => gen.report(Severity::Error, format!(
+                "module {} is generated both in {} and in {}",
+                module_path.join("::"), other.display(), file.display())),
+            None => tree.file = Some(file.to_path_buf()),
+        }
+    }
+
+    /// Generates the declarations of the children of this module. `prefix` leads from the
+    /// modules file to the output directory.
+    fn generate(&self, prefix: &str) -> FormattedText {
+        let mut lines = Vec::new();
+        for (name, child) in &self.children {
+            let mut contents = Vec::new();
+            if let Some(ref file) = child.file {
+                let components: Vec<_> =
+                    file.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect();
+                contents.push(Line(format!("include!({:?});", format!("{}{}", prefix, components.join("/")))));
+            }
+            contents.push(child.generate(prefix));
+            lines.push(Line(format!("pub mod {} {{", name)));
+            lines.push(Indent(Box::new(Branch(contents))));
+            lines.push(Line("}".to_string()));
+        }
+        Branch(lines)
+    }
+}
+
+/// Writes files returned by `generate_code_in_memory()` to `out_dir`, creating directories
+/// as needed. A file that already has the contents to be written is left untouched, so that
+/// its modification time does not make Cargo rebuild the code that includes it.
+pub fn write_generated_files(out_dir: &::std::path::Path, files: &[(::std::path::PathBuf, String)])
+                             -> ::capnp::Result<()>
+{
+    use std::io::Write;
+
+    for &(ref path, ref text) in files {
+        let filepath = out_dir.join(path);
+        if let Some(parent) = filepath.parent() {
+            ::std::fs::create_dir_all(parent)?;
+        }
+        if let Ok(existing) = ::std::fs::read(&filepath) {
+            if existing == text.as_bytes() {
+                continue;
+            }
+        }
 
         // It would be simpler to use try! instead of a pattern match, but then the error message
         // would not include `filepath`.
@@ -84,7 +84,7 @@ pub struct TypeParameterTexts {
 // this is a collection of helpers acting on a "Node" (most of them are Type definitions)
 pub trait RustNodeInfo {
     fn parameters_texts(&self, gen: &::codegen::GeneratorContext,
-                        parent_node_id: Option<u64>) -> TypeParameterTexts;
+                        parent_node_id: Option<u64>) -> Result<TypeParameterTexts, Error>;
 }
 
 // this is a collection of helpers acting on a "Type" (someplace where a Type is used, not defined)
@@ -98,9 +98,9 @@ pub trait RustTypeInfo {
 
 impl <'a> RustNodeInfo for node::Reader<'a> {
     fn parameters_texts(&self, gen:&::codegen::GeneratorContext,
-                        parent_node_id: Option<u64>) -> TypeParameterTexts {
+                        parent_node_id: Option<u64>) -> Result<TypeParameterTexts, Error> {
         if self.get_is_generic() {
-            let params = get_type_parameters(&gen, self.get_id(), parent_node_id);
+            let params = get_type_parameters(&gen, self.get_id(), parent_node_id)?;
             let type_parameters = params.iter().map(|param| {
                 format!("{}",param)
             }).collect::<Vec<String>>().join(",");
@@ -115,23 +115,23 @@ impl <'a> RustNodeInfo for node::Reader<'a> {
             }).collect::<Vec<String>>().join(", ") + " ");
             let phantom_data = "_phantom: ::std::marker::PhantomData,".to_string();
 
-            TypeParameterTexts {
+            Ok(TypeParameterTexts {
                 expanded_list: params,
                 params: type_parameters,
                 where_clause: where_clause,
                 where_clause_with_send: where_clause_with_send,
                 pipeline_where_clause: pipeline_where_clause,
                 phantom_data: phantom_data
-            }
+            })
         } else {
-            TypeParameterTexts {
+            Ok(TypeParameterTexts {
                 expanded_list: vec!(),
                 params: "".to_string(),
                 where_clause: "".to_string(),
                 where_clause_with_send: "".to_string(),
                 pipeline_where_clause: "".to_string(),
                 phantom_data: "".to_string(),
-            }
+            })
         }
     }
 }
@@ -167,11 +167,11 @@ impl <'a> RustTypeInfo for type_::Reader<'a> {
             type_::Data(()) => Ok(format!("::capnp::data::{}", module)),
             type_::Struct(st) => {
                 do_branding(gen, st.get_type_id(), try!(st.get_brand()), module,
-                            gen.scope_map[&st.get_type_id()].join("::"), None)
+                            gen.get_scope(st.get_type_id())?.join("::"), None)
             }
             type_::Interface(interface) => {
                 do_branding(gen, interface.get_type_id(), try!(interface.get_brand()), module,
-                            gen.scope_map[&interface.get_type_id()].join("::"), None)
+                            gen.get_scope(interface.get_type_id())?.join("::"), None)
             }
             type_::List(ot1) => {
                 let element_type = try!(ot1.get_element_type());
@@ -206,18 +206,26 @@ impl <'a> RustTypeInfo for type_::Reader<'a> {
                 }
             },
             type_::Enum(en) => {
-                let scope = &gen.scope_map[&en.get_type_id()];
+                let scope = gen.get_scope(en.get_type_id())?;
                 Ok(scope.join("::").to_string())
             },
             type_::AnyPointer(pointer) => {
-                match try!(pointer.which()) {
+                let parameter_name = match try!(pointer.which()) {
                     type_::any_pointer::Parameter(def) => {
-                        let the_struct = &gen.node_map[&def.get_scope_id()];
+                        let the_struct = gen.get_node(def.get_scope_id())?;
                         let parameters = try!(the_struct.get_parameters());
                         let parameter = parameters.get(def.get_parameter_index() as u32);
-                        let parameter_name = try!(parameter.get_name());
+                        Some(try!(parameter.get_name()).to_string())
+                    }
+                    type_::any_pointer::ImplicitMethodParameter(def) => {
+                        gen.implicit_method_param_name(def.get_parameter_index())
+                    }
+                    type_::any_pointer::Unconstrained(_) => None,
+                };
+                match parameter_name {
+                    Some(parameter_name) => {
                         match module {
-                            Leaf::Owned => Ok(parameter_name.to_string()),
+                            Leaf::Owned => Ok(parameter_name),
                             Leaf::Reader(lifetime) => {
                                 Ok(format!(
                                     "<{} as ::capnp::traits::Owned<{}>>::Reader",
@@ -234,7 +242,7 @@ impl <'a> RustTypeInfo for type_::Reader<'a> {
                             _ => Err(Error::unimplemented("unimplemented any_pointer leaf".to_string())),
                         }
                     },
-                    _ => {
+                    None => {
                         match module {
                             Leaf::Reader(lifetime) => {
                                 Ok(format!("::capnp::any_pointer::Reader<{}>", lifetime))
@@ -324,7 +332,11 @@ pub fn do_branding(gen: &GeneratorContext,
                     }
                     brand::scope::Bind(bindings_list_opt) => {
                         let bindings_list = try!(bindings_list_opt);
-                        assert_eq!(bindings_list.len(), params.len());
+                        if bindings_list.len() != params.len() {
+                            return Err(Error::failed(format!(
+                                "brand binds {} parameters of node @0x{:x}, which has {}",
+                                bindings_list.len(), current_node_id, params.len())));
+                        }
                         for binding in bindings_list.iter() {
                             match try!(binding.which()) {
                                 brand::binding::Unbound(()) => {
@@ -377,7 +389,7 @@ pub fn do_branding(gen: &GeneratorContext,
 
 pub fn get_type_parameters(gen: &GeneratorContext,
                            node_id: u64,
-                           mut parent_scope_id: Option<u64>) -> Vec<String> {
+                           mut parent_scope_id: Option<u64>) -> Result<Vec<String>, Error> {
     let mut current_node_id = node_id;
     let mut accumulator: Vec<Vec<String>> = Vec::new();
     loop {
@@ -386,8 +398,8 @@ pub fn get_type_parameters(gen: &GeneratorContext,
             Some(node) => node,
         };
         let mut params = Vec::new();
-        for param in current_node.get_parameters().unwrap().iter() {
-            params.push(param.get_name().unwrap().to_string());
+        for param in current_node.get_parameters()?.iter() {
+            params.push(param.get_name()?.to_string());
         }
 
         accumulator.push(params);
@@ -400,5 +412,5 @@ pub fn get_type_parameters(gen: &GeneratorContext,
     }
 
     accumulator.reverse();
-    accumulator.concat()
+    Ok(accumulator.concat())
 }
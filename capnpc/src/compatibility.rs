@@ -0,0 +1,429 @@
+// Copyright (c) 2013-2018 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Checking that a new version of a set of schemas can still communicate with the old one.
+//!
+//! Nodes are matched by ID, struct fields and enumerants by their position in the
+//! schema's member lists (which never changes for a member as a schema evolves), and
+//! methods by ordinal. Changes that break communication between the two versions, like
+//! changing the type of a field, are reported as [`Incompatibility`](struct.Incompatibility.html)s.
+//! Nodes that were removed, and members that were renamed, are not reported.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use schema_capnp::{code_generator_request, field, node, type_, value};
+
+/// A change between two versions of a schema that breaks wire compatibility.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Incompatibility {
+    /// ID of the changed node.
+    pub node_id: u64,
+
+    /// Display name of the node in the old schema, like `foo.capnp:Bar`.
+    pub node_name: String,
+
+    /// Name of the changed field, enumerant or method, if the change is to a member.
+    pub member: Option<String>,
+
+    /// What changed.
+    pub description: String,
+}
+
+impl fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.member {
+            Some(ref member) => write!(f, "{}.{}: {}", self.node_name, member, self.description),
+            None => write!(f, "{}: {}", self.node_name, self.description),
+        }
+    }
+}
+
+struct Checker<'a, 'b> {
+    old_nodes: HashMap<u64, node::Reader<'a>>,
+    new_nodes: HashMap<u64, node::Reader<'b>>,
+    result: Vec<Incompatibility>,
+}
+
+/// Compares the nodes of `old` with those of `new`, returning the changes that would
+/// prevent a program built from one from communicating with a program built from the other.
+pub fn check_compatibility(old: code_generator_request::Reader,
+                           new: code_generator_request::Reader)
+                           -> ::capnp::Result<Vec<Incompatibility>>
+{
+    let mut checker = Checker {
+        old_nodes: HashMap::new(),
+        new_nodes: HashMap::new(),
+        result: Vec::new(),
+    };
+    for node in old.get_nodes()?.iter() {
+        checker.old_nodes.insert(node.get_id(), node);
+    }
+    for node in new.get_nodes()?.iter() {
+        checker.new_nodes.insert(node.get_id(), node);
+    }
+    for old_node in old.get_nodes()?.iter() {
+        if let Some(&new_node) = checker.new_nodes.get(&old_node.get_id()) {
+            checker.check_node(old_node, new_node)?;
+        }
+    }
+    Ok(checker.result)
+}
+
+fn node_kind(node: node::Reader) -> ::capnp::Result<&'static str> {
+    Ok(match node.which()? {
+        node::File(()) => "file",
+        node::Struct(_) => "struct",
+        node::Enum(_) => "enum",
+        node::Interface(_) => "interface",
+        node::Const(_) => "const",
+        node::Annotation(_) => "annotation",
+    })
+}
+
+/// Returns a key that is equal for two types exactly when they are the same type, ignoring
+/// brands.
+fn type_key(typ: type_::Reader) -> ::capnp::Result<String> {
+    Ok(match typ.which()? {
+        type_::List(list) => format!("List({})", type_key(list.get_element_type()?)?),
+        type_::Enum(e) => format!("enum @0x{:x}", e.get_type_id()),
+        type_::Struct(s) => format!("struct @0x{:x}", s.get_type_id()),
+        type_::Interface(i) => format!("interface @0x{:x}", i.get_type_id()),
+        type_::AnyPointer(_) => "AnyPointer".to_string(),
+        _ => type_name(&HashMap::new(), typ)?,
+    })
+}
+
+/// Returns the display name of the node with ID `id` in `nodes`, or the ID if there is no
+/// such node.
+fn node_name(nodes: &HashMap<u64, node::Reader>, id: u64) -> ::capnp::Result<String> {
+    match nodes.get(&id) {
+        Some(node) => Ok(node.get_display_name()?.to_string()),
+        None => Ok(format!("@0x{:x}", id)),
+    }
+}
+
+/// Returns the name of `typ` as written in a schema, looking up named types in `nodes`.
+fn type_name(nodes: &HashMap<u64, node::Reader>, typ: type_::Reader) -> ::capnp::Result<String> {
+    Ok(match typ.which()? {
+        type_::Void(()) => "Void".to_string(),
+        type_::Bool(()) => "Bool".to_string(),
+        type_::Int8(()) => "Int8".to_string(),
+        type_::Int16(()) => "Int16".to_string(),
+        type_::Int32(()) => "Int32".to_string(),
+        type_::Int64(()) => "Int64".to_string(),
+        type_::Uint8(()) => "UInt8".to_string(),
+        type_::Uint16(()) => "UInt16".to_string(),
+        type_::Uint32(()) => "UInt32".to_string(),
+        type_::Uint64(()) => "UInt64".to_string(),
+        type_::Float32(()) => "Float32".to_string(),
+        type_::Float64(()) => "Float64".to_string(),
+        type_::Text(()) => "Text".to_string(),
+        type_::Data(()) => "Data".to_string(),
+        type_::List(list) => format!("List({})", type_name(nodes, list.get_element_type()?)?),
+        type_::Enum(e) => node_name(nodes, e.get_type_id())?,
+        type_::Struct(s) => node_name(nodes, s.get_type_id())?,
+        type_::Interface(i) => node_name(nodes, i.get_type_id())?,
+        type_::AnyPointer(_) => "AnyPointer".to_string(),
+    })
+}
+
+fn is_pointer(typ: type_::Reader) -> ::capnp::Result<bool> {
+    Ok(match typ.which()? {
+        type_::Text(()) | type_::Data(()) | type_::List(_) | type_::Struct(_) |
+        type_::Interface(_) | type_::AnyPointer(_) => true,
+        _ => false,
+    })
+}
+
+/// Returns a key for a default value of a non-pointer type, which is equal for two values
+/// exactly when they are encoded the same way.
+fn value_key(value: value::Reader) -> ::capnp::Result<Option<String>> {
+    Ok(Some(match value.which()? {
+        value::Void(()) => "void".to_string(),
+        value::Bool(v) => format!("{}", v),
+        value::Int8(v) => format!("{}", v),
+        value::Int16(v) => format!("{}", v),
+        value::Int32(v) => format!("{}", v),
+        value::Int64(v) => format!("{}", v),
+        value::Uint8(v) => format!("{}", v),
+        value::Uint16(v) => format!("{}", v),
+        value::Uint32(v) => format!("{}", v),
+        value::Uint64(v) => format!("{}", v),
+        value::Float32(v) => format!("{:?}", v),
+        value::Float64(v) => format!("{:?}", v),
+        value::Enum(v) => format!("enumerant {}", v),
+        _ => return Ok(None),
+    }))
+}
+
+impl <'a, 'b> Checker<'a, 'b> {
+    fn report(&mut self, node: node::Reader, member: Option<&str>, description: String)
+              -> ::capnp::Result<()>
+    {
+        self.result.push(Incompatibility {
+            node_id: node.get_id(),
+            node_name: node.get_display_name()?.to_string(),
+            member: member.map(|m| m.to_string()),
+            description: description,
+        });
+        Ok(())
+    }
+
+    fn check_node(&mut self, old: node::Reader<'a>, new: node::Reader<'b>) -> ::capnp::Result<()> {
+        match (old.which()?, new.which()?) {
+            (node::Struct(old_struct), node::Struct(new_struct)) => {
+                if !old_struct.get_is_group() {
+                    if new_struct.get_data_word_count() < old_struct.get_data_word_count() {
+                        self.report(old, None, format!(
+                            "data section shrank from {} to {} words",
+                            old_struct.get_data_word_count(), new_struct.get_data_word_count()))?;
+                    }
+                    if new_struct.get_pointer_count() < old_struct.get_pointer_count() {
+                        self.report(old, None, format!(
+                            "pointer section shrank from {} to {} pointers",
+                            old_struct.get_pointer_count(), new_struct.get_pointer_count()))?;
+                    }
+                }
+                if old_struct.get_discriminant_count() > 0 && new_struct.get_discriminant_count() > 0 &&
+                    old_struct.get_discriminant_offset() != new_struct.get_discriminant_offset()
+                {
+                    self.report(old, None, "union discriminant moved".to_string())?;
+                }
+
+                let old_fields = old_struct.get_fields()?;
+                let new_fields = new_struct.get_fields()?;
+                for (index, old_field) in old_fields.iter().enumerate() {
+                    if index as u32 >= new_fields.len() {
+                        self.report(old, Some(old_field.get_name()?), "field removed".to_string())?;
+                    } else {
+                        self.check_field(old, old_field, new_fields.get(index as u32))?;
+                    }
+                }
+            }
+            (node::Enum(old_enum), node::Enum(new_enum)) => {
+                let new_count = new_enum.get_enumerants()?.len();
+                for (index, enumerant) in old_enum.get_enumerants()?.iter().enumerate() {
+                    if index as u32 >= new_count {
+                        self.report(old, Some(enumerant.get_name()?), "enumerant removed".to_string())?;
+                    }
+                }
+            }
+            (node::Interface(old_interface), node::Interface(new_interface)) => {
+                let new_superclasses = new_interface.get_superclasses()?;
+                for superclass in old_interface.get_superclasses()?.iter() {
+                    if !new_superclasses.iter().any(|s| s.get_id() == superclass.get_id()) {
+                        let name = node_name(&self.old_nodes, superclass.get_id())?;
+                        self.report(old, None, format!("no longer extends {}", name))?;
+                    }
+                }
+
+                let old_methods = old_interface.get_methods()?;
+                let new_methods = new_interface.get_methods()?;
+                for (index, old_method) in old_methods.iter().enumerate() {
+                    let name = old_method.get_name()?;
+                    if index as u32 >= new_methods.len() {
+                        self.report(old, Some(name), "method removed".to_string())?;
+                        continue;
+                    }
+                    let new_method = new_methods.get(index as u32);
+                    if old_method.get_param_struct_type() != new_method.get_param_struct_type() {
+                        let description = format!(
+                            "parameters changed from {} to {}",
+                            node_name(&self.old_nodes, old_method.get_param_struct_type())?,
+                            node_name(&self.new_nodes, new_method.get_param_struct_type())?);
+                        self.report(old, Some(name), description)?;
+                    }
+                    if old_method.get_result_struct_type() != new_method.get_result_struct_type() {
+                        let description = format!(
+                            "results changed from {} to {}",
+                            node_name(&self.old_nodes, old_method.get_result_struct_type())?,
+                            node_name(&self.new_nodes, new_method.get_result_struct_type())?);
+                        self.report(old, Some(name), description)?;
+                    }
+                }
+            }
+            (node::File(()), node::File(())) | (node::Const(_), node::Const(_)) |
+            (node::Annotation(_), node::Annotation(_)) => {}
+            _ => {
+                self.report(old, None, format!("changed from {} to {}", node_kind(old)?, node_kind(new)?))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_field(&mut self, node: node::Reader<'a>, old: field::Reader<'a>, new: field::Reader<'b>)
+                   -> ::capnp::Result<()>
+    {
+        let name = old.get_name()?;
+        let old_discriminant = old.get_discriminant_value();
+        let new_discriminant = new.get_discriminant_value();
+        if old_discriminant == field::NO_DISCRIMINANT && new_discriminant != field::NO_DISCRIMINANT {
+            self.report(node, Some(name), "moved into a union".to_string())?;
+        } else if old_discriminant != field::NO_DISCRIMINANT && new_discriminant == field::NO_DISCRIMINANT {
+            self.report(node, Some(name), "moved out of a union".to_string())?;
+        } else if old_discriminant != new_discriminant {
+            self.report(node, Some(name), format!(
+                "union discriminant changed from {} to {}", old_discriminant, new_discriminant))?;
+        }
+
+        match (old.which()?, new.which()?) {
+            (field::Slot(old_slot), field::Slot(new_slot)) => {
+                let old_type = old_slot.get_type()?;
+                let new_type = new_slot.get_type()?;
+                let old_key = type_key(old_type)?;
+                let new_key = type_key(new_type)?;
+                let same_type = old_key == new_key;
+                // Any pointer field can be read as an AnyPointer, and an AnyPointer as anything.
+                let compatible_pointer = is_pointer(old_type)? && is_pointer(new_type)? &&
+                    (old_key == "AnyPointer" || new_key == "AnyPointer");
+                if !same_type && !compatible_pointer {
+                    let description = format!("type changed from {} to {}",
+                                              type_name(&self.old_nodes, old_type)?,
+                                              type_name(&self.new_nodes, new_type)?);
+                    self.report(node, Some(name), description)?;
+                } else if old_slot.get_offset() != new_slot.get_offset() {
+                    self.report(node, Some(name), format!(
+                        "moved from offset {} to offset {}", old_slot.get_offset(), new_slot.get_offset()))?;
+                } else if same_type {
+                    let old_default = value_key(old_slot.get_default_value()?)?;
+                    let new_default = value_key(new_slot.get_default_value()?)?;
+                    if let (Some(old_default), Some(new_default)) = (old_default, new_default) {
+                        if old_default != new_default {
+                            self.report(node, Some(name), format!(
+                                "default value changed from {} to {}", old_default, new_default))?;
+                        }
+                    }
+                }
+            }
+            (field::Group(_), field::Group(_)) => {}
+            (field::Slot(_), field::Group(_)) => {
+                self.report(node, Some(name), "changed from a field to a group".to_string())?;
+            }
+            (field::Group(_), field::Slot(_)) => {
+                self.report(node, Some(name), "changed from a group to a field".to_string())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_compatibility;
+    use compiler::compile_source;
+    use schema_capnp::code_generator_request;
+
+    fn check(old: &str, new: &str) -> Vec<String> {
+        let old = compile_source("test.capnp", old).unwrap();
+        let new = compile_source("test.capnp", new).unwrap();
+        check_compatibility(old.get_root_as_reader::<code_generator_request::Reader>().unwrap(),
+                            new.get_root_as_reader::<code_generator_request::Reader>().unwrap())
+            .unwrap().iter().map(|i| i.to_string()).collect()
+    }
+
+    const OLD: &'static str = r#"
+@0xa93fc509624c72d9;
+struct Foo {
+  a @0 :Int32;
+  b @1 :Text;
+  c @2 :UInt16 = 7;
+  union {
+    d @3 :Void;
+    e @4 :Int64;
+  }
+}
+enum Color { red @0; green @1; blue @2; }
+interface Bar {
+  get @0 (id :UInt64) -> Foo;
+  put @1 Foo -> ();
+}
+"#;
+
+    #[test]
+    fn compatible_changes() {
+        // Renames, new fields, enumerants and methods, and AnyPointer in place of a pointer.
+        let new = r#"
+@0xa93fc509624c72d9;
+struct Foo {
+  a @0 :Int32;
+  renamed @1 :AnyPointer;
+  c @2 :UInt16 = 7;
+  union {
+    d @3 :Void;
+    e @4 :Int64;
+    f @6 :Text;
+  }
+  g @5 :List(Float64);
+}
+enum Color { red @0; green @1; blue @2; violet @3; }
+interface Bar {
+  get @0 (id :UInt64, verbose :Bool) -> Foo;
+  put @1 Foo -> ();
+  delete @2 (id :UInt64);
+}
+"#;
+        assert_eq!(check(OLD, new), Vec::<String>::new());
+    }
+
+    #[test]
+    fn incompatible_changes() {
+        let new = r#"
+@0xa93fc509624c72d9;
+struct Foo {
+  a @0 :UInt32;
+  union {
+    b @1 :Text;
+    d @3 :Void;
+  }
+  c @2 :UInt16 = 8;
+  e @4 :Int64;
+}
+enum Color { red @0; green @1; }
+interface Bar {
+  get @0 (id :UInt64) -> Bar.Baz;
+  struct Baz {}
+}
+"#;
+        assert_eq!(check(OLD, new), vec![
+            "test.capnp:Foo.a: type changed from Int32 to UInt32".to_string(),
+            "test.capnp:Foo.b: moved into a union".to_string(),
+            "test.capnp:Foo.c: default value changed from 7 to 8".to_string(),
+            "test.capnp:Foo.d: union discriminant changed from 0 to 1".to_string(),
+            "test.capnp:Foo.e: moved out of a union".to_string(),
+            "test.capnp:Color.blue: enumerant removed".to_string(),
+            "test.capnp:Bar.get: results changed from test.capnp:Foo to test.capnp:Bar.Baz".to_string(),
+            "test.capnp:Bar.put: method removed".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn shrunk_sections_and_changed_kinds() {
+        let old = "@0xa93fc509624c72d9;\nstruct Foo { a @0 :UInt64; b @1 :Text; }\nstruct Baz @0xd2b2e9a3a4d8cbf3 {}\n";
+        let new = "@0xa93fc509624c72d9;\nstruct Foo { a @0 :UInt8; }\nenum Baz @0xd2b2e9a3a4d8cbf3 {}\n";
+        assert_eq!(check(old, new), vec![
+            "test.capnp:Foo: pointer section shrank from 1 to 0 pointers".to_string(),
+            "test.capnp:Foo.a: type changed from UInt64 to UInt8".to_string(),
+            "test.capnp:Foo.b: field removed".to_string(),
+            "test.capnp:Baz: changed from struct to enum".to_string(),
+        ]);
+    }
+}
@@ -0,0 +1,161 @@
+// Copyright (c) 2013-2018 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Syntax tree of a parsed schema file.
+
+use std::rc::Rc;
+
+/// A position in a schema file: the index of the file in the compiler's list of loaded
+/// files, and a byte offset within it.
+#[derive(Clone, Copy, Debug)]
+pub struct Span {
+    pub file: usize,
+    pub offset: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Name {
+    pub value: String,
+    pub span: Span,
+}
+
+/// A reference to a type, constant or annotation, like `Foo.Bar`, `.Foo`, `List(Text)` or
+/// `import "foo.capnp".Bar`.
+#[derive(Clone, Debug)]
+pub enum Expression {
+    /// A name to be looked up in the enclosing scopes.
+    Relative(Name),
+
+    /// A name prefixed by `.`, to be looked up at the top level of the file.
+    Absolute(Name),
+
+    Import(String, Span),
+
+    Member(Box<Expression>, Name),
+
+    /// Generic parameters applied to a type, like `Foo(Text)`.
+    Application(Box<Expression>, Vec<Expression>),
+}
+
+impl Expression {
+    pub fn span(&self) -> Span {
+        match *self {
+            Expression::Relative(ref name) | Expression::Absolute(ref name) => name.span,
+            Expression::Import(_, span) => span,
+            Expression::Member(ref parent, _) | Expression::Application(ref parent, _) => parent.span(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ValueKind {
+    /// An integer literal; `true` if negated.
+    Integer(u64, bool),
+    Float(f64),
+    String(String),
+    Binary(Vec<u8>),
+    List(Vec<Value>),
+    Struct(Vec<(Name, Value)>),
+
+    /// A name, which may refer to a constant, an enumerant or one of the keywords `void`,
+    /// `true`, `false`, `inf` and `nan`. `true` if negated.
+    Name(Expression, bool),
+}
+
+#[derive(Clone, Debug)]
+pub struct Value {
+    pub kind: ValueKind,
+    pub span: Span,
+}
+
+/// An annotation application like `$foo("bar")`.
+#[derive(Clone, Debug)]
+pub struct AnnotationApplication {
+    pub name: Expression,
+    pub value: Option<Value>,
+}
+
+/// The `@` part of a declaration.
+#[derive(Clone, Copy, Debug)]
+pub enum Id {
+    Unspecified,
+    Uid(u64, Span),
+    Ordinal(u16, Span),
+}
+
+#[derive(Clone, Debug)]
+pub struct Param {
+    pub name: Name,
+    pub type_: Expression,
+    pub default: Option<Value>,
+    pub annotations: Vec<AnnotationApplication>,
+}
+
+#[derive(Clone, Debug)]
+pub enum ParamList {
+    Named(Vec<Param>, Span),
+    Type(Expression),
+}
+
+#[derive(Clone, Debug)]
+pub enum DeclarationKind {
+    /// `using Foo = expression;`
+    Using(Expression),
+    Const { type_: Expression, value: Value },
+    Annotation { targets: Vec<Name>, type_: Expression },
+    Struct { params: Vec<Name>, nested: Vec<Rc<Declaration>> },
+    Enum { nested: Vec<Rc<Declaration>> },
+    Interface { params: Vec<Name>, superclasses: Vec<Expression>, nested: Vec<Rc<Declaration>> },
+    Field { type_: Expression, default: Option<Value> },
+
+    /// A union. Unnamed unions have an empty name.
+    Union { nested: Vec<Rc<Declaration>> },
+    Group { nested: Vec<Rc<Declaration>> },
+    Enumerant,
+    Method { implicit_params: Vec<Name>, params: ParamList, results: Option<ParamList> },
+}
+
+#[derive(Clone, Debug)]
+pub struct Declaration {
+    pub name: Name,
+    pub id: Id,
+    pub kind: DeclarationKind,
+    pub annotations: Vec<AnnotationApplication>,
+    pub doc_comment: Option<String>,
+}
+
+impl Declaration {
+    pub fn nested(&self) -> &[Rc<Declaration>] {
+        match self.kind {
+            DeclarationKind::Struct { ref nested, .. } | DeclarationKind::Enum { ref nested } |
+            DeclarationKind::Interface { ref nested, .. } | DeclarationKind::Union { ref nested } |
+            DeclarationKind::Group { ref nested } => nested,
+            _ => &[],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct File {
+    pub id: Id,
+    pub annotations: Vec<AnnotationApplication>,
+    pub declarations: Vec<Rc<Declaration>>,
+}
@@ -0,0 +1,434 @@
+// Copyright (c) 2013-2018 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Encoding of compiled nodes as `schema.capnp` structures.
+
+use std::collections::HashMap;
+
+use capnp::private::layout::{ElementSize, ListBuilder, PointerBuilder, PrimitiveElement,
+                             StructBuilder, StructSize};
+use capnp::traits::FromPointerBuilder;
+
+use compiler::model::*;
+use schema_capnp::{brand, field, node, type_, value};
+
+/// Gives access to the raw pointer behind an `any_pointer::Builder`, so that values whose
+/// type is only known at runtime can be written.
+struct RawPointer<'a>(PointerBuilder<'a>);
+
+impl <'a> FromPointerBuilder<'a> for RawPointer<'a> {
+    fn init_pointer(builder: PointerBuilder<'a>, _size: u32) -> RawPointer<'a> {
+        RawPointer(builder)
+    }
+
+    fn get_from_pointer(builder: PointerBuilder<'a>) -> ::capnp::Result<RawPointer<'a>> {
+        Ok(RawPointer(builder))
+    }
+}
+
+pub struct Emitter<'a> {
+    nodes: HashMap<u64, &'a Node>,
+}
+
+impl <'a> Emitter<'a> {
+    pub fn new(nodes: &'a [Node]) -> Emitter<'a> {
+        Emitter { nodes: nodes.iter().map(|n| (n.id, n)).collect() }
+    }
+
+    fn struct_of(&self, id: u64) -> &'a Struct {
+        match self.nodes[&id].body {
+            Body::Struct(ref s) => s,
+            _ => panic!("node @0x{:016x} is not a struct", id),
+        }
+    }
+
+    pub fn node(&self, node: &Node, mut builder: node::Builder) {
+        builder.set_id(node.id);
+        builder.set_display_name(&node.display_name);
+        builder.set_display_name_prefix_length(node.display_name_prefix_length);
+        builder.set_scope_id(node.scope_id);
+        {
+            let mut nested = builder.reborrow().init_nested_nodes(node.nested_nodes.len() as u32);
+            for (i, &(ref name, id)) in node.nested_nodes.iter().enumerate() {
+                let mut n = nested.reborrow().get(i as u32);
+                n.set_name(name);
+                n.set_id(id);
+            }
+        }
+        self.annotations(&node.annotations, builder.reborrow().init_annotations(node.annotations.len() as u32));
+        {
+            let mut parameters = builder.reborrow().init_parameters(node.parameters.len() as u32);
+            for (i, name) in node.parameters.iter().enumerate() {
+                parameters.reborrow().get(i as u32).set_name(name);
+            }
+        }
+        builder.set_is_generic(node.is_generic);
+
+        match node.body {
+            Body::File => builder.set_file(()),
+            Body::Struct(ref s) => {
+                let mut b = builder.init_struct();
+                b.set_data_word_count(s.data_word_count);
+                b.set_pointer_count(s.pointer_count);
+                b.set_preferred_list_encoding(::schema_capnp::ElementSize::InlineComposite);
+                b.set_is_group(s.is_group);
+                b.set_discriminant_count(s.discriminant_count);
+                b.set_discriminant_offset(s.discriminant_offset);
+                let mut fields = b.init_fields(s.fields.len() as u32);
+                for (i, f) in s.fields.iter().enumerate() {
+                    self.field(f, fields.reborrow().get(i as u32));
+                }
+            }
+            Body::Enum(ref enumerants) => {
+                let mut list = builder.init_enum().init_enumerants(enumerants.len() as u32);
+                for (i, e) in enumerants.iter().enumerate() {
+                    let mut b = list.reborrow().get(i as u32);
+                    b.set_name(&e.name);
+                    b.set_code_order(e.code_order);
+                    self.annotations(&e.annotations, b.init_annotations(e.annotations.len() as u32));
+                }
+            }
+            Body::Interface { ref methods, ref superclasses } => {
+                let mut b = builder.init_interface();
+                {
+                    let mut list = b.reborrow().init_methods(methods.len() as u32);
+                    for (i, m) in methods.iter().enumerate() {
+                        let mut mb = list.reborrow().get(i as u32);
+                        mb.set_name(&m.name);
+                        mb.set_code_order(m.code_order);
+                        {
+                            let mut implicit = mb.reborrow().init_implicit_parameters(m.implicit_parameters.len() as u32);
+                            for (j, name) in m.implicit_parameters.iter().enumerate() {
+                                implicit.reborrow().get(j as u32).set_name(name);
+                            }
+                        }
+                        mb.set_param_struct_type(m.param_struct_type);
+                        self.brand(&m.param_brand, mb.reborrow().init_param_brand());
+                        mb.set_result_struct_type(m.result_struct_type);
+                        self.brand(&m.result_brand, mb.reborrow().init_result_brand());
+                        self.annotations(&m.annotations, mb.init_annotations(m.annotations.len() as u32));
+                    }
+                }
+                let mut list = b.init_superclasses(superclasses.len() as u32);
+                for (i, s) in superclasses.iter().enumerate() {
+                    let mut sb = list.reborrow().get(i as u32);
+                    sb.set_id(s.id);
+                    self.brand(&s.brand, sb.init_brand());
+                }
+            }
+            Body::Const { ref type_, ref value } => {
+                let mut b = builder.init_const();
+                self.type_(type_, b.reborrow().init_type());
+                self.value(value, b.init_value());
+            }
+            Body::Annotation { ref type_, targets } => {
+                let mut b = builder.init_annotation();
+                self.type_(type_, b.reborrow().init_type());
+                b.set_targets_file(targets[0]);
+                b.set_targets_const(targets[1]);
+                b.set_targets_enum(targets[2]);
+                b.set_targets_enumerant(targets[3]);
+                b.set_targets_struct(targets[4]);
+                b.set_targets_field(targets[5]);
+                b.set_targets_union(targets[6]);
+                b.set_targets_group(targets[7]);
+                b.set_targets_interface(targets[8]);
+                b.set_targets_method(targets[9]);
+                b.set_targets_param(targets[10]);
+                b.set_targets_annotation(targets[11]);
+            }
+        }
+    }
+
+    fn field(&self, f: &Field, mut builder: field::Builder) {
+        builder.set_name(&f.name);
+        builder.set_code_order(f.code_order);
+        builder.set_discriminant_value(f.discriminant_value);
+        self.annotations(&f.annotations, builder.reborrow().init_annotations(f.annotations.len() as u32));
+        match f.ordinal {
+            Some(ordinal) => builder.reborrow().init_ordinal().set_explicit(ordinal),
+            None => builder.reborrow().init_ordinal().set_implicit(()),
+        }
+        match f.kind {
+            FieldKind::Slot { offset, ref type_, ref default_value, had_explicit_default } => {
+                let mut slot = builder.init_slot();
+                slot.set_offset(offset);
+                self.type_(type_, slot.reborrow().init_type());
+                self.value(default_value, slot.reborrow().init_default_value());
+                slot.set_had_explicit_default(had_explicit_default);
+            }
+            FieldKind::Group(id) => builder.init_group().set_type_id(id),
+        }
+    }
+
+    fn annotations(&self, annotations: &[Annotation],
+                   mut builder: ::capnp::struct_list::Builder<::schema_capnp::annotation::Owned>)
+    {
+        for (i, a) in annotations.iter().enumerate() {
+            let mut b = builder.reborrow().get(i as u32);
+            b.set_id(a.id);
+            self.brand(&a.brand, b.reborrow().init_brand());
+            self.value(&a.value, b.init_value());
+        }
+    }
+
+    pub fn type_(&self, t: &Type, mut builder: type_::Builder) {
+        match *t {
+            Type::Void => builder.set_void(()),
+            Type::Bool => builder.set_bool(()),
+            Type::Int8 => builder.set_int8(()),
+            Type::Int16 => builder.set_int16(()),
+            Type::Int32 => builder.set_int32(()),
+            Type::Int64 => builder.set_int64(()),
+            Type::Uint8 => builder.set_uint8(()),
+            Type::Uint16 => builder.set_uint16(()),
+            Type::Uint32 => builder.set_uint32(()),
+            Type::Uint64 => builder.set_uint64(()),
+            Type::Float32 => builder.set_float32(()),
+            Type::Float64 => builder.set_float64(()),
+            Type::Text => builder.set_text(()),
+            Type::Data => builder.set_data(()),
+            Type::List(ref element) => self.type_(element, builder.init_list().init_element_type()),
+            Type::Enum(id, ref brand) => {
+                let mut b = builder.init_enum();
+                b.set_type_id(id);
+                self.brand(brand, b.init_brand());
+            }
+            Type::Struct(id, ref brand) => {
+                let mut b = builder.init_struct();
+                b.set_type_id(id);
+                self.brand(brand, b.init_brand());
+            }
+            Type::Interface(id, ref brand) => {
+                let mut b = builder.init_interface();
+                b.set_type_id(id);
+                self.brand(brand, b.init_brand());
+            }
+            Type::AnyPointer(ref kind) => {
+                let b = builder.init_any_pointer();
+                match *kind {
+                    AnyPointerKind::AnyKind => b.init_unconstrained().set_any_kind(()),
+                    AnyPointerKind::Struct => b.init_unconstrained().set_struct(()),
+                    AnyPointerKind::List => b.init_unconstrained().set_list(()),
+                    AnyPointerKind::Capability => b.init_unconstrained().set_capability(()),
+                    AnyPointerKind::Parameter { scope_id, index } => {
+                        let mut p = b.init_parameter();
+                        p.set_scope_id(scope_id);
+                        p.set_parameter_index(index);
+                    }
+                    AnyPointerKind::ImplicitMethodParameter(index) => {
+                        b.init_implicit_method_parameter().set_parameter_index(index);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn brand(&self, brand: &Brand, builder: brand::Builder) {
+        let mut scopes = builder.init_scopes(brand.scopes.len() as u32);
+        for (i, scope) in brand.scopes.iter().enumerate() {
+            let mut b = scopes.reborrow().get(i as u32);
+            b.set_scope_id(scope.scope_id());
+            match *scope {
+                BrandScope::Inherit(_) => b.set_inherit(()),
+                BrandScope::Bind(_, ref bindings) => {
+                    let mut list = b.init_bind(bindings.len() as u32);
+                    for (j, binding) in bindings.iter().enumerate() {
+                        let mut bb = list.reborrow().get(j as u32);
+                        match *binding {
+                            Binding::Unbound => bb.set_unbound(()),
+                            Binding::Type(ref t) => self.type_(t, bb.init_type()),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn value(&self, v: &Value, mut builder: value::Builder) {
+        match *v {
+            Value::Void => builder.set_void(()),
+            Value::Bool(b) => builder.set_bool(b),
+            Value::Int8(x) => builder.set_int8(x),
+            Value::Int16(x) => builder.set_int16(x),
+            Value::Int32(x) => builder.set_int32(x),
+            Value::Int64(x) => builder.set_int64(x),
+            Value::Uint8(x) => builder.set_uint8(x),
+            Value::Uint16(x) => builder.set_uint16(x),
+            Value::Uint32(x) => builder.set_uint32(x),
+            Value::Uint64(x) => builder.set_uint64(x),
+            Value::Float32(x) => builder.set_float32(x),
+            Value::Float64(x) => builder.set_float64(x),
+            Value::Text(Some(ref s)) => builder.set_text(s),
+            Value::Text(None) => { builder.init_text(0); }
+            Value::Data(Some(ref d)) => builder.set_data(d),
+            Value::Data(None) => { builder.init_data(0); }
+            Value::List(_, None) => { builder.init_list(); }
+            Value::List(ref element, Some(ref elements)) => {
+                let RawPointer(pointer) = builder.init_list().init_as::<RawPointer>();
+                self.list(pointer, element, elements);
+            }
+            Value::Enum(x) => builder.set_enum(x),
+            Value::Struct(_, None) => { builder.init_struct(); }
+            Value::Struct(id, Some(ref fields)) => {
+                let RawPointer(pointer) = builder.init_struct().init_as::<RawPointer>();
+                self.struct_pointer(pointer, id, fields);
+            }
+            Value::Interface => builder.set_interface(()),
+            Value::AnyPointer(None) => { builder.init_any_pointer(); }
+            Value::AnyPointer(Some(ref value)) => {
+                let RawPointer(pointer) = builder.init_any_pointer().init_as::<RawPointer>();
+                self.pointer(pointer, value);
+            }
+        }
+    }
+
+    fn struct_pointer(&self, pointer: PointerBuilder, id: u64, fields: &[(usize, Value)]) {
+        let s = self.struct_of(id);
+        let builder = pointer.init_struct(StructSize { data: s.data_word_count, pointers: s.pointer_count });
+        self.struct_fields(builder, id, fields);
+    }
+
+    fn struct_fields(&self, builder: StructBuilder, id: u64, fields: &[(usize, Value)]) {
+        let s = self.struct_of(id);
+        for &(index, ref value) in fields {
+            let field = &s.fields[index];
+            if field.discriminant_value != NO_DISCRIMINANT {
+                builder.set_data_field::<u16>(s.discriminant_offset as usize, field.discriminant_value);
+            }
+            match field.kind {
+                FieldKind::Slot { offset, ref type_, ref default_value, .. } => {
+                    match type_.placement() {
+                        Placement::None => (),
+                        Placement::Data(0) => {
+                            let default = match *default_value { Value::Bool(b) => b, _ => false };
+                            let v = match *value { Value::Bool(b) => b, _ => false };
+                            builder.set_bool_field(offset as usize, v ^ default);
+                        }
+                        Placement::Data(lg_size) => {
+                            let bits = primitive_bits(value) ^ primitive_bits(default_value);
+                            let offset = offset as usize;
+                            match lg_size {
+                                3 => builder.set_data_field::<u8>(offset, bits as u8),
+                                4 => builder.set_data_field::<u16>(offset, bits as u16),
+                                5 => builder.set_data_field::<u32>(offset, bits as u32),
+                                _ => builder.set_data_field::<u64>(offset, bits),
+                            }
+                        }
+                        Placement::Pointer => {
+                            self.pointer(builder.get_pointer_field(offset as usize), value);
+                        }
+                    }
+                }
+                FieldKind::Group(group_id) => {
+                    if let Value::Struct(_, Some(ref group_fields)) = *value {
+                        self.struct_fields(builder, group_id, group_fields);
+                    }
+                }
+            }
+        }
+    }
+
+    fn pointer(&self, pointer: PointerBuilder, v: &Value) {
+        match *v {
+            Value::Text(Some(ref s)) => pointer.set_text(s),
+            Value::Data(Some(ref d)) => pointer.set_data(d),
+            Value::List(ref element, Some(ref elements)) => self.list(pointer, element, elements),
+            Value::Struct(id, Some(ref fields)) => self.struct_pointer(pointer, id, fields),
+            Value::AnyPointer(Some(ref value)) => self.pointer(pointer, value),
+            _ => (),
+        }
+    }
+
+    fn list(&self, pointer: PointerBuilder, element: &Type, elements: &[Value]) {
+        let count = elements.len() as u32;
+        match element.placement() {
+            Placement::None => { pointer.init_list(ElementSize::Void, count); }
+            Placement::Data(lg_size) => {
+                let size = match lg_size {
+                    0 => ElementSize::Bit,
+                    3 => ElementSize::Byte,
+                    4 => ElementSize::TwoBytes,
+                    5 => ElementSize::FourBytes,
+                    _ => ElementSize::EightBytes,
+                };
+                let list = pointer.init_list(size, count);
+                for (i, e) in elements.iter().enumerate() {
+                    set_primitive_element(&list, i as u32, e);
+                }
+            }
+            Placement::Pointer => {
+                if let Type::Struct(id, _) = *element {
+                    let s = self.struct_of(id);
+                    let list = pointer.init_struct_list(
+                        count, StructSize { data: s.data_word_count, pointers: s.pointer_count });
+                    for (i, e) in elements.iter().enumerate() {
+                        if let Value::Struct(_, Some(ref fields)) = *e {
+                            self.struct_fields(list.get_struct_element(i as u32), id, fields);
+                        }
+                    }
+                } else {
+                    let list = pointer.init_list(ElementSize::Pointer, count);
+                    for (i, e) in elements.iter().enumerate() {
+                        self.pointer(list.get_pointer_element(i as u32), e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The bits of a primitive value as stored in a struct's data section.
+fn primitive_bits(value: &Value) -> u64 {
+    match *value {
+        Value::Bool(b) => b as u64,
+        Value::Int8(x) => x as u8 as u64,
+        Value::Int16(x) => x as u16 as u64,
+        Value::Int32(x) => x as u32 as u64,
+        Value::Int64(x) => x as u64,
+        Value::Uint8(x) => x as u64,
+        Value::Uint16(x) => x as u64,
+        Value::Uint32(x) => x as u64,
+        Value::Uint64(x) => x,
+        Value::Float32(x) => x.to_bits() as u64,
+        Value::Float64(x) => x.to_bits(),
+        Value::Enum(x) => x as u64,
+        _ => 0,
+    }
+}
+
+fn set_primitive_element(list: &ListBuilder, index: u32, value: &Value) {
+    match *value {
+        Value::Bool(b) => PrimitiveElement::set(list, index, b),
+        Value::Int8(x) => PrimitiveElement::set(list, index, x),
+        Value::Int16(x) => PrimitiveElement::set(list, index, x),
+        Value::Int32(x) => PrimitiveElement::set(list, index, x),
+        Value::Int64(x) => PrimitiveElement::set(list, index, x),
+        Value::Uint8(x) => PrimitiveElement::set(list, index, x),
+        Value::Uint16(x) => PrimitiveElement::set(list, index, x),
+        Value::Uint32(x) => PrimitiveElement::set(list, index, x),
+        Value::Uint64(x) => PrimitiveElement::set(list, index, x),
+        Value::Float32(x) => PrimitiveElement::set(list, index, x),
+        Value::Float64(x) => PrimitiveElement::set(list, index, x),
+        Value::Enum(x) => PrimitiveElement::set(list, index, x),
+        _ => (),
+    }
+}
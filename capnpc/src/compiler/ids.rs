@@ -0,0 +1,174 @@
+// Copyright (c) 2013-2018 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Derivation of the IDs of nodes that don't declare one explicitly. These must match
+//! what the C++ compiler computes, so that both compilers agree on type IDs.
+
+const SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+    5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+    4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+    6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+const CONSTANTS: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee,
+    0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be,
+    0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa,
+    0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed,
+    0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c,
+    0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05,
+    0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039,
+    0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1,
+    0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+/// Computes the MD5 digest of `input`.
+fn md5(input: &[u8]) -> [u8; 16] {
+    let mut message = input.to_vec();
+    let bit_length = (input.len() as u64).wrapping_mul(8);
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    for i in 0..8 {
+        message.push((bit_length >> (i * 8)) as u8);
+    }
+
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    for chunk in message.chunks(64) {
+        let mut words = [0u32; 16];
+        for (i, word) in words.iter_mut().enumerate() {
+            *word = (chunk[i * 4] as u32) |
+                    (chunk[i * 4 + 1] as u32) << 8 |
+                    (chunk[i * 4 + 2] as u32) << 16 |
+                    (chunk[i * 4 + 3] as u32) << 24;
+        }
+
+        let (mut a, mut b, mut c, mut d) = (state[0], state[1], state[2], state[3]);
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a.wrapping_add(f).wrapping_add(CONSTANTS[i]).wrapping_add(words[g])
+                .rotate_left(SHIFTS[i]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    }
+
+    let mut result = [0u8; 16];
+    for (i, word) in state.iter().enumerate() {
+        for j in 0..4 {
+            result[i * 4 + j] = (word >> (j * 8)) as u8;
+        }
+    }
+    result
+}
+
+/// Takes the first eight bytes of the digest of `bytes` and sets the high bit, which all
+/// IDs have.
+fn id_from_bytes(bytes: &[u8]) -> u64 {
+    let digest = md5(bytes);
+    let mut result = 0u64;
+    for byte in &digest[..8] {
+        result = (result << 8) | (*byte as u64);
+    }
+    result | (1 << 63)
+}
+
+fn push_u64(bytes: &mut Vec<u8>, value: u64) {
+    for i in 0..8 {
+        bytes.push((value >> (i * 8)) as u8);
+    }
+}
+
+fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.push(value as u8);
+    bytes.push((value >> 8) as u8);
+}
+
+/// Returns the ID of the declaration `child_name` nested in the node `parent_id`.
+pub fn generate_child_id(parent_id: u64, child_name: &str) -> u64 {
+    let mut bytes = Vec::new();
+    push_u64(&mut bytes, parent_id);
+    bytes.extend_from_slice(child_name.as_bytes());
+    id_from_bytes(&bytes)
+}
+
+/// Returns the ID of the group or named union at index `group_index` of the fields of the
+/// struct `parent_id`.
+pub fn generate_group_id(parent_id: u64, group_index: u16) -> u64 {
+    let mut bytes = Vec::new();
+    push_u64(&mut bytes, parent_id);
+    push_u16(&mut bytes, group_index);
+    id_from_bytes(&bytes)
+}
+
+/// Returns the ID of the implicitly declared params or results struct of the method with
+/// ordinal `method_ordinal` of the interface `parent_id`.
+pub fn generate_method_params_id(parent_id: u64, method_ordinal: u16, is_results: bool) -> u64 {
+    let mut bytes = Vec::new();
+    push_u64(&mut bytes, parent_id);
+    push_u16(&mut bytes, method_ordinal);
+    bytes.push(is_results as u8);
+    id_from_bytes(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{md5, generate_child_id, generate_group_id, generate_method_params_id};
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn md5_digests() {
+        assert_eq!(hex(&md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hex(&md5(b"The quick brown fox jumps over the lazy dog")),
+                   "9e107d9d372bb6826bd81d3542a419d6");
+    }
+
+    #[test]
+    fn ids() {
+        // `Node` in schema.capnp.
+        assert_eq!(generate_child_id(0xa93fc509624c72d9, "Node"), 0xe682ab4cf923a417);
+        assert_eq!(generate_group_id(0xe682ab4cf923a417, 3), 0xf75bab5f5e1446ec);
+        assert_eq!(generate_method_params_id(0x85150b117366d14b, 0, true), 0xdcb67f5961f37635);
+    }
+}
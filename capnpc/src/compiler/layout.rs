@@ -0,0 +1,462 @@
+// Copyright (c) 2013-2018 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Allocation of struct fields to offsets in the data and pointer sections.
+//!
+//! This follows the algorithm of the C++ compiler exactly, since the offsets it produces
+//! are part of the wire format. Fields are added in ordinal order. Sizes are given as the
+//! base-2 logarithm of the number of bits (`lg_size`), and data offsets are in multiples
+//! of the field's size.
+
+const HOLE_COUNT: u32 = 6;
+
+/// Free space in a data section: `holes[i]` is the offset of a free slot of `2^i` bits,
+/// or zero if there is none. (Zero is never a valid hole, since a hole always follows some
+/// allocated space.)
+#[derive(Clone, Copy, Default)]
+struct HoleSet {
+    holes: [u32; HOLE_COUNT as usize],
+}
+
+impl HoleSet {
+    fn try_allocate(&mut self, lg_size: u32) -> Option<u32> {
+        if lg_size >= HOLE_COUNT {
+            None
+        } else if self.holes[lg_size as usize] != 0 {
+            let result = self.holes[lg_size as usize];
+            self.holes[lg_size as usize] = 0;
+            Some(result)
+        } else {
+            match self.try_allocate(lg_size + 1) {
+                Some(next) => {
+                    let result = next * 2;
+                    self.holes[lg_size as usize] = result + 1;
+                    Some(result)
+                }
+                None => None,
+            }
+        }
+    }
+
+    /// Records the holes that are left after allocating a field of size `lg_size` at the
+    /// start of a fresh space of size `limit_lg_size`. `offset` is the offset of the first
+    /// of them.
+    fn add_holes_at_end(&mut self, mut lg_size: u32, mut offset: u32, limit_lg_size: u32) {
+        while lg_size < limit_lg_size {
+            self.holes[lg_size as usize] = offset;
+            lg_size += 1;
+            offset = (offset + 1) / 2;
+        }
+    }
+
+    /// Tries to grow the value at `old_offset` to `2^expansion_factor` times its size by
+    /// merging it with the holes that follow it.
+    fn try_expand(&mut self, old_lg_size: u32, old_offset: u32, expansion_factor: u32) -> bool {
+        if expansion_factor == 0 {
+            return true
+        }
+        if old_lg_size >= HOLE_COUNT || self.holes[old_lg_size as usize] != old_offset + 1 {
+            return false
+        }
+        if self.try_expand(old_lg_size + 1, old_offset >> 1, expansion_factor - 1) {
+            self.holes[old_lg_size as usize] = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the size of the smallest hole of at least `lg_size`.
+    fn smallest_at_least(&self, lg_size: u32) -> Option<u32> {
+        (lg_size..HOLE_COUNT).find(|&i| self.holes[i as usize] != 0)
+    }
+}
+
+/// A slot of the data section that is shared by the members of a union.
+#[derive(Clone, Copy)]
+struct DataLocation {
+    lg_size: u32,
+    offset: u32,
+}
+
+/// How a member of a union uses one of the union's data locations.
+#[derive(Clone, Copy, Default)]
+struct DataLocationUsage {
+    is_used: bool,
+
+    /// The size of the prefix of the location that the member uses.
+    lg_size_used: u32,
+
+    /// Holes in the used prefix, with offsets relative to the location.
+    holes: HoleSet,
+}
+
+impl DataLocationUsage {
+    fn smallest_hole_at_least(&self, location: DataLocation, lg_size: u32) -> Option<u32> {
+        if !self.is_used {
+            if lg_size <= location.lg_size { Some(location.lg_size) } else { None }
+        } else if lg_size >= self.lg_size_used {
+            if lg_size < location.lg_size { Some(lg_size) } else { None }
+        } else if let Some(size) = self.holes.smallest_at_least(lg_size) {
+            Some(size)
+        } else if self.lg_size_used < location.lg_size {
+            Some(self.lg_size_used)
+        } else {
+            None
+        }
+    }
+
+    fn allocate_from_hole(&mut self, location: DataLocation, lg_size: u32) -> u32 {
+        let result = if !self.is_used {
+            self.is_used = true;
+            self.lg_size_used = lg_size;
+            0
+        } else if lg_size >= self.lg_size_used {
+            // Double the used space and take the second half.
+            self.holes.add_holes_at_end(self.lg_size_used, 1, lg_size);
+            self.lg_size_used = lg_size + 1;
+            1
+        } else if let Some(hole) = self.holes.try_allocate(lg_size) {
+            hole
+        } else {
+            let result = 1 << (self.lg_size_used - lg_size);
+            self.holes.add_holes_at_end(lg_size, result + 1, self.lg_size_used);
+            self.lg_size_used += 1;
+            result
+        };
+        (location.offset << (location.lg_size - lg_size)) + result
+    }
+}
+
+/// Where fields are added: the struct itself, or a member of a union.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scope {
+    Top,
+    Group(usize),
+}
+
+struct Union {
+    parent: Scope,
+    group_count: u32,
+    discriminant_offset: Option<u32>,
+    data_locations: Vec<DataLocation>,
+    pointer_locations: Vec<u32>,
+}
+
+/// A member of a union. Plain fields in a union get a group of their own.
+struct Group {
+    parent: usize,
+    data_location_usage: Vec<DataLocationUsage>,
+    pointer_location_usage: usize,
+    has_members: bool,
+}
+
+pub struct Layout {
+    data_word_count: u32,
+    pointer_count: u32,
+    holes: HoleSet,
+    unions: Vec<Union>,
+    groups: Vec<Group>,
+}
+
+impl Layout {
+    pub fn new() -> Layout {
+        Layout {
+            data_word_count: 0,
+            pointer_count: 0,
+            holes: HoleSet::default(),
+            unions: Vec::new(),
+            groups: Vec::new(),
+        }
+    }
+
+    pub fn data_word_count(&self) -> u32 { self.data_word_count }
+
+    pub fn pointer_count(&self) -> u32 { self.pointer_count }
+
+    /// Adds a union whose discriminant and members live in `parent`.
+    pub fn new_union(&mut self, parent: Scope) -> usize {
+        self.unions.push(Union {
+            parent: parent,
+            group_count: 0,
+            discriminant_offset: None,
+            data_locations: Vec::new(),
+            pointer_locations: Vec::new(),
+        });
+        self.unions.len() - 1
+    }
+
+    /// Adds a member to the union `union_index`, returning the scope for its fields.
+    pub fn new_group(&mut self, union_index: usize) -> Scope {
+        self.groups.push(Group {
+            parent: union_index,
+            data_location_usage: Vec::new(),
+            pointer_location_usage: 0,
+            has_members: false,
+        });
+        Scope::Group(self.groups.len() - 1)
+    }
+
+    pub fn discriminant_offset(&self, union_index: usize) -> Option<u32> {
+        self.unions[union_index].discriminant_offset
+    }
+
+    /// Allocates the discriminant of the union, unless that has already happened.
+    /// Returns whether it was allocated by this call.
+    pub fn add_discriminant(&mut self, union_index: usize) -> bool {
+        if self.unions[union_index].discriminant_offset.is_some() {
+            return false
+        }
+        let parent = self.unions[union_index].parent;
+        let offset = self.add_data(parent, 4);
+        self.unions[union_index].discriminant_offset = Some(offset);
+        true
+    }
+
+    pub fn add_void(&mut self, scope: Scope) {
+        if let Scope::Group(g) = scope {
+            self.add_member(g);
+            // The enclosing union may itself be in a union that needs to know about this
+            // member, in order to allocate its discriminant in time.
+            let parent = self.unions[self.groups[g].parent].parent;
+            self.add_void(parent);
+        }
+    }
+
+    pub fn add_data(&mut self, scope: Scope, lg_size: u32) -> u32 {
+        match scope {
+            Scope::Top => {
+                match self.holes.try_allocate(lg_size) {
+                    Some(hole) => hole,
+                    None => {
+                        let offset = self.data_word_count << (6 - lg_size);
+                        self.data_word_count += 1;
+                        self.holes.add_holes_at_end(lg_size, offset + 1, HOLE_COUNT);
+                        offset
+                    }
+                }
+            }
+            Scope::Group(g) => self.group_add_data(g, lg_size),
+        }
+    }
+
+    pub fn add_pointer(&mut self, scope: Scope) -> u32 {
+        match scope {
+            Scope::Top => {
+                self.pointer_count += 1;
+                self.pointer_count - 1
+            }
+            Scope::Group(g) => {
+                self.add_member(g);
+                let u = self.groups[g].parent;
+                let usage = self.groups[g].pointer_location_usage;
+                self.groups[g].pointer_location_usage += 1;
+                if usage < self.unions[u].pointer_locations.len() {
+                    self.unions[u].pointer_locations[usage]
+                } else {
+                    let parent = self.unions[u].parent;
+                    let offset = self.add_pointer(parent);
+                    self.unions[u].pointer_locations.push(offset);
+                    offset
+                }
+            }
+        }
+    }
+
+    fn try_expand_data(&mut self, scope: Scope, old_lg_size: u32, old_offset: u32,
+                       expansion_factor: u32) -> bool {
+        match scope {
+            Scope::Top => self.holes.try_expand(old_lg_size, old_offset, expansion_factor),
+            Scope::Group(g) => {
+                if old_lg_size + expansion_factor > 6 ||
+                    (old_offset & ((1 << expansion_factor) - 1)) != 0
+                {
+                    return false
+                }
+                let u = self.groups[g].parent;
+                for i in 0..self.groups[g].data_location_usage.len() {
+                    let location = self.unions[u].data_locations[i];
+                    if location.lg_size >= old_lg_size &&
+                        old_offset >> (location.lg_size - old_lg_size) == location.offset
+                    {
+                        let local_offset = old_offset - (location.offset << (location.lg_size - old_lg_size));
+                        return self.usage_try_expand(g, i, old_lg_size, local_offset, expansion_factor)
+                    }
+                }
+                false
+            }
+        }
+    }
+
+    fn add_member(&mut self, g: usize) {
+        if !self.groups[g].has_members {
+            self.groups[g].has_members = true;
+            let u = self.groups[g].parent;
+            self.unions[u].group_count += 1;
+            if self.unions[u].group_count == 2 {
+                self.add_discriminant(u);
+            }
+        }
+    }
+
+    fn group_add_data(&mut self, g: usize, lg_size: u32) -> u32 {
+        self.add_member(g);
+        let u = self.groups[g].parent;
+
+        let mut best: Option<(u32, usize)> = None;
+        for i in 0..self.unions[u].data_locations.len() {
+            if self.groups[g].data_location_usage.len() == i {
+                self.groups[g].data_location_usage.push(DataLocationUsage::default());
+            }
+            let location = self.unions[u].data_locations[i];
+            if let Some(size) = self.groups[g].data_location_usage[i].smallest_hole_at_least(location, lg_size) {
+                if best.map_or(true, |(best_size, _)| size < best_size) {
+                    best = Some((size, i));
+                }
+            }
+        }
+        if let Some((_, i)) = best {
+            let location = self.unions[u].data_locations[i];
+            return self.groups[g].data_location_usage[i].allocate_from_hole(location, lg_size)
+        }
+
+        // No holes are big enough, so try to expand one of the existing locations.
+        for i in 0..self.groups[g].data_location_usage.len() {
+            if let Some(result) = self.try_allocate_by_expanding(g, i, lg_size) {
+                return result
+            }
+        }
+
+        // Allocate a new location.
+        self.groups[g].data_location_usage.push(DataLocationUsage {
+            is_used: true,
+            lg_size_used: lg_size,
+            holes: HoleSet::default(),
+        });
+        let parent = self.unions[u].parent;
+        let offset = self.add_data(parent, lg_size);
+        self.unions[u].data_locations.push(DataLocation { lg_size: lg_size, offset: offset });
+        offset
+    }
+
+    fn location_try_expand_to(&mut self, u: usize, i: usize, new_lg_size: u32) -> bool {
+        let location = self.unions[u].data_locations[i];
+        if new_lg_size <= location.lg_size {
+            return true
+        }
+        let parent = self.unions[u].parent;
+        if self.try_expand_data(parent, location.lg_size, location.offset, new_lg_size - location.lg_size) {
+            let location = &mut self.unions[u].data_locations[i];
+            location.offset >>= new_lg_size - location.lg_size;
+            location.lg_size = new_lg_size;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn try_allocate_by_expanding(&mut self, g: usize, i: usize, lg_size: u32) -> Option<u32> {
+        let u = self.groups[g].parent;
+        let usage = self.groups[g].data_location_usage[i];
+        if !usage.is_used {
+            if self.location_try_expand_to(u, i, lg_size) {
+                let location = self.unions[u].data_locations[i];
+                let usage = &mut self.groups[g].data_location_usage[i];
+                usage.is_used = true;
+                usage.lg_size_used = lg_size;
+                Some(location.offset << (location.lg_size - lg_size))
+            } else {
+                None
+            }
+        } else {
+            let new_size = ::std::cmp::max(usage.lg_size_used, lg_size) + 1;
+            if self.usage_try_expand_usage(g, i, new_size, true) {
+                let location = self.unions[u].data_locations[i];
+                let hole = self.groups[g].data_location_usage[i].holes.try_allocate(lg_size)
+                    .expect("expanded location should have a hole");
+                Some((location.offset << (location.lg_size - lg_size)) + hole)
+            } else {
+                None
+            }
+        }
+    }
+
+    fn usage_try_expand(&mut self, g: usize, i: usize, old_lg_size: u32, old_offset: u32,
+                        expansion_factor: u32) -> bool {
+        let usage = self.groups[g].data_location_usage[i];
+        if old_offset == 0 && usage.lg_size_used == old_lg_size {
+            // The location holds exactly this value, so the whole location can grow.
+            self.usage_try_expand_usage(g, i, old_lg_size + expansion_factor, false)
+        } else {
+            // Other values share the location, so the value can only merge with holes.
+            self.groups[g].data_location_usage[i].holes.try_expand(old_lg_size, old_offset, expansion_factor)
+        }
+    }
+
+    fn usage_try_expand_usage(&mut self, g: usize, i: usize, desired_usage: u32, new_holes: bool) -> bool {
+        let u = self.groups[g].parent;
+        if desired_usage > self.unions[u].data_locations[i].lg_size &&
+            !self.location_try_expand_to(u, i, desired_usage)
+        {
+            return false
+        }
+        let usage = &mut self.groups[g].data_location_usage[i];
+        if new_holes {
+            usage.holes.add_holes_at_end(usage.lg_size_used, 1, desired_usage);
+        }
+        usage.lg_size_used = desired_usage;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Layout, Scope};
+
+    #[test]
+    fn packs_small_fields_into_holes() {
+        let mut layout = Layout::new();
+        assert_eq!(layout.add_data(Scope::Top, 5), 0); // 32 bits
+        assert_eq!(layout.add_data(Scope::Top, 0), 32); // 1 bit
+        assert_eq!(layout.add_data(Scope::Top, 3), 5); // 8 bits
+        assert_eq!(layout.add_data(Scope::Top, 6), 1); // 64 bits
+        assert_eq!(layout.add_data(Scope::Top, 4), 3); // 16 bits
+        assert_eq!(layout.add_pointer(Scope::Top), 0);
+        assert_eq!(layout.data_word_count(), 2);
+        assert_eq!(layout.pointer_count(), 1);
+    }
+
+    #[test]
+    fn union_members_share_space() {
+        let mut layout = Layout::new();
+        let u = layout.new_union(Scope::Top);
+        let a = layout.new_group(u);
+        let b = layout.new_group(u);
+        assert_eq!(layout.add_data(a, 5), 0);
+        assert_eq!(layout.add_pointer(a), 0);
+        // The discriminant is allocated when the second member is added.
+        assert_eq!(layout.add_data(b, 6), 1);
+        assert_eq!(layout.discriminant_offset(u), Some(2));
+        assert_eq!(layout.add_pointer(b), 0);
+        assert_eq!(layout.data_word_count(), 2);
+        assert_eq!(layout.pointer_count(), 1);
+    }
+}
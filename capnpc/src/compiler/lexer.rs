@@ -0,0 +1,318 @@
+// Copyright (c) 2013-2018 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Tokenization of schema files.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenKind {
+    Identifier(String),
+    Integer(u64),
+    Float(f64),
+    String(String),
+    Binary(Vec<u8>),
+
+    /// One of `@ : ; , . = ( ) [ ] { } $ - *`.
+    Symbol(char),
+
+    /// `->`
+    Arrow,
+
+    /// The text of a `#` comment, without the `#`, a single following space, and the
+    /// line break.
+    Comment(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub kind: TokenKind,
+
+    /// Byte offset of the token in the file.
+    pub start: usize,
+
+    /// Zero-based line of the token's first character.
+    pub line: usize,
+}
+
+/// An error at the byte offset `.0` of the file.
+pub struct LexError(pub usize, pub String);
+
+const SYMBOLS: &'static str = "@:;,.=()[]{}$-*";
+
+struct Lexer<'a> {
+    text: &'a [u8],
+    pos: usize,
+    line: usize,
+    tokens: Vec<Token>,
+    errors: Vec<LexError>,
+}
+
+impl <'a> Lexer<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).cloned()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.text.get(self.pos + offset).cloned()
+    }
+
+    fn push(&mut self, kind: TokenKind, start: usize, line: usize) {
+        self.tokens.push(Token { kind: kind, start: start, line: line });
+    }
+
+    fn error(&mut self, start: usize, message: String) {
+        self.errors.push(LexError(start, message));
+    }
+
+    fn run(&mut self) {
+        while let Some(c) = self.peek() {
+            let start = self.pos;
+            let line = self.line;
+            match c {
+                b'\n' => {
+                    self.line += 1;
+                    self.pos += 1;
+                }
+                b' ' | b'\t' | b'\r' => {
+                    self.pos += 1;
+                }
+                b'#' => {
+                    self.pos += 1;
+                    if self.peek() == Some(b' ') {
+                        self.pos += 1;
+                    }
+                    let text_start = self.pos;
+                    while let Some(c) = self.peek() {
+                        if c == b'\n' { break }
+                        self.pos += 1;
+                    }
+                    let text = String::from_utf8_lossy(&self.text[text_start..self.pos]);
+                    let text = text.trim_end_matches('\r').to_string();
+                    self.push(TokenKind::Comment(text), start, line);
+                }
+                b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
+                    while let Some(c) = self.peek() {
+                        match c {
+                            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' => self.pos += 1,
+                            _ => break,
+                        }
+                    }
+                    let name = String::from_utf8_lossy(&self.text[start..self.pos]).into_owned();
+                    self.push(TokenKind::Identifier(name), start, line);
+                }
+                b'0'..=b'9' => {
+                    if c == b'0' && (self.peek_at(1) == Some(b'x') || self.peek_at(1) == Some(b'X')) &&
+                        self.peek_at(2) == Some(b'"')
+                    {
+                        self.pos += 2;
+                        self.lex_binary(start, line);
+                    } else {
+                        self.lex_number(start, line);
+                    }
+                }
+                b'"' => self.lex_string(start, line),
+                b'-' if self.peek_at(1) == Some(b'>') => {
+                    self.pos += 2;
+                    self.push(TokenKind::Arrow, start, line);
+                }
+                _ if SYMBOLS.as_bytes().contains(&c) => {
+                    self.pos += 1;
+                    self.push(TokenKind::Symbol(c as char), start, line);
+                }
+                _ => {
+                    self.pos += 1;
+                    self.error(start, format!("Unexpected character {:?}.", c as char));
+                }
+            }
+        }
+    }
+
+    fn lex_number(&mut self, start: usize, line: usize) {
+        let is_hex = self.peek() == Some(b'0') &&
+            (self.peek_at(1) == Some(b'x') || self.peek_at(1) == Some(b'X'));
+        if is_hex {
+            self.pos += 2;
+            let digits_start = self.pos;
+            while let Some(c) = self.peek() {
+                if (c as char).is_digit(16) { self.pos += 1 } else { break }
+            }
+            let digits = String::from_utf8_lossy(&self.text[digits_start..self.pos]).into_owned();
+            match u64::from_str_radix(&digits, 16) {
+                Ok(v) => self.push(TokenKind::Integer(v), start, line),
+                Err(_) => self.error(start, "Invalid hexadecimal integer.".to_string()),
+            }
+            return
+        }
+
+        let mut is_float = false;
+        while let Some(c) = self.peek() {
+            match c {
+                b'0'..=b'9' => self.pos += 1,
+                b'.' if !is_float && self.peek_at(1).map_or(false, |c| (c as char).is_digit(10)) => {
+                    is_float = true;
+                    self.pos += 1;
+                }
+                b'e' | b'E' => {
+                    let sign = match self.peek_at(1) { Some(b'+') | Some(b'-') => 1, _ => 0 };
+                    if self.peek_at(1 + sign).map_or(false, |c| (c as char).is_digit(10)) {
+                        is_float = true;
+                        self.pos += 1 + sign;
+                    } else {
+                        break;
+                    }
+                }
+                _ => break,
+            }
+        }
+        let text = String::from_utf8_lossy(&self.text[start..self.pos]).into_owned();
+        if is_float {
+            match text.parse::<f64>() {
+                Ok(v) => self.push(TokenKind::Float(v), start, line),
+                Err(_) => self.error(start, "Invalid floating-point number.".to_string()),
+            }
+        } else if text.len() > 1 && text.starts_with('0') {
+            match u64::from_str_radix(&text[1..], 8) {
+                Ok(v) => self.push(TokenKind::Integer(v), start, line),
+                Err(_) => self.error(start, "Invalid octal integer.".to_string()),
+            }
+        } else {
+            match text.parse::<u64>() {
+                Ok(v) => self.push(TokenKind::Integer(v), start, line),
+                Err(_) => self.error(start, "Integer is too big.".to_string()),
+            }
+        }
+    }
+
+    fn lex_string(&mut self, start: usize, line: usize) {
+        self.pos += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let c = match self.peek() {
+                None | Some(b'\n') => {
+                    self.error(start, "Unterminated string literal.".to_string());
+                    return
+                }
+                Some(c) => c,
+            };
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escape = match self.peek() {
+                        Some(e) => e,
+                        None => continue,
+                    };
+                    self.pos += 1;
+                    match escape {
+                        b'a' => bytes.push(0x07),
+                        b'b' => bytes.push(0x08),
+                        b'f' => bytes.push(0x0c),
+                        b'n' => bytes.push(b'\n'),
+                        b'r' => bytes.push(b'\r'),
+                        b't' => bytes.push(b'\t'),
+                        b'v' => bytes.push(0x0b),
+                        b'\'' | b'"' | b'\\' | b'?' => bytes.push(escape),
+                        b'x' => {
+                            let mut value = 0u32;
+                            let mut count = 0;
+                            while count < 2 {
+                                match self.peek().and_then(|c| (c as char).to_digit(16)) {
+                                    Some(d) => { value = value * 16 + d; self.pos += 1; count += 1; }
+                                    None => break,
+                                }
+                            }
+                            if count == 0 {
+                                self.error(self.pos - 2, "Invalid \\x escape.".to_string());
+                            }
+                            bytes.push(value as u8);
+                        }
+                        b'0'..=b'7' => {
+                            let mut value = (escape - b'0') as u32;
+                            let mut count = 1;
+                            while count < 3 {
+                                match self.peek().and_then(|c| (c as char).to_digit(8)) {
+                                    Some(d) => { value = value * 8 + d; self.pos += 1; count += 1; }
+                                    None => break,
+                                }
+                            }
+                            bytes.push(value as u8);
+                        }
+                        _ => self.error(self.pos - 2, "Invalid escape sequence.".to_string()),
+                    }
+                }
+                _ => bytes.push(c),
+            }
+        }
+        match String::from_utf8(bytes) {
+            Ok(s) => self.push(TokenKind::String(s), start, line),
+            Err(_) => self.error(start, "String literal is not valid UTF-8.".to_string()),
+        }
+    }
+
+    fn lex_binary(&mut self, start: usize, line: usize) {
+        self.pos += 1;
+        let mut bytes = Vec::new();
+        let mut high: Option<u32> = None;
+        loop {
+            let c = match self.peek() {
+                None => {
+                    self.error(start, "Unterminated data literal.".to_string());
+                    return
+                }
+                Some(c) => c,
+            };
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\n' => self.line += 1,
+                b' ' | b'\t' | b'\r' => (),
+                _ => match (c as char).to_digit(16) {
+                    Some(d) => match high.take() {
+                        Some(h) => bytes.push((h * 16 + d) as u8),
+                        None => high = Some(d),
+                    },
+                    None => self.error(self.pos - 1, "Invalid character in data literal.".to_string()),
+                },
+            }
+        }
+        if high.is_some() {
+            self.error(start, "Data literal has an odd number of hex digits.".to_string());
+        }
+        self.push(TokenKind::Binary(bytes), start, line);
+    }
+}
+
+/// The UTF-8 encoding of U+FEFF.
+pub const BYTE_ORDER_MARK: &str = "\u{feff}";
+
+/// Splits `text` into tokens. Comments are kept as tokens so that the parser can pick out
+/// doc comments.
+pub fn lex(text: &str) -> (Vec<Token>, Vec<LexError>) {
+    let mut lexer = Lexer {
+        text: text.as_bytes(),
+        // A leading byte order mark is skipped rather than lexed.
+        pos: if text.starts_with(BYTE_ORDER_MARK) { BYTE_ORDER_MARK.len() } else { 0 },
+        line: 0,
+        tokens: Vec::new(),
+        errors: Vec::new(),
+    };
+    lexer.run();
+    (lexer.tokens, lexer.errors)
+}
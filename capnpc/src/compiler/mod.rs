@@ -0,0 +1,525 @@
+// Copyright (c) 2013-2018 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! A compiler for the Cap'n Proto schema language, written in Rust.
+//!
+//! It parses `.capnp` files and produces the same `CodeGeneratorRequest` that
+//! `capnp compile -o-` would, so that code can be generated without the `capnp`
+//! executable being installed. Use it through `CompilerCommand::builtin_compiler()`.
+//!
+//! Not supported: `embed` expressions, declarations nested in unions and groups, default
+//! values for interface fields, and values for `AnyPointer` fields or constants other than
+//! the name of a constant of a pointer type.
+
+mod ast;
+mod emit;
+mod ids;
+mod layout;
+mod lexer;
+mod model;
+mod parser;
+mod translate;
+
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use capnp::message;
+use schema_capnp::code_generator_request;
+
+pub use self::ids::generate_child_id;
+
+/// The display name of a file: its path with `src_prefixes` removed, using `/` as the
+/// separator.
+fn display_name(path: &Path, src_prefixes: &[PathBuf]) -> String {
+    let mut result = path;
+    for prefix in src_prefixes {
+        if let Ok(stripped) = path.strip_prefix(prefix) {
+            if stripped.as_os_str().len() < result.as_os_str().len() {
+                result = stripped;
+            }
+        }
+    }
+    normalize(result)
+}
+
+/// Removes `.` components and resolves `..` components where possible.
+fn normalize(path: &Path) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                if parts.last().map_or(false, |p| p != "..") {
+                    parts.pop();
+                } else {
+                    parts.push("..".to_string());
+                }
+            }
+            Component::RootDir => parts.push(String::new()),
+            _ => parts.push(component.as_os_str().to_string_lossy().into_owned()),
+        }
+    }
+    if parts.len() == 1 && parts[0].is_empty() {
+        return "/".to_string()
+    }
+    parts.join("/")
+}
+
+fn import_paths_of_expression(expression: &ast::Expression, result: &mut Vec<(String, ast::Span)>) {
+    match *expression {
+        ast::Expression::Import(ref path, span) => result.push((path.clone(), span)),
+        ast::Expression::Member(ref parent, _) => import_paths_of_expression(parent, result),
+        ast::Expression::Application(ref base, ref args) => {
+            import_paths_of_expression(base, result);
+            for arg in args {
+                import_paths_of_expression(arg, result);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn import_paths_of_param_list(list: &ast::ParamList, result: &mut Vec<(String, ast::Span)>) {
+    match *list {
+        ast::ParamList::Named(ref params, _) => {
+            for param in params {
+                import_paths_of_expression(&param.type_, result);
+            }
+        }
+        ast::ParamList::Type(ref expression) => import_paths_of_expression(expression, result),
+    }
+}
+
+/// Collects the paths of all `import` expressions in `declarations`.
+fn import_paths(declarations: &[::std::rc::Rc<ast::Declaration>], result: &mut Vec<(String, ast::Span)>) {
+    for declaration in declarations {
+        match declaration.kind {
+            ast::DeclarationKind::Using(ref target) => import_paths_of_expression(target, result),
+            ast::DeclarationKind::Const { ref type_, .. } |
+            ast::DeclarationKind::Annotation { ref type_, .. } |
+            ast::DeclarationKind::Field { ref type_, .. } => import_paths_of_expression(type_, result),
+            ast::DeclarationKind::Interface { ref superclasses, .. } => {
+                for superclass in superclasses {
+                    import_paths_of_expression(superclass, result);
+                }
+            }
+            ast::DeclarationKind::Method { ref params, ref results, .. } => {
+                import_paths_of_param_list(params, result);
+                if let Some(ref results) = *results {
+                    import_paths_of_param_list(results, result);
+                }
+            }
+            _ => (),
+        }
+        import_paths(declaration.nested(), result);
+    }
+}
+
+struct Loader {
+    import_paths: Vec<PathBuf>,
+    files: Vec<translate::SourceFile>,
+    texts: Vec<String>,
+    by_path: HashMap<PathBuf, usize>,
+    errors: Vec<String>,
+}
+
+impl Loader {
+    fn new(import_paths: Vec<PathBuf>) -> Loader {
+        Loader {
+            import_paths: import_paths,
+            files: Vec::new(),
+            texts: Vec::new(),
+            by_path: HashMap::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, file: usize, offset: usize, message: &str) {
+        let text = &self.texts[file];
+        let offset = ::std::cmp::min(offset, text.len());
+        let before = &text.as_bytes()[..offset];
+        let line = before.iter().filter(|&&c| c == b'\n').count() + 1;
+        let first_line_start = if text.starts_with(lexer::BYTE_ORDER_MARK) { lexer::BYTE_ORDER_MARK.len() } else { 0 };
+        let line_start = before.iter().rposition(|&c| c == b'\n').map_or(first_line_start, |i| i + 1);
+        let column = offset.saturating_sub(line_start) + 1;
+        let message = format!("{}:{}:{}: error: {}", self.files[file].display_name, line, column, message);
+        if !self.errors.contains(&message) {
+            self.errors.push(message);
+        }
+    }
+
+    /// Loads the file at `path`, along with everything it imports.
+    fn load(&mut self, path: &Path, display_name: String) -> ::std::io::Result<usize> {
+        let canonical = ::std::fs::canonicalize(path)?;
+        if let Some(&index) = self.by_path.get(&canonical) {
+            return Ok(index)
+        }
+        let text = {
+            use std::io::Read;
+            let mut text = String::new();
+            ::std::fs::File::open(path)?.read_to_string(&mut text)?;
+            text
+        };
+        let index = self.add_source(display_name, text);
+        self.by_path.insert(canonical, index);
+        self.load_imports(index, Some(path));
+        Ok(index)
+    }
+
+    /// Parses `text` as a file named `display_name`, without loading its imports.
+    fn add_source(&mut self, display_name: String, text: String) -> usize {
+        let index = self.files.len();
+        let (ast, errors) = parser::parse(index, &text);
+        self.files.push(translate::SourceFile {
+            display_name: display_name,
+            ast: ast,
+            imports: HashMap::new(),
+        });
+        self.texts.push(text);
+        for (offset, message) in errors {
+            self.error(index, offset, &message);
+        }
+        index
+    }
+
+    fn load_imports(&mut self, index: usize, path: Option<&Path>) {
+        let mut imports = Vec::new();
+        import_paths(&self.files[index].ast.declarations, &mut imports);
+        for (import, span) in imports {
+            if self.files[index].imports.contains_key(&import) {
+                continue
+            }
+            let mut candidates = Vec::new();
+            if import.starts_with('/') {
+                for dir in &self.import_paths {
+                    candidates.push((dir.join(&import[1..]), import[1..].to_string()));
+                }
+            } else if let Some(path) = path {
+                let dir = path.parent().unwrap_or(Path::new(""));
+                let display_dir = Path::new(&self.files[index].display_name).parent()
+                    .unwrap_or(Path::new("")).to_path_buf();
+                candidates.push((dir.join(&import), normalize(&display_dir.join(&import))));
+            }
+            let mut loaded = None;
+            for (candidate, display_name) in candidates {
+                if candidate.is_file() {
+                    match self.load(&candidate, display_name) {
+                        Ok(i) => loaded = Some(i),
+                        Err(e) => self.error(index, span.offset,
+                                             &format!("Could not read {}: {}", candidate.display(), e)),
+                    }
+                    break
+                }
+            }
+            match loaded {
+                Some(i) => { self.files[index].imports.insert(import, i); }
+                None => self.error(index, span.offset, &format!("Import failed: {}", import)),
+            }
+        }
+    }
+
+    /// Translates the loaded files and writes the result as a `CodeGeneratorRequest`
+    /// for `requested`.
+    fn compile(mut self, requested: &[usize])
+               -> ::capnp::Result<message::Builder<message::HeapAllocator>>
+    {
+        let translation = match translate::translate(&self.files) {
+            Ok(translation) => Some(translation),
+            Err(errors) => {
+                for error in errors {
+                    self.error(error.span.file, error.span.offset, &error.message);
+                }
+                None
+            }
+        };
+        if !self.errors.is_empty() {
+            return Err(::capnp::Error::failed(self.errors.join("\n")))
+        }
+        let translation = translation.expect("translation");
+
+        let mut message = message::Builder::new_default();
+        {
+            let mut request = message.init_root::<code_generator_request::Builder>();
+            let emitter = emit::Emitter::new(&translation.nodes);
+            {
+                let mut nodes = request.reborrow().init_nodes(translation.nodes.len() as u32);
+                for (i, node) in translation.nodes.iter().enumerate() {
+                    emitter.node(node, nodes.reborrow().get(i as u32));
+                }
+            }
+            {
+                let documented: Vec<&model::Node> = translation.nodes.iter().filter(|n| {
+                    n.doc_comment.is_some() || n.member_doc_comments.iter().any(|d| d.is_some())
+                }).collect();
+                let mut source_info = request.reborrow().init_source_info(documented.len() as u32);
+                for (i, node) in documented.iter().enumerate() {
+                    let mut info = source_info.reborrow().get(i as u32);
+                    info.set_id(node.id);
+                    if let Some(ref doc) = node.doc_comment {
+                        info.set_doc_comment(doc);
+                    }
+                    let mut members = info.init_members(node.member_doc_comments.len() as u32);
+                    for (j, doc) in node.member_doc_comments.iter().enumerate() {
+                        if let Some(ref doc) = *doc {
+                            members.reborrow().get(j as u32).set_doc_comment(doc);
+                        }
+                    }
+                }
+            }
+            let mut requested_files = request.init_requested_files(requested.len() as u32);
+            for (i, &file) in requested.iter().enumerate() {
+                let mut b = requested_files.reborrow().get(i as u32);
+                b.set_id(translation.file_ids[file]);
+                b.set_filename(&self.files[file].display_name);
+                let mut imports: Vec<(&String, &usize)> = self.files[file].imports.iter().collect();
+                imports.sort();
+                let mut list = b.init_imports(imports.len() as u32);
+                for (j, (name, &imported)) in imports.into_iter().enumerate() {
+                    let mut import = list.reborrow().get(j as u32);
+                    import.set_id(translation.file_ids[imported]);
+                    import.set_name(name);
+                }
+            }
+        }
+        Ok(message)
+    }
+}
+
+/// Compiles the schema `files`, returning a message whose root is a `CodeGeneratorRequest`
+/// for them. `src_prefixes`, `import_paths` and `no_standard_import` have the same meaning
+/// as the corresponding options of `capnp compile`. All errors found are reported together,
+/// one per line, in the error's description.
+pub fn compile(files: &[PathBuf], src_prefixes: &[PathBuf], import_paths: &[PathBuf],
+               no_standard_import: bool)
+               -> ::capnp::Result<message::Builder<message::HeapAllocator>>
+{
+    let mut all_import_paths = import_paths.to_vec();
+    if !no_standard_import {
+        all_import_paths.push(PathBuf::from("/usr/local/include"));
+        all_import_paths.push(PathBuf::from("/usr/include"));
+    }
+    let mut loader = Loader::new(all_import_paths);
+    let mut requested = Vec::new();
+    for file in files {
+        match loader.load(file, display_name(file, src_prefixes)) {
+            Ok(index) => {
+                if !requested.contains(&index) {
+                    requested.push(index);
+                }
+            }
+            Err(e) => loader.errors.push(format!("{}: error: {}", file.display(), e)),
+        }
+    }
+    loader.compile(&requested)
+}
+
+/// Compiles a single schema file whose contents are `text`, as `compile()` does. The file is
+/// called `display_name` in the request and in errors, and cannot import other files.
+pub fn compile_source(display_name: &str, text: &str)
+                      -> ::capnp::Result<message::Builder<message::HeapAllocator>>
+{
+    let mut loader = Loader::new(Vec::new());
+    let index = loader.add_source(display_name.to_string(), text.to_string());
+    loader.compile(&[index])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Loader;
+    use capnp::message;
+    use schema_capnp::{code_generator_request, node, field};
+
+    fn compile(text: &str) -> ::capnp::Result<message::Builder<message::HeapAllocator>> {
+        let mut loader = Loader::new(Vec::new());
+        loader.add_source("test.capnp".to_string(), text.to_string());
+        loader.compile(&[0])
+    }
+
+    fn find<'a>(request: code_generator_request::Reader<'a>, id: u64) -> node::Reader<'a> {
+        request.get_nodes().unwrap().iter().find(|n| n.get_id() == id).expect("node")
+    }
+
+    fn slot_offset(node: node::Reader, name: &str) -> u32 {
+        let fields = match node.which().unwrap() {
+            node::Struct(s) => s.get_fields().unwrap(),
+            _ => panic!("not a struct"),
+        };
+        let field = fields.iter().find(|f| f.get_name().unwrap() == name).expect("field");
+        match field.which().unwrap() {
+            field::Slot(slot) => slot.get_offset(),
+            field::Group(_) => panic!("not a slot"),
+        }
+    }
+
+    #[test]
+    fn layout_and_ids_match_schema_capnp() {
+        // `Node` from schema.capnp, which has unions, groups and out-of-order ordinals.
+        let message = compile(r#"
+@0xa93fc509624c72d9;
+using Id = UInt64;
+struct Node {
+  id @0 :Id;
+  displayName @1 :Text;
+  displayNamePrefixLength @2 :UInt32;
+  scopeId @3 :Id;
+  parameters @32 :List(Parameter);
+  isGeneric @33 :Bool;
+  struct Parameter { name @0 :Text; }
+  nestedNodes @4 :List(NestedNode);
+  struct NestedNode { name @0 :Text; id @1 :Id; }
+  annotations @5 :List(Void);
+  union {
+    file @6 :Void;
+    struct :group {
+      dataWordCount @7 :UInt16;
+      pointerCount @8 :UInt16;
+      preferredListEncoding @9 :UInt16;
+      isGroup @10 :Bool;
+      discriminantCount @11 :UInt16;
+      discriminantOffset @12 :UInt32;
+      fields @13 :List(Void);
+    }
+    enum :group { enumerants @14 :List(Void); }
+    interface :group {
+      methods @15 :List(Void);
+      superclasses @31 :List(Void);
+    }
+    const :group { type @16 :Void; value @17 :Void; }
+    annotation :group {
+      type @18 :Void;
+      targetsFile @19 :Bool;
+      targetsConst @20 :Bool;
+      targetsEnum @21 :Bool;
+      targetsEnumerant @22 :Bool;
+      targetsStruct @23 :Bool;
+      targetsField @24 :Bool;
+      targetsUnion @25 :Bool;
+      targetsGroup @26 :Bool;
+      targetsInterface @27 :Bool;
+      targetsMethod @28 :Bool;
+      targetsParam @29 :Bool;
+      targetsAnnotation @30 :Bool;
+    }
+  }
+}
+"#).unwrap();
+        let request = message.get_root_as_reader::<code_generator_request::Reader>().unwrap();
+        let node = find(request, 0xe682ab4cf923a417);
+        match node.which().unwrap() {
+            node::Struct(s) => {
+                assert_eq!(s.get_data_word_count(), 5);
+                assert_eq!(s.get_pointer_count(), 6);
+                assert_eq!(s.get_discriminant_count(), 6);
+                assert_eq!(s.get_discriminant_offset(), 6);
+            }
+            _ => panic!("not a struct"),
+        }
+        assert_eq!(slot_offset(node, "isGeneric"), 288);
+        assert_eq!(slot_offset(node, "parameters"), 5);
+
+        let struct_group = find(request, 0x9ea0b19b37fb4435);
+        assert_eq!(struct_group.get_display_name().unwrap(), "test.capnp:Node.struct");
+        assert_eq!(slot_offset(struct_group, "dataWordCount"), 7);
+        assert_eq!(slot_offset(struct_group, "isGroup"), 224);
+        assert_eq!(slot_offset(struct_group, "discriminantOffset"), 8);
+
+        assert_eq!(slot_offset(find(request, 0xb54ab3364333f598), "enumerants"), 3);
+        let interface_group = find(request, 0xe82753cff0c2218f);
+        assert_eq!(slot_offset(interface_group, "methods"), 3);
+        assert_eq!(slot_offset(interface_group, "superclasses"), 4);
+        let annotation_group = find(request, 0xec1619d4400a0290);
+        assert_eq!(slot_offset(annotation_group, "targetsAnnotation"), 123);
+    }
+
+    #[test]
+    fn reports_errors_with_positions() {
+        let error = compile("@0xa93fc509624c72d9;\nstruct Foo {\n  a @0 :Text;\n  b @0 :Bar;\n}\n")
+            .err().expect("error");
+        assert!(error.description.contains("test.capnp:4:9: error: Not defined: Bar"), "{}", error.description);
+        assert!(error.description.contains("test.capnp:4:5: error: Duplicate ordinal number."),
+                "{}", error.description);
+    }
+
+    fn compile_error(text: &str) -> String {
+        compile(text).err().expect("error").description
+    }
+
+    #[test]
+    fn skips_a_leading_byte_order_mark() {
+        compile("\u{feff}@0xa93fc509624c72d9;\nstruct Foo {}\n").unwrap();
+        let error = compile_error("\u{feff}@0xa93fc509624c72d9; struct Foo { a @0 :Bar; }\n");
+        assert!(error.contains("test.capnp:1:41: error: Not defined: Bar"), "{}", error);
+    }
+
+    #[test]
+    fn reports_duplicate_names() {
+        let error = compile_error("@0xa93fc509624c72d9;\nstruct S { a @0 :Int32 $ann; }\nstruct S {}\n\
+                                   annotation ann(field) :Void;\n");
+        assert!(error.contains("test.capnp:3:8: error: 'S' is already defined in this scope."), "{}", error);
+
+        let error = compile_error("@0xa93fc509624c72d9;\nstruct S { a @0 :Int32; a @1 :Text; }\n");
+        assert!(error.contains("test.capnp:2:25: error: 'a' is already defined in this scope."), "{}", error);
+
+        let error = compile_error("@0xa93fc509624c72d9;\nenum E { a @0; a @1; }\n");
+        assert!(error.contains("test.capnp:2:16: error: 'a' is already defined in this scope."), "{}", error);
+
+        let error = compile_error("@0xa93fc509624c72d9;\nstruct S @0xb6b3b9d4c3ec5a52 { a @0 :Int32 $ann; }\n\
+                                   struct T @0xb6b3b9d4c3ec5a52 {}\nannotation ann(field) :Void;\n");
+        assert!(error.contains("test.capnp:3:10: error: Duplicate ID @0xb6b3b9d4c3ec5a52."), "{}", error);
+    }
+
+    #[test]
+    fn reports_bad_ordinals() {
+        let error = compile_error("@0xa93fc509624c72d9;\nstruct S { a @0 :Int32; b @2 :Int32; }\n");
+        assert!(error.contains("test.capnp:2:27: error: Skipped ordinal @1."), "{}", error);
+
+        let error = compile_error("@0xa93fc509624c72d9;\nstruct S { a @1 :Int32; }\n");
+        assert!(error.contains("test.capnp:2:14: error: Skipped ordinal @0."), "{}", error);
+
+        let error = compile_error("@0xa93fc509624c72d9;\nstruct S { a @65536 :Int32; }\n");
+        assert!(error.contains("test.capnp:2:14: error: Ordinal is too large."), "{}", error);
+    }
+
+    #[test]
+    fn reports_unknown_names() {
+        let error = compile_error("@0xa93fc509624c72d9;\nstruct S { a @0 :Foo.Bar; }\n");
+        assert!(error.contains("test.capnp:2:18: error: Not defined: Foo"), "{}", error);
+
+        let error = compile_error("@0xa93fc509624c72d9;\nstruct S { a @0 :S.Bar; }\n");
+        assert!(error.contains("test.capnp:2:20: error: 'S' has no member named 'Bar'."), "{}", error);
+
+        let error = compile_error("@0xa93fc509624c72d9;\nconst c :Int32 = d;\n");
+        assert!(error.contains("test.capnp:2:18: error: Not defined: d"), "{}", error);
+    }
+
+    #[test]
+    fn reports_bad_imports() {
+        let mut loader = Loader::new(Vec::new());
+        let index = loader.add_source(
+            "test.capnp".to_string(),
+            "@0xa93fc509624c72d9;\nusing M = import \"missing.capnp\";\n\
+             using N = import \"/absolute.capnp\";\n".to_string());
+        loader.load_imports(index, Some(::std::path::Path::new("test.capnp")));
+        let error = loader.compile(&[index]).err().expect("error").description;
+        assert!(error.contains("test.capnp:2:11: error: Import failed: missing.capnp"), "{}", error);
+        assert!(error.contains("test.capnp:3:11: error: Import failed: /absolute.capnp"), "{}", error);
+    }
+}
@@ -0,0 +1,284 @@
+// Copyright (c) 2013-2018 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Compiled schema nodes, mirroring the structure of `schema.capnp`. They are written out
+//! as a `CodeGeneratorRequest` once compilation has finished.
+
+pub const NO_DISCRIMINANT: u16 = 0xffff;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AnyPointerKind {
+    AnyKind,
+    Struct,
+    List,
+    Capability,
+    Parameter { scope_id: u64, index: u16 },
+    ImplicitMethodParameter(u16),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Void,
+    Bool,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Uint8,
+    Uint16,
+    Uint32,
+    Uint64,
+    Float32,
+    Float64,
+    Text,
+    Data,
+    List(Box<Type>),
+    Enum(u64, Brand),
+    Struct(u64, Brand),
+    Interface(u64, Brand),
+    AnyPointer(AnyPointerKind),
+}
+
+/// Where a value of a type lives in a struct.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Placement {
+    /// Void takes no space.
+    None,
+
+    /// In the data section, with the given base-2 logarithm of its size in bits.
+    Data(u32),
+
+    Pointer,
+}
+
+impl Type {
+    pub fn placement(&self) -> Placement {
+        match *self {
+            Type::Void => Placement::None,
+            Type::Bool => Placement::Data(0),
+            Type::Int8 | Type::Uint8 => Placement::Data(3),
+            Type::Int16 | Type::Uint16 | Type::Enum(..) => Placement::Data(4),
+            Type::Int32 | Type::Uint32 | Type::Float32 => Placement::Data(5),
+            Type::Int64 | Type::Uint64 | Type::Float64 => Placement::Data(6),
+            Type::Text | Type::Data | Type::List(_) | Type::Struct(..) |
+            Type::Interface(..) | Type::AnyPointer(_) => Placement::Pointer,
+        }
+    }
+
+    /// Returns whether a value of type `other` can be used where this type is expected,
+    /// ignoring generic parameters.
+    pub fn accepts(&self, other: &Type) -> bool {
+        match (self, other) {
+            (&Type::List(ref a), &Type::List(ref b)) => a.accepts(b),
+            (&Type::AnyPointer(AnyPointerKind::AnyKind), _) => other.placement() == Placement::Pointer,
+            (&Type::AnyPointer(AnyPointerKind::Struct), &Type::Struct(..)) |
+            (&Type::AnyPointer(AnyPointerKind::List), &Type::List(_)) |
+            (&Type::AnyPointer(AnyPointerKind::List), &Type::Text) |
+            (&Type::AnyPointer(AnyPointerKind::List), &Type::Data) |
+            (&Type::AnyPointer(AnyPointerKind::Capability), &Type::Interface(..)) => true,
+            (&Type::Enum(a, _), &Type::Enum(b, _)) |
+            (&Type::Struct(a, _), &Type::Struct(b, _)) |
+            (&Type::Interface(a, _), &Type::Interface(b, _)) => a == b,
+            (&Type::AnyPointer(_), &Type::AnyPointer(_)) => true,
+            _ => self == other,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Binding {
+    Unbound,
+    Type(Type),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BrandScope {
+    /// The parameters of the scope are those of the scope in which the brand occurs.
+    Inherit(u64),
+    Bind(u64, Vec<Binding>),
+}
+
+impl BrandScope {
+    pub fn scope_id(&self) -> u64 {
+        match *self {
+            BrandScope::Inherit(id) | BrandScope::Bind(id, _) => id,
+        }
+    }
+}
+
+/// The generic parameters bound for a type. Scopes are listed innermost first.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Brand {
+    pub scopes: Vec<BrandScope>,
+}
+
+/// A value of a known type.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Void,
+    Bool(bool),
+    Int8(i8),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+    Uint8(u8),
+    Uint16(u16),
+    Uint32(u32),
+    Uint64(u64),
+    Float32(f32),
+    Float64(f64),
+    Text(Option<String>),
+    Data(Option<Vec<u8>>),
+
+    /// A list with elements of the given type, or a null list.
+    List(Type, Option<Vec<Value>>),
+    Enum(u16),
+
+    /// A struct value, as the ID of the struct type and its assigned fields (by index in the
+    /// struct's field list). Groups are themselves assigned struct values. `None` is a null
+    /// struct.
+    Struct(u64, Option<Vec<(usize, Value)>>),
+    Interface,
+
+    /// The value of an `AnyPointer`, which can only be given by naming a constant of a
+    /// pointer type. `None` is a null pointer.
+    AnyPointer(Option<Box<Value>>),
+}
+
+impl Value {
+    /// The value of a field of type `type_` that has no explicit default.
+    pub fn default_for(type_: &Type) -> Value {
+        match *type_ {
+            Type::Void => Value::Void,
+            Type::Bool => Value::Bool(false),
+            Type::Int8 => Value::Int8(0),
+            Type::Int16 => Value::Int16(0),
+            Type::Int32 => Value::Int32(0),
+            Type::Int64 => Value::Int64(0),
+            Type::Uint8 => Value::Uint8(0),
+            Type::Uint16 => Value::Uint16(0),
+            Type::Uint32 => Value::Uint32(0),
+            Type::Uint64 => Value::Uint64(0),
+            Type::Float32 => Value::Float32(0.0),
+            Type::Float64 => Value::Float64(0.0),
+            Type::Text => Value::Text(None),
+            Type::Data => Value::Data(None),
+            Type::List(ref element) => Value::List((**element).clone(), None),
+            Type::Enum(..) => Value::Enum(0),
+            Type::Struct(id, _) => Value::Struct(id, None),
+            Type::Interface(..) => Value::Interface,
+            Type::AnyPointer(_) => Value::AnyPointer(None),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Annotation {
+    pub id: u64,
+    pub brand: Brand,
+    pub value: Value,
+}
+
+#[derive(Clone, Debug)]
+pub enum FieldKind {
+    Slot { offset: u32, type_: Type, default_value: Value, had_explicit_default: bool },
+    Group(u64),
+}
+
+#[derive(Clone, Debug)]
+pub struct Field {
+    pub name: String,
+    pub code_order: u16,
+    pub annotations: Vec<Annotation>,
+    pub discriminant_value: u16,
+    pub kind: FieldKind,
+    pub ordinal: Option<u16>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Struct {
+    pub data_word_count: u16,
+    pub pointer_count: u16,
+    pub is_group: bool,
+    pub discriminant_count: u16,
+    pub discriminant_offset: u32,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Enumerant {
+    pub name: String,
+    pub code_order: u16,
+    pub annotations: Vec<Annotation>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Method {
+    pub name: String,
+    pub code_order: u16,
+    pub implicit_parameters: Vec<String>,
+    pub param_struct_type: u64,
+    pub param_brand: Brand,
+    pub result_struct_type: u64,
+    pub result_brand: Brand,
+    pub annotations: Vec<Annotation>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Superclass {
+    pub id: u64,
+    pub brand: Brand,
+}
+
+/// The kinds of declarations that an annotation may be applied to.
+pub const ANNOTATION_TARGETS: [&'static str; 12] = [
+    "file", "const", "enum", "enumerant", "struct", "field", "union", "group",
+    "interface", "method", "param", "annotation",
+];
+
+#[derive(Clone, Debug)]
+pub enum Body {
+    File,
+    Struct(Struct),
+    Enum(Vec<Enumerant>),
+    Interface { methods: Vec<Method>, superclasses: Vec<Superclass> },
+    Const { type_: Type, value: Value },
+
+    /// An annotation declaration. `targets` is indexed like `ANNOTATION_TARGETS`.
+    Annotation { type_: Type, targets: [bool; 12] },
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub id: u64,
+    pub display_name: String,
+    pub display_name_prefix_length: u32,
+    pub scope_id: u64,
+    pub nested_nodes: Vec<(String, u64)>,
+    pub annotations: Vec<Annotation>,
+    pub parameters: Vec<String>,
+    pub is_generic: bool,
+    pub body: Body,
+    pub doc_comment: Option<String>,
+
+    /// Doc comments of the fields, enumerants or methods, in the same order.
+    pub member_doc_comments: Vec<Option<String>>,
+}
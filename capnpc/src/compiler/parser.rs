@@ -0,0 +1,715 @@
+// Copyright (c) 2013-2018 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Parsing of the token stream of a schema file into a syntax tree.
+
+use std::rc::Rc;
+
+use compiler::ast::*;
+use compiler::lexer::{lex, Token, TokenKind};
+
+/// A syntax error at the byte offset `.0` of the file.
+pub struct ParseError(pub usize, pub String);
+
+type Result<T> = ::std::result::Result<T, ParseError>;
+
+/// The kind of block whose body is being parsed, which determines how its members are
+/// interpreted.
+#[derive(Clone, Copy, PartialEq)]
+enum Context {
+    File,
+    Struct,
+    Enum,
+    Interface,
+}
+
+struct Parser {
+    file: usize,
+    tokens: Vec<Token>,
+    pos: usize,
+    end_offset: usize,
+    errors: Vec<ParseError>,
+}
+
+impl Parser {
+    fn skip_comments(&mut self) {
+        while let Some(&Token { kind: TokenKind::Comment(_), .. }) = self.tokens.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<&TokenKind> {
+        self.skip_comments();
+        self.tokens.get(self.pos).map(|t| &t.kind)
+    }
+
+    fn peek_second(&mut self) -> Option<&TokenKind> {
+        self.skip_comments();
+        self.tokens[self.pos..].iter()
+            .filter(|t| match t.kind { TokenKind::Comment(_) => false, _ => true })
+            .nth(1).map(|t| &t.kind)
+    }
+
+    fn offset(&mut self) -> usize {
+        self.skip_comments();
+        match self.tokens.get(self.pos) {
+            Some(t) => t.start,
+            None => self.end_offset,
+        }
+    }
+
+    fn span(&mut self) -> Span {
+        Span { file: self.file, offset: self.offset() }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        self.skip_comments();
+        let result = self.tokens.get(self.pos).cloned();
+        if result.is_some() {
+            self.pos += 1;
+        }
+        result
+    }
+
+    fn error<T>(&mut self, message: &str) -> Result<T> {
+        let offset = self.offset();
+        Err(ParseError(offset, message.to_string()))
+    }
+
+    fn is_symbol(&mut self, c: char) -> bool {
+        self.peek() == Some(&TokenKind::Symbol(c))
+    }
+
+    fn is_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(&TokenKind::Identifier(ref name)) => name == keyword,
+            _ => false,
+        }
+    }
+
+    fn eat_symbol(&mut self, c: char) -> bool {
+        if self.is_symbol(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consumes the symbol `c`, returning the line it is on.
+    fn expect_symbol(&mut self, c: char) -> Result<usize> {
+        if self.is_symbol(c) {
+            Ok(self.next().unwrap().line)
+        } else {
+            self.error(&format!("Expected \"{}\".", c))
+        }
+    }
+
+    fn expect_name(&mut self) -> Result<Name> {
+        let span = self.span();
+        match self.peek() {
+            Some(&TokenKind::Identifier(_)) => (),
+            _ => return self.error("Expected identifier."),
+        }
+        match self.next() {
+            Some(Token { kind: TokenKind::Identifier(value), .. }) => Ok(Name { value: value, span: span }),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Collects the comment lines that directly follow a token on line `line`: either on
+    /// the same line or starting on the next one, and continuing on consecutive lines.
+    fn doc_comment(&mut self, line: usize) -> Option<String> {
+        let mut result = String::new();
+        let mut expected_line = line;
+        let mut first = true;
+        while let Some(token) = self.tokens.get(self.pos) {
+            match token.kind {
+                TokenKind::Comment(ref text) if (first && token.line == expected_line) ||
+                    token.line == expected_line + 1 =>
+                {
+                    result.push_str(text);
+                    result.push('\n');
+                    expected_line = token.line;
+                    first = false;
+                }
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        if result.is_empty() { None } else { Some(result) }
+    }
+
+    /// Skips to the end of the current statement after a syntax error.
+    fn recover(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.next() {
+            match token.kind {
+                TokenKind::Symbol('{') => depth += 1,
+                TokenKind::Symbol('}') => {
+                    if depth == 0 {
+                        self.pos -= 1;
+                        return
+                    }
+                    depth -= 1;
+                    if depth == 0 {
+                        return
+                    }
+                }
+                TokenKind::Symbol(';') if depth == 0 => return,
+                _ => (),
+            }
+        }
+    }
+
+    fn parse_file(&mut self) -> File {
+        let mut file = File {
+            id: Id::Unspecified,
+            annotations: Vec::new(),
+            declarations: Vec::new(),
+        };
+        while self.peek().is_some() {
+            let result = if self.is_symbol('@') {
+                self.parse_id().and_then(|id| {
+                    file.id = id;
+                    self.expect_symbol(';').map(|_| ())
+                })
+            } else if self.is_symbol('$') {
+                self.parse_annotation().and_then(|annotation| {
+                    file.annotations.push(annotation);
+                    self.expect_symbol(';').map(|_| ())
+                })
+            } else {
+                self.parse_declaration(Context::File).map(|decl| file.declarations.push(Rc::new(decl)))
+            };
+            if let Err(e) = result {
+                self.errors.push(e);
+                self.recover();
+                if self.is_symbol('}') {
+                    self.pos += 1;
+                }
+            }
+        }
+        file
+    }
+
+    /// Parses declarations up to and including the closing `}` of a block.
+    fn parse_block(&mut self, context: Context) -> Result<Vec<Rc<Declaration>>> {
+        let mut result = Vec::new();
+        loop {
+            match self.peek() {
+                None => return self.error("Expected \"}\"."),
+                Some(&TokenKind::Symbol('}')) => {
+                    self.pos += 1;
+                    return Ok(result)
+                }
+                _ => (),
+            }
+            match self.parse_declaration(context) {
+                Ok(decl) => result.push(Rc::new(decl)),
+                Err(e) => {
+                    self.errors.push(e);
+                    self.recover();
+                }
+            }
+        }
+    }
+
+    fn parse_id(&mut self) -> Result<Id> {
+        let span = self.span();
+        self.expect_symbol('@')?;
+        match self.next() {
+            Some(Token { kind: TokenKind::Integer(value), .. }) => {
+                if value >= 1 << 32 {
+                    Ok(Id::Uid(value, span))
+                } else if value <= 0xffff {
+                    Ok(Id::Ordinal(value as u16, span))
+                } else {
+                    Err(ParseError(span.offset, "Ordinal is too large.".to_string()))
+                }
+            }
+            _ => Err(ParseError(span.offset, "Expected integer after \"@\".".to_string())),
+        }
+    }
+
+    fn parse_optional_id(&mut self) -> Result<Id> {
+        if self.is_symbol('@') { self.parse_id() } else { Ok(Id::Unspecified) }
+    }
+
+    fn parse_annotations(&mut self) -> Result<Vec<AnnotationApplication>> {
+        let mut result = Vec::new();
+        while self.is_symbol('$') {
+            result.push(self.parse_annotation()?);
+        }
+        Ok(result)
+    }
+
+    fn parse_annotation(&mut self) -> Result<AnnotationApplication> {
+        self.expect_symbol('$')?;
+        let name = self.parse_name_expression()?;
+        let value = if self.is_symbol('(') {
+            let span = self.span();
+            self.pos += 1;
+            let is_struct = self.is_symbol(')') || match (self.peek().cloned(), self.peek_second()) {
+                (Some(TokenKind::Identifier(_)), Some(&TokenKind::Symbol('='))) => true,
+                _ => false,
+            };
+            if is_struct {
+                let fields = self.parse_struct_value_fields()?;
+                Some(Value { kind: ValueKind::Struct(fields), span: span })
+            } else {
+                let value = self.parse_value()?;
+                self.expect_symbol(')')?;
+                Some(value)
+            }
+        } else {
+            None
+        };
+        Ok(AnnotationApplication { name: name, value: value })
+    }
+
+    /// Returns whether the parenthesized group starting at the current token is followed by
+    /// a `.`, i.e. whether it holds generic parameters rather than an annotation value.
+    fn parens_followed_by_member(&mut self) -> bool {
+        self.skip_comments();
+        let mut depth = 0;
+        for (i, token) in self.tokens[self.pos..].iter().enumerate() {
+            match token.kind {
+                TokenKind::Symbol('(') | TokenKind::Symbol('[') | TokenKind::Symbol('{') => depth += 1,
+                TokenKind::Symbol(')') | TokenKind::Symbol(']') | TokenKind::Symbol('}') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return self.tokens[self.pos + i + 1..].iter()
+                            .find(|t| match t.kind { TokenKind::Comment(_) => false, _ => true })
+                            .map(|t| t.kind == TokenKind::Symbol('.')) == Some(true);
+                    }
+                }
+                _ => (),
+            }
+        }
+        false
+    }
+
+    /// Parses the name of an annotation, like `.Foo.bar` or `Foo(Text).bar`. A trailing
+    /// parenthesized group is left for the annotation value.
+    fn parse_name_expression(&mut self) -> Result<Expression> {
+        let mut result = if self.eat_symbol('.') {
+            Expression::Absolute(self.expect_name()?)
+        } else {
+            Expression::Relative(self.expect_name()?)
+        };
+        loop {
+            if self.eat_symbol('.') {
+                result = Expression::Member(Box::new(result), self.expect_name()?);
+            } else if self.is_symbol('(') && self.parens_followed_by_member() {
+                self.pos += 1;
+                let mut args = Vec::new();
+                loop {
+                    args.push(self.parse_expression()?);
+                    if self.eat_symbol(')') { break }
+                    self.expect_symbol(',')?;
+                }
+                result = Expression::Application(Box::new(result), args);
+            } else {
+                return Ok(result)
+            }
+        }
+    }
+
+    fn parse_expression(&mut self) -> Result<Expression> {
+        let mut result = if self.eat_symbol('.') {
+            Expression::Absolute(self.expect_name()?)
+        } else if self.is_keyword("import") {
+            let span = self.span();
+            self.pos += 1;
+            match self.next() {
+                Some(Token { kind: TokenKind::String(path), .. }) => Expression::Import(path, span),
+                _ => return Err(ParseError(span.offset, "Expected string after \"import\".".to_string())),
+            }
+        } else {
+            Expression::Relative(self.expect_name()?)
+        };
+        loop {
+            if self.eat_symbol('.') {
+                result = Expression::Member(Box::new(result), self.expect_name()?);
+            } else if self.eat_symbol('(') {
+                let mut args = Vec::new();
+                if !self.eat_symbol(')') {
+                    loop {
+                        args.push(self.parse_expression()?);
+                        if self.eat_symbol(')') { break }
+                        self.expect_symbol(',')?;
+                    }
+                }
+                result = Expression::Application(Box::new(result), args);
+            } else {
+                return Ok(result)
+            }
+        }
+    }
+
+    fn parse_struct_value_fields(&mut self) -> Result<Vec<(Name, Value)>> {
+        let mut fields = Vec::new();
+        if self.eat_symbol(')') {
+            return Ok(fields)
+        }
+        loop {
+            let name = self.expect_name()?;
+            self.expect_symbol('=')?;
+            fields.push((name, self.parse_value()?));
+            if self.eat_symbol(')') { return Ok(fields) }
+            self.expect_symbol(',')?;
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value> {
+        let span = self.span();
+        let negative = self.eat_symbol('-');
+        let kind = match self.peek().cloned() {
+            Some(TokenKind::Integer(v)) => { self.pos += 1; ValueKind::Integer(v, negative) }
+            Some(TokenKind::Float(v)) => { self.pos += 1; ValueKind::Float(if negative { -v } else { v }) }
+            Some(TokenKind::Identifier(_)) | Some(TokenKind::Symbol('.')) => {
+                if self.is_keyword("embed") {
+                    return self.error("Sorry, `embed` is not supported.");
+                }
+                ValueKind::Name(self.parse_name_expression()?, negative)
+            }
+            _ if negative => return self.error("Expected number."),
+            Some(TokenKind::String(s)) => { self.pos += 1; ValueKind::String(s) }
+            Some(TokenKind::Binary(b)) => { self.pos += 1; ValueKind::Binary(b) }
+            Some(TokenKind::Symbol('[')) => {
+                self.pos += 1;
+                let mut elements = Vec::new();
+                if !self.eat_symbol(']') {
+                    loop {
+                        elements.push(self.parse_value()?);
+                        if self.eat_symbol(']') { break }
+                        self.expect_symbol(',')?;
+                    }
+                }
+                ValueKind::List(elements)
+            }
+            Some(TokenKind::Symbol('(')) => {
+                self.pos += 1;
+                ValueKind::Struct(self.parse_struct_value_fields()?)
+            }
+            _ => return self.error("Expected value."),
+        };
+        Ok(Value { kind: kind, span: span })
+    }
+
+    fn parse_generic_params(&mut self) -> Result<Vec<Name>> {
+        let mut result = Vec::new();
+        if self.eat_symbol('(') {
+            loop {
+                result.push(self.expect_name()?);
+                if self.eat_symbol(')') { break }
+                self.expect_symbol(',')?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Parses the optional ID and generic parameter list of a struct or interface, which
+    /// may appear in either order.
+    fn parse_id_and_generic_params(&mut self) -> Result<(Id, Vec<Name>)> {
+        let params = self.parse_generic_params()?;
+        let id = self.parse_optional_id()?;
+        if params.is_empty() {
+            Ok((id, self.parse_generic_params()?))
+        } else {
+            Ok((id, params))
+        }
+    }
+
+    /// Parses the annotations and the body of a block declaration, along with the doc
+    /// comment that follows its `{`.
+    fn parse_block_tail(&mut self, context: Context)
+                        -> Result<(Vec<AnnotationApplication>, Option<String>, Vec<Rc<Declaration>>)>
+    {
+        let annotations = self.parse_annotations()?;
+        let line = self.expect_symbol('{')?;
+        let doc_comment = self.doc_comment(line);
+        let nested = self.parse_block(context)?;
+        Ok((annotations, doc_comment, nested))
+    }
+
+    /// Parses the annotations and `;` that end a declaration, along with the doc comment
+    /// that follows the `;`.
+    fn parse_statement_tail(&mut self) -> Result<(Vec<AnnotationApplication>, Option<String>)> {
+        let annotations = self.parse_annotations()?;
+        let line = self.expect_symbol(';')?;
+        Ok((annotations, self.doc_comment(line)))
+    }
+
+    fn parse_declaration(&mut self, context: Context) -> Result<Declaration> {
+        let keyword = match self.peek().cloned() {
+            Some(TokenKind::Identifier(name)) => name,
+            _ => return self.error("Expected declaration."),
+        };
+        let second = self.peek_second().cloned();
+        let is_member_start = match second {
+            Some(TokenKind::Symbol('@')) | Some(TokenKind::Symbol(':')) => true,
+            _ => false,
+        };
+
+        match context {
+            Context::Struct if is_member_start => return self.parse_member(),
+            Context::Struct if keyword == "union" => {
+                let span = self.span();
+                self.pos += 1;
+                let id = self.parse_optional_id()?;
+                let (annotations, doc_comment, nested) = self.parse_block_tail(Context::Struct)?;
+                return Ok(Declaration {
+                    name: Name { value: String::new(), span: span },
+                    id: id,
+                    kind: DeclarationKind::Union { nested: nested },
+                    annotations: annotations,
+                    doc_comment: doc_comment,
+                })
+            }
+            Context::Enum => {
+                let name = self.expect_name()?;
+                let id = self.parse_id()?;
+                let (annotations, doc_comment) = self.parse_statement_tail()?;
+                return Ok(Declaration {
+                    name: name, id: id, kind: DeclarationKind::Enumerant,
+                    annotations: annotations, doc_comment: doc_comment,
+                })
+            }
+            Context::Interface if second == Some(TokenKind::Symbol('@')) => return self.parse_method(),
+            _ => (),
+        }
+
+        let keyword_span = self.span();
+        self.pos += 1;
+        match &keyword[..] {
+            "using" => {
+                let explicit_name = match self.peek_second() {
+                    Some(&TokenKind::Symbol('=')) => true,
+                    _ => false,
+                };
+                let name = if explicit_name {
+                    let name = self.expect_name()?;
+                    self.expect_symbol('=')?;
+                    Some(name)
+                } else {
+                    None
+                };
+                let target = self.parse_expression()?;
+                let name = match name {
+                    Some(name) => name,
+                    None => match target {
+                        Expression::Relative(ref name) | Expression::Absolute(ref name) |
+                        Expression::Member(_, ref name) => name.clone(),
+                        _ => return Err(ParseError(keyword_span.offset,
+                                                   "`using` requires a name here.".to_string())),
+                    },
+                };
+                let (annotations, doc_comment) = self.parse_statement_tail()?;
+                Ok(Declaration {
+                    name: name, id: Id::Unspecified, kind: DeclarationKind::Using(target),
+                    annotations: annotations, doc_comment: doc_comment,
+                })
+            }
+            "const" => {
+                let name = self.expect_name()?;
+                let id = self.parse_optional_id()?;
+                self.expect_symbol(':')?;
+                let type_ = self.parse_expression()?;
+                self.expect_symbol('=')?;
+                let value = self.parse_value()?;
+                let (annotations, doc_comment) = self.parse_statement_tail()?;
+                Ok(Declaration {
+                    name: name, id: id, kind: DeclarationKind::Const { type_: type_, value: value },
+                    annotations: annotations, doc_comment: doc_comment,
+                })
+            }
+            "annotation" => {
+                let name = self.expect_name()?;
+                let id = self.parse_optional_id()?;
+                self.expect_symbol('(')?;
+                let mut targets = Vec::new();
+                loop {
+                    let span = self.span();
+                    if self.eat_symbol('*') {
+                        targets.push(Name { value: "*".to_string(), span: span });
+                    } else {
+                        targets.push(self.expect_name()?);
+                    }
+                    if self.eat_symbol(')') { break }
+                    self.expect_symbol(',')?;
+                }
+                self.expect_symbol(':')?;
+                let type_ = self.parse_expression()?;
+                let (annotations, doc_comment) = self.parse_statement_tail()?;
+                Ok(Declaration {
+                    name: name, id: id,
+                    kind: DeclarationKind::Annotation { targets: targets, type_: type_ },
+                    annotations: annotations, doc_comment: doc_comment,
+                })
+            }
+            "struct" => {
+                let name = self.expect_name()?;
+                let (id, params) = self.parse_id_and_generic_params()?;
+                let (annotations, doc_comment, nested) = self.parse_block_tail(Context::Struct)?;
+                Ok(Declaration {
+                    name: name, id: id, kind: DeclarationKind::Struct { params: params, nested: nested },
+                    annotations: annotations, doc_comment: doc_comment,
+                })
+            }
+            "enum" => {
+                let name = self.expect_name()?;
+                let id = self.parse_optional_id()?;
+                let (annotations, doc_comment, nested) = self.parse_block_tail(Context::Enum)?;
+                Ok(Declaration {
+                    name: name, id: id, kind: DeclarationKind::Enum { nested: nested },
+                    annotations: annotations, doc_comment: doc_comment,
+                })
+            }
+            "interface" => {
+                let name = self.expect_name()?;
+                let (id, params) = self.parse_id_and_generic_params()?;
+                let mut superclasses = Vec::new();
+                if self.is_keyword("extends") {
+                    self.pos += 1;
+                    self.expect_symbol('(')?;
+                    if !self.eat_symbol(')') {
+                        loop {
+                            superclasses.push(self.parse_expression()?);
+                            if self.eat_symbol(')') { break }
+                            self.expect_symbol(',')?;
+                        }
+                    }
+                }
+                let (annotations, doc_comment, nested) = self.parse_block_tail(Context::Interface)?;
+                Ok(Declaration {
+                    name: name, id: id,
+                    kind: DeclarationKind::Interface {
+                        params: params, superclasses: superclasses, nested: nested,
+                    },
+                    annotations: annotations, doc_comment: doc_comment,
+                })
+            }
+            _ => Err(ParseError(keyword_span.offset, format!("Unexpected \"{}\".", keyword))),
+        }
+    }
+
+    /// Parses a field, group or named union of a struct.
+    fn parse_member(&mut self) -> Result<Declaration> {
+        let name = self.expect_name()?;
+        let id = self.parse_optional_id()?;
+        self.expect_symbol(':')?;
+        let is_block = match self.peek_second() {
+            Some(&TokenKind::Symbol('{')) | Some(&TokenKind::Symbol('$')) => true,
+            _ => false,
+        };
+        if is_block && self.is_keyword("union") {
+            self.pos += 1;
+            let (annotations, doc_comment, nested) = self.parse_block_tail(Context::Struct)?;
+            Ok(Declaration {
+                name: name, id: id, kind: DeclarationKind::Union { nested: nested },
+                annotations: annotations, doc_comment: doc_comment,
+            })
+        } else if is_block && self.is_keyword("group") {
+            self.pos += 1;
+            let (annotations, doc_comment, nested) = self.parse_block_tail(Context::Struct)?;
+            Ok(Declaration {
+                name: name, id: id, kind: DeclarationKind::Group { nested: nested },
+                annotations: annotations, doc_comment: doc_comment,
+            })
+        } else {
+            let type_ = self.parse_expression()?;
+            let default = if self.eat_symbol('=') { Some(self.parse_value()?) } else { None };
+            let (annotations, doc_comment) = self.parse_statement_tail()?;
+            Ok(Declaration {
+                name: name, id: id, kind: DeclarationKind::Field { type_: type_, default: default },
+                annotations: annotations, doc_comment: doc_comment,
+            })
+        }
+    }
+
+    fn parse_param_list(&mut self) -> Result<ParamList> {
+        let span = self.span();
+        if !self.eat_symbol('(') {
+            return Ok(ParamList::Type(self.parse_expression()?))
+        }
+        let mut params = Vec::new();
+        if !self.eat_symbol(')') {
+            loop {
+                let name = self.expect_name()?;
+                self.expect_symbol(':')?;
+                let type_ = self.parse_expression()?;
+                let default = if self.eat_symbol('=') { Some(self.parse_value()?) } else { None };
+                let annotations = self.parse_annotations()?;
+                params.push(Param { name: name, type_: type_, default: default, annotations: annotations });
+                if self.eat_symbol(')') { break }
+                self.expect_symbol(',')?;
+            }
+        }
+        Ok(ParamList::Named(params, span))
+    }
+
+    fn parse_method(&mut self) -> Result<Declaration> {
+        let name = self.expect_name()?;
+        let id = self.parse_id()?;
+        let mut implicit_params = Vec::new();
+        if self.eat_symbol('[') {
+            loop {
+                implicit_params.push(self.expect_name()?);
+                if self.eat_symbol(']') { break }
+                self.expect_symbol(',')?;
+            }
+        }
+        let params = self.parse_param_list()?;
+        let results = if self.peek() == Some(&TokenKind::Arrow) {
+            self.pos += 1;
+            Some(self.parse_param_list()?)
+        } else {
+            None
+        };
+        let (annotations, doc_comment) = self.parse_statement_tail()?;
+        Ok(Declaration {
+            name: name, id: id,
+            kind: DeclarationKind::Method {
+                implicit_params: implicit_params, params: params, results: results,
+            },
+            annotations: annotations, doc_comment: doc_comment,
+        })
+    }
+}
+
+/// Parses the text of the file with index `file`. Returns the syntax tree, along with
+/// the offsets and messages of any errors.
+pub fn parse(file: usize, text: &str) -> (File, Vec<(usize, String)>) {
+    let (tokens, lex_errors) = lex(text);
+    let mut parser = Parser {
+        file: file,
+        tokens: tokens,
+        pos: 0,
+        end_offset: text.len(),
+        errors: Vec::new(),
+    };
+    let result = parser.parse_file();
+    let mut errors: Vec<(usize, String)> = lex_errors.into_iter().map(|e| (e.0, e.1)).collect();
+    errors.extend(parser.errors.into_iter().map(|e| (e.0, e.1)));
+    (result, errors)
+}
@@ -0,0 +1,1852 @@
+// Copyright (c) 2013-2018 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Semantic analysis of parsed schema files: assigns IDs, resolves names, lays out structs
+//! and evaluates constants, default values and annotations.
+//!
+//! Struct layout and ID assignment follow the C++ implementation exactly, so that both
+//! compilers produce the same wire format for the same schema.
+
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use compiler::ast::{self, DeclarationKind, Expression, Id, Span};
+use compiler::ids;
+use compiler::layout::{self, Layout};
+use compiler::model::*;
+
+/// A parsed schema file.
+pub struct SourceFile {
+    pub display_name: String,
+    pub ast: ast::File,
+
+    /// The files that the `import` expressions of this file refer to, as indexes into the
+    /// list of files, by import path.
+    pub imports: HashMap<String, usize>,
+}
+
+pub struct Error {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A named declaration that can be looked up in a scope.
+struct Decl {
+    id: u64,
+    file: usize,
+    parent: Option<usize>,
+
+    /// `None` for files.
+    ast: Option<Rc<ast::Declaration>>,
+    display_name: String,
+    display_name_prefix_length: usize,
+    params: Vec<String>,
+    is_generic: bool,
+    members: HashMap<String, usize>,
+
+    /// Nested declarations that are nodes, in declaration order.
+    nested: Vec<usize>,
+}
+
+/// The result of looking up a name.
+enum Resolved {
+    /// A declaration along with the generic parameters bound so far, in no particular order.
+    Decl(usize, Vec<BrandScope>),
+    Type(Type),
+
+    /// The builtin `List`, which has yet to be applied to an element type.
+    List,
+}
+
+/// The parts of a node or its members that annotations can be attached to.
+#[derive(Clone, Copy)]
+enum Target {
+    Node,
+    Field(usize),
+    Enumerant(usize),
+    Method(usize),
+}
+
+struct PendingAnnotations {
+    node_id: u64,
+    target: Target,
+
+    /// Index into `ANNOTATION_TARGETS`.
+    target_kind: usize,
+    scope: usize,
+    annotations: Vec<ast::AnnotationApplication>,
+}
+
+struct PendingDefault {
+    node_id: u64,
+    field: usize,
+    scope: usize,
+    value: ast::Value,
+}
+
+const TARGET_FILE: usize = 0;
+const TARGET_CONST: usize = 1;
+const TARGET_ENUM: usize = 2;
+const TARGET_ENUMERANT: usize = 3;
+const TARGET_STRUCT: usize = 4;
+const TARGET_FIELD: usize = 5;
+const TARGET_UNION: usize = 6;
+const TARGET_GROUP: usize = 7;
+const TARGET_INTERFACE: usize = 8;
+const TARGET_METHOD: usize = 9;
+const TARGET_PARAM: usize = 10;
+const TARGET_ANNOTATION: usize = 11;
+
+fn expression_text(expression: &Expression) -> String {
+    match *expression {
+        Expression::Relative(ref name) => name.value.clone(),
+        Expression::Absolute(ref name) => format!(".{}", name.value),
+        Expression::Import(ref path, _) => format!("import \"{}\"", path),
+        Expression::Member(ref parent, ref name) => format!("{}.{}", expression_text(parent), name.value),
+        Expression::Application(ref base, ref args) => {
+            let args: Vec<String> = args.iter().map(expression_text).collect();
+            format!("{}({})", expression_text(base), args.join(", "))
+        }
+    }
+}
+
+fn builtin(name: &str) -> Option<Resolved> {
+    let type_ = match name {
+        "Void" => Type::Void,
+        "Bool" => Type::Bool,
+        "Int8" => Type::Int8,
+        "Int16" => Type::Int16,
+        "Int32" => Type::Int32,
+        "Int64" => Type::Int64,
+        "UInt8" => Type::Uint8,
+        "UInt16" => Type::Uint16,
+        "UInt32" => Type::Uint32,
+        "UInt64" => Type::Uint64,
+        "Float32" => Type::Float32,
+        "Float64" => Type::Float64,
+        "Text" => Type::Text,
+        "Data" => Type::Data,
+        "AnyPointer" => Type::AnyPointer(AnyPointerKind::AnyKind),
+        "AnyStruct" => Type::AnyPointer(AnyPointerKind::Struct),
+        "AnyList" => Type::AnyPointer(AnyPointerKind::List),
+        "Capability" => Type::AnyPointer(AnyPointerKind::Capability),
+        "List" => return Some(Resolved::List),
+        _ => return None,
+    };
+    Some(Resolved::Type(type_))
+}
+
+fn signed_value(magnitude: u64, negative: bool, bits: u32) -> Option<i64> {
+    let limit = 1u64 << (bits - 1);
+    if negative {
+        if magnitude <= limit { Some((magnitude as i64).wrapping_neg()) } else { None }
+    } else if magnitude < limit {
+        Some(magnitude as i64)
+    } else {
+        None
+    }
+}
+
+fn unsigned_value(magnitude: u64, negative: bool, bits: u32) -> Option<u64> {
+    if negative && magnitude != 0 {
+        None
+    } else if bits == 64 || magnitude < 1u64 << bits {
+        Some(magnitude)
+    } else {
+        None
+    }
+}
+
+fn negate(value: Value) -> Option<Value> {
+    match value {
+        Value::Int8(v) => v.checked_neg().map(Value::Int8),
+        Value::Int16(v) => v.checked_neg().map(Value::Int16),
+        Value::Int32(v) => v.checked_neg().map(Value::Int32),
+        Value::Int64(v) => v.checked_neg().map(Value::Int64),
+        Value::Float32(v) => Some(Value::Float32(-v)),
+        Value::Float64(v) => Some(Value::Float64(-v)),
+        Value::Uint8(0) | Value::Uint16(0) | Value::Uint32(0) | Value::Uint64(0) => Some(value),
+        _ => None,
+    }
+}
+
+/// Replaces the implicit method parameters in `type_` with the corresponding parameters of
+/// the struct `scope_id`, which is the params or results struct declared by the method.
+fn implicit_to_struct_parameters(type_: &Type, scope_id: u64) -> Type {
+    match *type_ {
+        Type::AnyPointer(AnyPointerKind::ImplicitMethodParameter(index)) => {
+            Type::AnyPointer(AnyPointerKind::Parameter { scope_id: scope_id, index: index })
+        }
+        Type::List(ref element) => Type::List(Box::new(implicit_to_struct_parameters(element, scope_id))),
+        Type::Enum(id, ref brand) => Type::Enum(id, implicit_to_struct_parameters_of_brand(brand, scope_id)),
+        Type::Struct(id, ref brand) => Type::Struct(id, implicit_to_struct_parameters_of_brand(brand, scope_id)),
+        Type::Interface(id, ref brand) => Type::Interface(id, implicit_to_struct_parameters_of_brand(brand, scope_id)),
+        _ => type_.clone(),
+    }
+}
+
+fn implicit_to_struct_parameters_of_brand(brand: &Brand, scope_id: u64) -> Brand {
+    Brand {
+        scopes: brand.scopes.iter().map(|scope| match *scope {
+            BrandScope::Bind(id, ref bindings) => {
+                BrandScope::Bind(id, bindings.iter().map(|b| match *b {
+                    Binding::Type(ref t) => Binding::Type(implicit_to_struct_parameters(t, scope_id)),
+                    Binding::Unbound => Binding::Unbound,
+                }).collect())
+            }
+            BrandScope::Inherit(id) => BrandScope::Inherit(id),
+        }).collect(),
+    }
+}
+
+/// Replaces the generic parameters in `type_` with their bindings in `scopes`.
+fn substitute(type_: &Type, scopes: &[BrandScope]) -> Type {
+    match *type_ {
+        Type::AnyPointer(AnyPointerKind::Parameter { scope_id, index }) => {
+            for scope in scopes {
+                if let BrandScope::Bind(id, ref bindings) = *scope {
+                    if id == scope_id {
+                        return match bindings.get(index as usize) {
+                            Some(&Binding::Type(ref bound)) => bound.clone(),
+                            _ => Type::AnyPointer(AnyPointerKind::AnyKind),
+                        }
+                    }
+                }
+            }
+            type_.clone()
+        }
+        Type::List(ref element) => Type::List(Box::new(substitute(element, scopes))),
+        Type::Enum(id, ref brand) => Type::Enum(id, substitute_brand(brand, scopes)),
+        Type::Struct(id, ref brand) => Type::Struct(id, substitute_brand(brand, scopes)),
+        Type::Interface(id, ref brand) => Type::Interface(id, substitute_brand(brand, scopes)),
+        _ => type_.clone(),
+    }
+}
+
+fn substitute_scopes(inner: &[BrandScope], scopes: &[BrandScope]) -> Vec<BrandScope> {
+    inner.iter().map(|scope| match *scope {
+        BrandScope::Inherit(id) => {
+            scopes.iter().find(|s| s.scope_id() == id).cloned().unwrap_or(BrandScope::Inherit(id))
+        }
+        BrandScope::Bind(id, ref bindings) => {
+            BrandScope::Bind(id, bindings.iter().map(|b| match *b {
+                Binding::Type(ref t) => Binding::Type(substitute(t, scopes)),
+                Binding::Unbound => Binding::Unbound,
+            }).collect())
+        }
+    }).collect()
+}
+
+fn substitute_brand(brand: &Brand, scopes: &[BrandScope]) -> Brand {
+    Brand { scopes: substitute_scopes(&brand.scopes, scopes) }
+}
+
+/// Where a struct member comes from.
+enum MemberSource {
+    Root,
+    Decl(Rc<ast::Declaration>),
+    Param(ast::Param),
+}
+
+/// A field, group or union of a struct that is being laid out. Mirrors `MemberInfo` in
+/// the C++ compiler.
+struct Member {
+    parent: Option<usize>,
+    source: MemberSource,
+    name: String,
+    code_order: u16,
+    is_in_union: bool,
+
+    /// Index of this member's field in the parent, once the field has been created.
+    index: Option<usize>,
+
+    /// For the root, groups and unions: the fields created so far.
+    fields: Vec<Field>,
+    field_docs: Vec<Option<String>>,
+    child_names: HashSet<String>,
+    union_discriminant_count: u16,
+    discriminant_offset: u32,
+
+    /// For fields: the layout scope in which the field is allocated.
+    field_scope: layout::Scope,
+
+    /// For unions, and for the parent of an unnamed union: the union's layout.
+    union_scope: Option<usize>,
+
+    /// For the root, groups and unions: the ID of the node.
+    node_id: u64,
+    display_name: String,
+}
+
+enum OrdinalEntry {
+    Field(usize),
+    Union(usize),
+}
+
+struct StructState {
+    layout: Layout,
+    members: Vec<Member>,
+    all_members: Vec<usize>,
+    members_by_ordinal: Vec<(u16, Span, OrdinalEntry)>,
+}
+
+impl StructState {
+    fn new(node_id: u64, display_name: String) -> StructState {
+        StructState {
+            layout: Layout::new(),
+            members: vec![Member {
+                parent: None,
+                source: MemberSource::Root,
+                name: String::new(),
+                code_order: 0,
+                is_in_union: false,
+                index: None,
+                fields: Vec::new(),
+                field_docs: Vec::new(),
+                child_names: HashSet::new(),
+                union_discriminant_count: 0,
+                discriminant_offset: 0,
+                field_scope: layout::Scope::Top,
+                union_scope: None,
+                node_id: node_id,
+                display_name: display_name,
+            }],
+            all_members: Vec::new(),
+            members_by_ordinal: Vec::new(),
+        }
+    }
+
+    fn add_member(&mut self, parent: usize, code_order: u16, source: MemberSource, name: String,
+                  field_scope: layout::Scope, is_in_union: bool) -> usize
+    {
+        let display_name = format!("{}.{}", self.members[parent].display_name, name);
+        self.members.push(Member {
+            parent: Some(parent),
+            source: source,
+            name: name,
+            code_order: code_order,
+            is_in_union: is_in_union,
+            index: None,
+            fields: Vec::new(),
+            field_docs: Vec::new(),
+            child_names: HashSet::new(),
+            union_discriminant_count: 0,
+            discriminant_offset: 0,
+            field_scope: field_scope,
+            union_scope: None,
+            node_id: 0,
+            display_name: display_name,
+        });
+        let result = self.members.len() - 1;
+        self.all_members.push(result);
+        result
+    }
+
+    /// Returns the index of the member's field in its parent, creating the field (and those
+    /// of its ancestors) if necessary. Fields are thus created in the order in which their
+    /// first ordinal is reached.
+    fn get_schema(&mut self, member: usize) -> usize {
+        if let Some(index) = self.members[member].index {
+            return index
+        }
+        let parent = self.members[member].parent.expect("root has no field");
+        if self.members[parent].parent.is_some() {
+            self.get_schema(parent);
+        }
+        let discriminant_value = if self.members[member].is_in_union {
+            let count = self.members[parent].union_discriminant_count;
+            self.members[parent].union_discriminant_count += 1;
+            count
+        } else {
+            NO_DISCRIMINANT
+        };
+        let doc_comment = match self.members[member].source {
+            MemberSource::Decl(ref decl) => decl.doc_comment.clone(),
+            _ => None,
+        };
+        let field = Field {
+            name: self.members[member].name.clone(),
+            code_order: self.members[member].code_order,
+            annotations: Vec::new(),
+            discriminant_value: discriminant_value,
+            kind: FieldKind::Group(0),
+            ordinal: None,
+        };
+        let index = self.members[parent].fields.len();
+        self.members[parent].fields.push(field);
+        self.members[parent].field_docs.push(doc_comment);
+        self.members[member].index = Some(index);
+        index
+    }
+}
+
+/// Detects duplicate and skipped ordinals, given ordinals in sorted order.
+struct DuplicateOrdinalDetector {
+    expected: u32,
+    last: Option<(u16, Span)>,
+}
+
+impl DuplicateOrdinalDetector {
+    fn new() -> DuplicateOrdinalDetector {
+        DuplicateOrdinalDetector { expected: 0, last: None }
+    }
+
+    fn check(&mut self, ordinal: u16, span: Span, errors: &mut Vec<Error>) {
+        let value = ordinal as u32;
+        if value < self.expected {
+            errors.push(Error { span: span, message: "Duplicate ordinal number.".to_string() });
+            if let Some((last, last_span)) = self.last.take() {
+                errors.push(Error {
+                    span: last_span,
+                    message: format!("Ordinal @{} originally used here.", last),
+                });
+            }
+        } else if value > self.expected {
+            errors.push(Error {
+                span: span,
+                message: format!("Skipped ordinal @{}. Ordinals must be sequential with no holes.",
+                                 self.expected),
+            });
+            self.expected = value + 1;
+        } else {
+            self.expected += 1;
+            self.last = Some((ordinal, span));
+        }
+    }
+}
+
+pub struct Translator<'a> {
+    files: &'a [SourceFile],
+    decls: Vec<Decl>,
+    file_decls: Vec<usize>,
+    ids: HashMap<u64, Span>,
+    nodes: HashMap<u64, Node>,
+    node_order: Vec<u64>,
+    pending_annotations: Vec<PendingAnnotations>,
+    pending_defaults: Vec<PendingDefault>,
+    const_values: HashMap<usize, Option<(Type, Value)>>,
+    consts_in_progress: HashSet<usize>,
+    alias_depth: u32,
+    errors: Vec<Error>,
+}
+
+/// The result of translating a set of files.
+pub struct Translation {
+    /// All nodes of all files, in a deterministic order.
+    pub nodes: Vec<Node>,
+
+    /// The ID of each file, in the same order as the files.
+    pub file_ids: Vec<u64>,
+}
+
+/// Translates all of `files` into nodes, returning the nodes or the errors found.
+pub fn translate(files: &[SourceFile]) -> ::std::result::Result<Translation, Vec<Error>> {
+    let mut translator = Translator {
+        files: files,
+        decls: Vec::new(),
+        file_decls: Vec::new(),
+        ids: HashMap::new(),
+        nodes: HashMap::new(),
+        node_order: Vec::new(),
+        pending_annotations: Vec::new(),
+        pending_defaults: Vec::new(),
+        const_values: HashMap::new(),
+        consts_in_progress: HashSet::new(),
+        alias_depth: 0,
+        errors: Vec::new(),
+    };
+    for file in 0..files.len() {
+        translator.declare_file(file);
+    }
+    for decl in 0..translator.decls.len() {
+        translator.translate_decl(decl);
+    }
+    translator.evaluate_values();
+
+    if !translator.errors.is_empty() {
+        return Err(translator.errors)
+    }
+    let Translator { mut nodes, node_order, file_decls, decls, .. } = translator;
+    Ok(Translation {
+        nodes: node_order.iter().map(|id| nodes.remove(id).expect("node")).collect(),
+        file_ids: file_decls.iter().map(|&d| decls[d].id).collect(),
+    })
+}
+
+impl <'a> Translator<'a> {
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push(Error { span: span, message: message });
+    }
+
+    fn decl_span(&self, decl: usize) -> Span {
+        match self.decls[decl].ast {
+            Some(ref ast) => ast.name.span,
+            None => Span { file: self.decls[decl].file, offset: 0 },
+        }
+    }
+
+    fn check_id(&mut self, id: u64, span: Span) {
+        if id & (1 << 63) == 0 {
+            self.error(span, "Invalid ID. Please generate a new one with 'capnp id'.".to_string());
+        }
+        if self.ids.contains_key(&id) {
+            let original = self.ids[&id];
+            self.error(span, format!("Duplicate ID @0x{:016x}.", id));
+            self.error(original, "ID previously used here.".to_string());
+        } else {
+            self.ids.insert(id, span);
+        }
+    }
+
+    // ------------------------------------------------------------------------------------
+    // Declaration of names and IDs.
+
+    fn declare_file(&mut self, file: usize) {
+        let display_name = self.files[file].display_name.clone();
+        let id = match self.files[file].ast.id {
+            Id::Uid(id, span) => {
+                self.check_id(id, span);
+                id
+            }
+            Id::Ordinal(_, span) => {
+                self.error(span, "Invalid file ID.".to_string());
+                ids::generate_child_id(0, &display_name)
+            }
+            Id::Unspecified => {
+                let nanos = ::std::time::SystemTime::now()
+                    .duration_since(::std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs() ^ (d.subsec_nanos() as u64)).unwrap_or(0);
+                let suggestion = ids::generate_child_id(nanos, &display_name);
+                self.error(Span { file: file, offset: 0 },
+                           format!("File does not declare an ID. I've generated one for you. \
+                                    Add this line to your file: @0x{:016x};", suggestion));
+                ids::generate_child_id(0, &display_name)
+            }
+        };
+        let prefix_length = display_name.rfind('/').map_or(0, |i| i + 1);
+        self.decls.push(Decl {
+            id: id,
+            file: file,
+            parent: None,
+            ast: None,
+            display_name: display_name,
+            display_name_prefix_length: prefix_length,
+            params: Vec::new(),
+            is_generic: false,
+            members: HashMap::new(),
+            nested: Vec::new(),
+        });
+        let decl = self.decls.len() - 1;
+        self.file_decls.push(decl);
+        let declarations = self.files[file].ast.declarations.clone();
+        self.declare_nested(decl, &declarations);
+    }
+
+    fn declare_nested(&mut self, parent: usize, declarations: &[Rc<ast::Declaration>]) {
+        for declaration in declarations {
+            match declaration.kind {
+                DeclarationKind::Struct { .. } | DeclarationKind::Enum { .. } |
+                DeclarationKind::Interface { .. } | DeclarationKind::Const { .. } |
+                DeclarationKind::Annotation { .. } | DeclarationKind::Using(_) => {
+                    self.declare(parent, declaration.clone());
+                }
+                DeclarationKind::Union { ref nested } | DeclarationKind::Group { ref nested } => {
+                    for member in nested {
+                        match member.kind {
+                            DeclarationKind::Field { .. } | DeclarationKind::Union { .. } |
+                            DeclarationKind::Group { .. } => {
+                                self.declare_nested(parent, ::std::slice::from_ref(member));
+                            }
+                            _ => self.error(member.name.span,
+                                            "Declarations nested in unions and groups are not supported.".to_string()),
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn declare(&mut self, parent: usize, declaration: Rc<ast::Declaration>) {
+        let name = declaration.name.value.clone();
+        let name_span = declaration.name.span;
+        if self.decls[parent].members.contains_key(&name) {
+            // Dropping the duplicate here keeps two declarations from sharing one node, whose
+            // members would then be looked up by the other declaration's indexes.
+            self.error(name_span, format!("'{}' is already defined in this scope.", name));
+            return
+        }
+        let is_node = match declaration.kind { DeclarationKind::Using(_) => false, _ => true };
+        let id = if !is_node {
+            0
+        } else {
+            match declaration.id {
+                Id::Uid(id, span) => {
+                    self.check_id(id, span);
+                    id
+                }
+                Id::Ordinal(_, span) => {
+                    self.error(span, "Invalid ID. Please generate a new one with 'capnp id'.".to_string());
+                    ids::generate_child_id(self.decls[parent].id, &name)
+                }
+                Id::Unspecified => {
+                    let id = ids::generate_child_id(self.decls[parent].id, &name);
+                    self.check_id(id, name_span);
+                    id
+                }
+            }
+        };
+        let params: Vec<ast::Name> = match declaration.kind {
+            DeclarationKind::Struct { ref params, .. } |
+            DeclarationKind::Interface { ref params, .. } => params.clone(),
+            _ => Vec::new(),
+        };
+        let mut param_names = Vec::new();
+        for param in params {
+            if param_names.contains(&param.value) {
+                self.error(param.span, format!("Duplicate parameter name '{}'.", param.value));
+            }
+            param_names.push(param.value);
+        }
+
+        let (display_name, prefix_length) = {
+            let parent_decl = &self.decls[parent];
+            let separator = if parent_decl.parent.is_none() { ":" } else { "." };
+            (format!("{}{}{}", parent_decl.display_name, separator, name),
+             parent_decl.display_name.len() + 1)
+        };
+        let is_generic = self.decls[parent].is_generic || !param_names.is_empty();
+        self.decls.push(Decl {
+            id: id,
+            file: self.decls[parent].file,
+            parent: Some(parent),
+            ast: Some(declaration.clone()),
+            display_name: display_name,
+            display_name_prefix_length: prefix_length,
+            params: param_names,
+            is_generic: is_generic,
+            members: HashMap::new(),
+            nested: Vec::new(),
+        });
+        let decl = self.decls.len() - 1;
+        self.decls[parent].members.insert(name, decl);
+        if is_node {
+            self.decls[parent].nested.push(decl);
+        }
+        self.declare_nested(decl, declaration.nested());
+    }
+
+    // ------------------------------------------------------------------------------------
+    // Name resolution.
+
+    fn is_ancestor_or_self(&self, ancestor: usize, mut decl: usize) -> bool {
+        loop {
+            if decl == ancestor {
+                return true
+            }
+            match self.decls[decl].parent {
+                Some(parent) => decl = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Returns the brand of a declaration that is found as a member of `scope` while
+    /// looking up a name from `from`: generic scopes that enclose the lookup are inherited.
+    fn found(&mut self, decl: usize, scope: usize, from: usize) -> Option<Resolved> {
+        if let Some(ref ast) = self.decls[decl].ast.clone() {
+            if let DeclarationKind::Using(ref target) = ast.kind {
+                return self.resolve_alias(decl, target)
+            }
+        }
+        let mut brand = Vec::new();
+        if self.decls[decl].is_generic && !self.decls[decl].params.is_empty() &&
+            self.is_ancestor_or_self(decl, from)
+        {
+            brand.push(BrandScope::Inherit(self.decls[decl].id));
+        }
+        let mut current = Some(scope);
+        while let Some(c) = current {
+            if !self.decls[c].params.is_empty() {
+                brand.push(BrandScope::Inherit(self.decls[c].id));
+            }
+            current = self.decls[c].parent;
+        }
+        Some(Resolved::Decl(decl, brand))
+    }
+
+    fn resolve_alias(&mut self, decl: usize, target: &Expression) -> Option<Resolved> {
+        if self.alias_depth > 64 {
+            let span = self.decl_span(decl);
+            self.error(span, "Alias refers to itself.".to_string());
+            return None
+        }
+        self.alias_depth += 1;
+        let parent = self.decls[decl].parent.expect("alias parent");
+        let result = self.resolve(target, parent, &[]);
+        self.alias_depth -= 1;
+        result
+    }
+
+    /// Looks up an expression in the scope of the declaration `scope`.
+    fn resolve(&mut self, expression: &Expression, scope: usize, implicit_params: &[String])
+               -> Option<Resolved>
+    {
+        match *expression {
+            Expression::Relative(ref name) => {
+                if let Some(i) = implicit_params.iter().position(|p| *p == name.value) {
+                    return Some(Resolved::Type(
+                        Type::AnyPointer(AnyPointerKind::ImplicitMethodParameter(i as u16))))
+                }
+                let mut current = Some(scope);
+                while let Some(c) = current {
+                    if let Some(i) = self.decls[c].params.iter().position(|p| *p == name.value) {
+                        return Some(Resolved::Type(Type::AnyPointer(AnyPointerKind::Parameter {
+                            scope_id: self.decls[c].id,
+                            index: i as u16,
+                        })))
+                    }
+                    if let Some(&member) = self.decls[c].members.get(&name.value) {
+                        return self.found(member, c, scope)
+                    }
+                    current = self.decls[c].parent;
+                }
+                match builtin(&name.value) {
+                    Some(result) => Some(result),
+                    None => {
+                        self.error(name.span, format!("Not defined: {}", name.value));
+                        None
+                    }
+                }
+            }
+            Expression::Absolute(ref name) => {
+                let file = self.file_decls[self.decls[scope].file];
+                match self.decls[file].members.get(&name.value).cloned() {
+                    Some(member) => self.found(member, file, scope),
+                    None => {
+                        self.error(name.span, format!("Not defined: {}", name.value));
+                        None
+                    }
+                }
+            }
+            Expression::Import(ref path, span) => {
+                match self.files[self.decls[scope].file].imports.get(path) {
+                    Some(&file) => Some(Resolved::Decl(self.file_decls[file], Vec::new())),
+                    None => {
+                        self.error(span, format!("Import failed: {}", path));
+                        None
+                    }
+                }
+            }
+            Expression::Member(ref parent, ref name) => {
+                match self.resolve(parent, scope, implicit_params)? {
+                    Resolved::Decl(decl, brand) => {
+                        match self.decls[decl].members.get(&name.value).cloned() {
+                            Some(member) => {
+                                if let Some(ref ast) = self.decls[member].ast.clone() {
+                                    if let DeclarationKind::Using(ref target) = ast.kind {
+                                        // The alias is written in terms of the parameters of
+                                        // its scope, which may be bound by now.
+                                        return Some(match self.resolve_alias(member, target)? {
+                                            Resolved::Type(type_) => Resolved::Type(substitute(&type_, &brand)),
+                                            Resolved::Decl(decl, inner) => {
+                                                let mut scopes = substitute_scopes(&inner, &brand);
+                                                for scope in brand {
+                                                    if !scopes.iter().any(|s| s.scope_id() == scope.scope_id()) {
+                                                        scopes.push(scope);
+                                                    }
+                                                }
+                                                Resolved::Decl(decl, scopes)
+                                            }
+                                            Resolved::List => Resolved::List,
+                                        })
+                                    }
+                                }
+                                let mut brand = brand;
+                                if !self.decls[member].params.is_empty() &&
+                                    self.is_ancestor_or_self(member, scope)
+                                {
+                                    brand.push(BrandScope::Inherit(self.decls[member].id));
+                                }
+                                Some(Resolved::Decl(member, brand))
+                            }
+                            None => {
+                                self.error(name.span, format!("'{}' has no member named '{}'.",
+                                                              expression_text(parent), name.value));
+                                None
+                            }
+                        }
+                    }
+                    _ => {
+                        self.error(name.span, format!("'{}' is not a scope.", expression_text(parent)));
+                        None
+                    }
+                }
+            }
+            Expression::Application(ref base, ref args) => {
+                let span = base.span();
+                match self.resolve(base, scope, implicit_params)? {
+                    Resolved::List => {
+                        if args.len() != 1 {
+                            self.error(span, "'List' requires exactly one parameter.".to_string());
+                            return None
+                        }
+                        let element = self.resolve_type(&args[0], scope, implicit_params)?;
+                        Some(Resolved::Type(Type::List(Box::new(element))))
+                    }
+                    Resolved::Decl(decl, mut brand) => {
+                        let param_count = self.decls[decl].params.len();
+                        if param_count == 0 {
+                            self.error(span, format!("'{}' does not accept parameters.", expression_text(base)));
+                            return None
+                        }
+                        if args.len() > param_count {
+                            self.error(span, "Too many generic parameters.".to_string());
+                            return None
+                        }
+                        let id = self.decls[decl].id;
+                        let mut bindings = Vec::new();
+                        for arg in args {
+                            let type_ = self.resolve_type(arg, scope, implicit_params)?;
+                            if type_.placement() != Placement::Pointer {
+                                self.error(arg.span(), "Sorry, only pointer types can be used as generic parameters.".to_string());
+                                return None
+                            }
+                            bindings.push(Binding::Type(type_));
+                        }
+                        while bindings.len() < param_count {
+                            bindings.push(Binding::Unbound);
+                        }
+                        brand.retain(|s| s.scope_id() != id);
+                        brand.push(BrandScope::Bind(id, bindings));
+                        Some(Resolved::Decl(decl, brand))
+                    }
+                    Resolved::Type(_) => {
+                        self.error(span, format!("'{}' does not accept parameters.", expression_text(base)));
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    /// Puts the scopes of a brand in order, innermost first.
+    fn order_brand(&self, decl: usize, scopes: Vec<BrandScope>) -> Brand {
+        let mut result = Vec::new();
+        let mut current = Some(decl);
+        while let Some(c) = current {
+            let id = self.decls[c].id;
+            if let Some(scope) = scopes.iter().find(|s| s.scope_id() == id) {
+                result.push(scope.clone());
+            }
+            current = self.decls[c].parent;
+        }
+        Brand { scopes: result }
+    }
+
+    fn resolve_type(&mut self, expression: &Expression, scope: usize, implicit_params: &[String])
+                    -> Option<Type>
+    {
+        match self.resolve(expression, scope, implicit_params)? {
+            Resolved::Type(type_) => Some(type_),
+            Resolved::List => {
+                self.error(expression.span(), "'List' requires a parameter.".to_string());
+                None
+            }
+            Resolved::Decl(decl, brand) => {
+                let id = self.decls[decl].id;
+                let kind = self.decls[decl].ast.as_ref().map(|a| match a.kind {
+                    DeclarationKind::Struct { .. } => 1,
+                    DeclarationKind::Enum { .. } => 2,
+                    DeclarationKind::Interface { .. } => 3,
+                    _ => 0,
+                });
+                let brand = self.order_brand(decl, brand);
+                match kind {
+                    Some(1) => Some(Type::Struct(id, brand)),
+                    Some(2) => Some(Type::Enum(id, brand)),
+                    Some(3) => Some(Type::Interface(id, brand)),
+                    _ => {
+                        self.error(expression.span(),
+                                   format!("'{}' is not a type.", expression_text(expression)));
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    // ------------------------------------------------------------------------------------
+    // Translation of declarations into nodes.
+
+    fn new_node(&self, decl: usize, body: Body) -> Node {
+        let d = &self.decls[decl];
+        Node {
+            id: d.id,
+            display_name: d.display_name.clone(),
+            display_name_prefix_length: d.display_name_prefix_length as u32,
+            scope_id: d.parent.map_or(0, |p| self.decls[p].id),
+            nested_nodes: d.nested.iter().map(|&n| {
+                (self.decls[n].ast.as_ref().expect("nested").name.value.clone(), self.decls[n].id)
+            }).collect(),
+            annotations: Vec::new(),
+            parameters: d.params.clone(),
+            is_generic: d.is_generic,
+            body: body,
+            doc_comment: d.ast.as_ref().and_then(|a| a.doc_comment.clone()),
+            member_doc_comments: Vec::new(),
+        }
+    }
+
+    fn add_node(&mut self, node: Node) {
+        self.node_order.push(node.id);
+        self.nodes.insert(node.id, node);
+    }
+
+    fn translate_decl(&mut self, decl: usize) {
+        let id = self.decls[decl].id;
+        let ast = match self.decls[decl].ast.clone() {
+            Some(ast) => ast,
+            None => {
+                let node = self.new_node(decl, Body::File);
+                self.add_node(node);
+                let file = self.decls[decl].file;
+                self.pending_annotations.push(PendingAnnotations {
+                    node_id: id,
+                    target: Target::Node,
+                    target_kind: TARGET_FILE,
+                    scope: decl,
+                    annotations: self.files[file].ast.annotations.clone(),
+                });
+                return
+            }
+        };
+        let target_kind = match ast.kind {
+            DeclarationKind::Struct { ref nested, .. } => {
+                self.translate_struct(decl, nested);
+                TARGET_STRUCT
+            }
+            DeclarationKind::Enum { ref nested } => {
+                self.translate_enum(decl, nested);
+                TARGET_ENUM
+            }
+            DeclarationKind::Interface { ref superclasses, ref nested, .. } => {
+                self.translate_interface(decl, superclasses, nested);
+                TARGET_INTERFACE
+            }
+            DeclarationKind::Const { ref type_, .. } => {
+                let type_ = self.resolve_type(type_, decl, &[]).unwrap_or(Type::Void);
+                let value = Value::default_for(&type_);
+                let node = self.new_node(decl, Body::Const { type_: type_, value: value });
+                self.add_node(node);
+                TARGET_CONST
+            }
+            DeclarationKind::Annotation { ref targets, ref type_ } => {
+                let type_ = self.resolve_type(type_, decl, &[]).unwrap_or(Type::Void);
+                let mut flags = [false; 12];
+                for target in targets {
+                    if target.value == "*" {
+                        flags = [true; 12];
+                    } else {
+                        match ANNOTATION_TARGETS.iter().position(|t| *t == target.value) {
+                            Some(i) => flags[i] = true,
+                            None => self.error(target.span,
+                                               format!("'{}' is not a valid annotation target.", target.value)),
+                        }
+                    }
+                }
+                let node = self.new_node(decl, Body::Annotation { type_: type_, targets: flags });
+                self.add_node(node);
+                TARGET_ANNOTATION
+            }
+            _ => return,
+        };
+        self.pending_annotations.push(PendingAnnotations {
+            node_id: id,
+            target: Target::Node,
+            target_kind: target_kind,
+            scope: decl,
+            annotations: ast.annotations.clone(),
+        });
+    }
+
+    fn translate_enum(&mut self, decl: usize, nested: &[Rc<ast::Declaration>]) {
+        let mut enumerants = Vec::new();
+        let mut names = HashSet::new();
+        for member in nested {
+            if let DeclarationKind::Enumerant = member.kind {
+                if !names.insert(member.name.value.clone()) {
+                    self.error(member.name.span,
+                               format!("'{}' is already defined in this scope.", member.name.value));
+                }
+                match member.id {
+                    Id::Ordinal(ordinal, span) => {
+                        let code_order = enumerants.len() as u16;
+                        enumerants.push((ordinal, span, code_order, member.clone()));
+                    }
+                    _ => self.error(member.name.span, "Enumerants must have ordinals.".to_string()),
+                }
+            }
+        }
+        enumerants.sort_by_key(|e| e.0);
+        let mut detector = DuplicateOrdinalDetector::new();
+        for &(ordinal, span, _, _) in &enumerants {
+            detector.check(ordinal, span, &mut self.errors);
+        }
+
+        let id = self.decls[decl].id;
+        let mut node = self.new_node(decl, Body::Enum(Vec::new()));
+        let mut result = Vec::new();
+        for (index, (_, _, code_order, member)) in enumerants.into_iter().enumerate() {
+            result.push(Enumerant {
+                name: member.name.value.clone(),
+                code_order: code_order,
+                annotations: Vec::new(),
+            });
+            node.member_doc_comments.push(member.doc_comment.clone());
+            self.pending_annotations.push(PendingAnnotations {
+                node_id: id,
+                target: Target::Enumerant(index),
+                target_kind: TARGET_ENUMERANT,
+                scope: decl,
+                annotations: member.annotations.clone(),
+            });
+        }
+        node.body = Body::Enum(result);
+        self.add_node(node);
+    }
+
+    fn translate_struct(&mut self, decl: usize, nested: &[Rc<ast::Declaration>]) {
+        let id = self.decls[decl].id;
+        let mut state = StructState::new(id, self.decls[decl].display_name.clone());
+        let names: Vec<String> = self.decls[decl].members.keys().cloned().collect();
+        state.members[0].child_names.extend(names);
+        let mut code_order = 0;
+        self.traverse_top_or_group(&mut state, nested, 0, layout::Scope::Top, &mut code_order);
+        let node = self.new_node(decl, Body::File);
+        let is_generic = self.decls[decl].is_generic;
+        self.finish_struct(state, node, decl, &[], is_generic);
+    }
+
+    fn member_ordinal(&mut self, declaration: &ast::Declaration) -> Option<(u16, Span)> {
+        match declaration.id {
+            Id::Ordinal(ordinal, span) => Some((ordinal, span)),
+            Id::Uid(_, span) => {
+                self.error(span, "Invalid ordinal.".to_string());
+                None
+            }
+            Id::Unspecified => None,
+        }
+    }
+
+    fn check_member_name(&mut self, state: &mut StructState, parent: usize, name: &ast::Name) {
+        if !state.members[parent].child_names.insert(name.value.clone()) {
+            self.error(name.span, format!("'{}' is already defined in this scope.", name.value));
+        }
+    }
+
+    fn traverse_top_or_group(&mut self, state: &mut StructState, members: &[Rc<ast::Declaration>],
+                             parent: usize, scope: layout::Scope, code_order: &mut u16)
+    {
+        for member in members {
+            match member.kind {
+                DeclarationKind::Field { .. } => {
+                    self.check_member_name(state, parent, &member.name);
+                    let m = state.add_member(parent, *code_order, MemberSource::Decl(member.clone()),
+                                             member.name.value.clone(), scope, false);
+                    *code_order += 1;
+                    match self.member_ordinal(member) {
+                        Some((ordinal, span)) => {
+                            state.members_by_ordinal.push((ordinal, span, OrdinalEntry::Field(m)));
+                        }
+                        None => self.error(member.name.span, "Fields must have ordinals.".to_string()),
+                    }
+                }
+                DeclarationKind::Union { ref nested } => {
+                    let union_index = state.layout.new_union(scope);
+                    let mut independent_code_order = 0;
+                    let (m, sub_code_order) = if member.name.value.is_empty() {
+                        (parent, &mut *code_order)
+                    } else {
+                        self.check_member_name(state, parent, &member.name);
+                        let m = state.add_member(parent, *code_order, MemberSource::Decl(member.clone()),
+                                                 member.name.value.clone(), scope, false);
+                        *code_order += 1;
+                        (m, &mut independent_code_order)
+                    };
+                    state.members[m].union_scope = Some(union_index);
+                    self.traverse_union(state, member, nested, m, union_index, sub_code_order);
+                    if let Some((ordinal, span)) = self.member_ordinal(member) {
+                        state.members_by_ordinal.push((ordinal, span, OrdinalEntry::Union(union_index)));
+                    }
+                }
+                DeclarationKind::Group { ref nested } => {
+                    self.check_member_name(state, parent, &member.name);
+                    let m = state.add_member(parent, *code_order, MemberSource::Decl(member.clone()),
+                                             member.name.value.clone(), scope, false);
+                    *code_order += 1;
+                    self.traverse_group(state, member, nested, m, scope);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn traverse_union(&mut self, state: &mut StructState, declaration: &ast::Declaration,
+                      members: &[Rc<ast::Declaration>], parent: usize, union_index: usize,
+                      code_order: &mut u16)
+    {
+        if members.len() < 2 {
+            self.error(declaration.name.span, "Union must have at least two members.".to_string());
+        }
+        for member in members {
+            match member.kind {
+                DeclarationKind::Field { .. } => {
+                    self.check_member_name(state, parent, &member.name);
+                    // For layout purposes, the field is enclosed in a group of its own.
+                    let group = state.layout.new_group(union_index);
+                    let m = state.add_member(parent, *code_order, MemberSource::Decl(member.clone()),
+                                             member.name.value.clone(), group, true);
+                    *code_order += 1;
+                    match self.member_ordinal(member) {
+                        Some((ordinal, span)) => {
+                            state.members_by_ordinal.push((ordinal, span, OrdinalEntry::Field(m)));
+                        }
+                        None => self.error(member.name.span, "Fields must have ordinals.".to_string()),
+                    }
+                }
+                DeclarationKind::Union { ref nested } => {
+                    if member.name.value.is_empty() {
+                        self.error(member.name.span, "Unions cannot contain unnamed unions.".to_string());
+                        continue
+                    }
+                    self.check_member_name(state, parent, &member.name);
+                    let group = state.layout.new_group(union_index);
+                    let inner_union = state.layout.new_union(group);
+                    let m = state.add_member(parent, *code_order, MemberSource::Decl(member.clone()),
+                                             member.name.value.clone(), group, true);
+                    *code_order += 1;
+                    state.members[m].union_scope = Some(inner_union);
+                    let mut sub_code_order = 0;
+                    self.traverse_union(state, member, nested, m, inner_union, &mut sub_code_order);
+                    if let Some((ordinal, span)) = self.member_ordinal(member) {
+                        state.members_by_ordinal.push((ordinal, span, OrdinalEntry::Union(inner_union)));
+                    }
+                }
+                DeclarationKind::Group { ref nested } => {
+                    self.check_member_name(state, parent, &member.name);
+                    let group = state.layout.new_group(union_index);
+                    let m = state.add_member(parent, *code_order, MemberSource::Decl(member.clone()),
+                                             member.name.value.clone(), group, true);
+                    *code_order += 1;
+                    self.traverse_group(state, member, nested, m, group);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn traverse_group(&mut self, state: &mut StructState, declaration: &ast::Declaration,
+                      members: &[Rc<ast::Declaration>], parent: usize, scope: layout::Scope)
+    {
+        if let Id::Ordinal(_, span) = declaration.id {
+            self.error(span, "Groups don't have ordinals.".to_string());
+        }
+        if members.is_empty() {
+            self.error(declaration.name.span, "Group must have at least one member.".to_string());
+        }
+        let mut code_order = 0;
+        self.traverse_top_or_group(state, members, parent, scope, &mut code_order);
+    }
+
+    /// Assigns offsets to the members of a struct whose members have been traversed, and
+    /// adds the nodes of the struct and its groups. `node` is the struct's node, whose body
+    /// is replaced.
+    fn finish_struct(&mut self, mut state: StructState, mut node: Node, scope: usize,
+                     implicit_params: &[String], is_generic: bool)
+    {
+        let mut defaults = Vec::new();
+        let mut entries = ::std::mem::replace(&mut state.members_by_ordinal, Vec::new());
+        entries.sort_by_key(|e| e.0);
+        let mut detector = DuplicateOrdinalDetector::new();
+        for (ordinal, span, entry) in entries {
+            detector.check(ordinal, span, &mut self.errors);
+            match entry {
+                OrdinalEntry::Field(m) => {
+                    let index = state.get_schema(m);
+                    let (type_expression, default) = match state.members[m].source {
+                        MemberSource::Decl(ref decl) => match decl.kind {
+                            DeclarationKind::Field { ref type_, ref default } => (type_.clone(), default.clone()),
+                            _ => unreachable!(),
+                        },
+                        MemberSource::Param(ref param) => (param.type_.clone(), param.default.clone()),
+                        MemberSource::Root => unreachable!(),
+                    };
+                    let mut type_ = self.resolve_type(&type_expression, scope, implicit_params)
+                        .unwrap_or(Type::Void);
+                    if !implicit_params.is_empty() {
+                        // The struct is generic over the method's implicit parameters.
+                        type_ = implicit_to_struct_parameters(&type_, node.id);
+                    }
+                    let field_scope = state.members[m].field_scope;
+                    let offset = match type_.placement() {
+                        Placement::None => {
+                            state.layout.add_void(field_scope);
+                            0
+                        }
+                        Placement::Data(lg_size) => state.layout.add_data(field_scope, lg_size),
+                        Placement::Pointer => state.layout.add_pointer(field_scope),
+                    };
+                    let parent = state.members[m].parent.expect("field parent");
+                    {
+                        let field = &mut state.members[parent].fields[index];
+                        field.ordinal = Some(ordinal);
+                        field.kind = FieldKind::Slot {
+                            offset: offset,
+                            default_value: Value::default_for(&type_),
+                            type_: type_,
+                            had_explicit_default: false,
+                        };
+                    }
+                    if let Some(default) = default {
+                        defaults.push((m, default));
+                    }
+                }
+                OrdinalEntry::Union(union_index) => {
+                    if !state.layout.add_discriminant(union_index) {
+                        self.error(span, "Union ordinal, if specified, must be greater than no more than \
+                                          one of its member ordinals (i.e. there can only be one field \
+                                          retroactively unionized).".to_string());
+                    }
+                }
+            }
+        }
+
+        // Allocate the remaining discriminants and assign group IDs. Parents come before
+        // their children in `all_members`, so their IDs are known by then.
+        let all_members = state.all_members.clone();
+        self.finish_group(&mut state, 0);
+        for &m in &all_members {
+            let is_group = match state.members[m].source {
+                MemberSource::Decl(ref decl) => match decl.kind {
+                    DeclarationKind::Union { .. } | DeclarationKind::Group { .. } => true,
+                    _ => false,
+                },
+                _ => false,
+            };
+            if is_group {
+                self.finish_group(&mut state, m);
+            }
+        }
+
+        let data_word_count = state.layout.data_word_count() as u16;
+        let pointer_count = state.layout.pointer_count() as u16;
+
+        for (m, value) in defaults {
+            let parent = state.members[m].parent.expect("field parent");
+            self.pending_defaults.push(PendingDefault {
+                node_id: state.members[parent].node_id,
+                field: state.members[m].index.expect("field index"),
+                scope: scope,
+                value: value,
+            });
+        }
+        for &m in &all_members {
+            let parent = state.members[m].parent.expect("member parent");
+            let (annotations, target_kind) = match state.members[m].source {
+                MemberSource::Decl(ref decl) => {
+                    let kind = match decl.kind {
+                        DeclarationKind::Union { .. } => TARGET_UNION,
+                        DeclarationKind::Group { .. } => TARGET_GROUP,
+                        _ => TARGET_FIELD,
+                    };
+                    (decl.annotations.clone(), kind)
+                }
+                MemberSource::Param(ref param) => (param.annotations.clone(), TARGET_PARAM),
+                MemberSource::Root => unreachable!(),
+            };
+            if let Some(index) = state.members[m].index {
+                self.pending_annotations.push(PendingAnnotations {
+                    node_id: state.members[parent].node_id,
+                    target: Target::Field(index),
+                    target_kind: target_kind,
+                    scope: scope,
+                    annotations: annotations,
+                });
+            }
+        }
+
+        // Build the nodes, root first.
+        let mut members = state.members;
+        let root = &mut members[0];
+        node.body = Body::Struct(Struct {
+            data_word_count: data_word_count,
+            pointer_count: pointer_count,
+            is_group: false,
+            discriminant_count: root.union_discriminant_count,
+            discriminant_offset: root.discriminant_offset,
+            fields: ::std::mem::replace(&mut root.fields, Vec::new()),
+        });
+        node.member_doc_comments = ::std::mem::replace(&mut root.field_docs, Vec::new());
+        self.add_node(node);
+
+        for &m in &all_members {
+            if members[m].node_id == 0 {
+                continue
+            }
+            let parent = members[m].parent.expect("group parent");
+            let scope_id = members[parent].node_id;
+            let prefix_length = members[parent].display_name.len() + 1;
+            let member = &mut members[m];
+            let doc_comment = match member.source {
+                MemberSource::Decl(ref decl) => decl.doc_comment.clone(),
+                _ => None,
+            };
+            self.add_node(Node {
+                id: member.node_id,
+                display_name: member.display_name.clone(),
+                display_name_prefix_length: prefix_length as u32,
+                scope_id: scope_id,
+                nested_nodes: Vec::new(),
+                annotations: Vec::new(),
+                parameters: Vec::new(),
+                is_generic: is_generic,
+                body: Body::Struct(Struct {
+                    data_word_count: data_word_count,
+                    pointer_count: pointer_count,
+                    is_group: true,
+                    discriminant_count: member.union_discriminant_count,
+                    discriminant_offset: member.discriminant_offset,
+                    fields: ::std::mem::replace(&mut member.fields, Vec::new()),
+                }),
+                doc_comment: doc_comment,
+                member_doc_comments: ::std::mem::replace(&mut member.field_docs, Vec::new()),
+            });
+        }
+    }
+
+    fn finish_group(&mut self, state: &mut StructState, m: usize) {
+        if let Some(union_index) = state.members[m].union_scope {
+            state.layout.add_discriminant(union_index);
+            state.members[m].discriminant_offset =
+                state.layout.discriminant_offset(union_index).expect("discriminant offset");
+        }
+        if let Some(parent) = state.members[m].parent {
+            let index = state.get_schema(m);
+            let id = ids::generate_group_id(state.members[parent].node_id, index as u16);
+            state.members[m].node_id = id;
+            state.members[parent].fields[index].kind = FieldKind::Group(id);
+            let span = match state.members[m].source {
+                MemberSource::Decl(ref decl) => decl.name.span,
+                _ => unreachable!(),
+            };
+            self.check_id(id, span);
+        }
+    }
+
+    fn translate_interface(&mut self, decl: usize, superclass_expressions: &[Expression],
+                           nested: &[Rc<ast::Declaration>])
+    {
+        let mut superclasses = Vec::new();
+        for expression in superclass_expressions {
+            match self.resolve_type(expression, decl, &[]) {
+                Some(Type::Interface(id, brand)) => superclasses.push(Superclass { id: id, brand: brand }),
+                Some(_) => self.error(expression.span(),
+                                      format!("'{}' is not an interface.", expression_text(expression))),
+                None => (),
+            }
+        }
+
+        let mut methods = Vec::new();
+        let mut names = HashSet::new();
+        for member in nested {
+            if let DeclarationKind::Method { .. } = member.kind {
+                if !names.insert(member.name.value.clone()) {
+                    self.error(member.name.span,
+                               format!("'{}' is already defined in this scope.", member.name.value));
+                }
+                match member.id {
+                    Id::Ordinal(ordinal, span) => {
+                        let code_order = methods.len() as u16;
+                        methods.push((ordinal, span, code_order, member.clone()));
+                    }
+                    _ => self.error(member.name.span, "Methods must have ordinals.".to_string()),
+                }
+            }
+        }
+        methods.sort_by_key(|m| m.0);
+        let mut detector = DuplicateOrdinalDetector::new();
+        for &(ordinal, span, _, _) in &methods {
+            detector.check(ordinal, span, &mut self.errors);
+        }
+
+        let id = self.decls[decl].id;
+        let mut node = self.new_node(decl, Body::File);
+        let mut result = Vec::new();
+        for (index, (ordinal, _, code_order, member)) in methods.into_iter().enumerate() {
+            let (implicit_params, params, results) = match member.kind {
+                DeclarationKind::Method { ref implicit_params, ref params, ref results } =>
+                    (implicit_params, params, results),
+                _ => unreachable!(),
+            };
+            let implicit_params: Vec<String> = implicit_params.iter().map(|n| n.value.clone()).collect();
+            let (param_struct_type, param_brand) =
+                self.translate_param_list(decl, &member.name.value, ordinal, false, params, &implicit_params);
+            let empty = ast::ParamList::Named(Vec::new(), member.name.span);
+            let results = results.as_ref().unwrap_or(&empty);
+            let (result_struct_type, result_brand) =
+                self.translate_param_list(decl, &member.name.value, ordinal, true, results, &implicit_params);
+            result.push(Method {
+                name: member.name.value.clone(),
+                code_order: code_order,
+                implicit_parameters: implicit_params,
+                param_struct_type: param_struct_type,
+                param_brand: param_brand,
+                result_struct_type: result_struct_type,
+                result_brand: result_brand,
+                annotations: Vec::new(),
+            });
+            node.member_doc_comments.push(member.doc_comment.clone());
+            self.pending_annotations.push(PendingAnnotations {
+                node_id: id,
+                target: Target::Method(index),
+                target_kind: TARGET_METHOD,
+                scope: decl,
+                annotations: member.annotations.clone(),
+            });
+        }
+        node.body = Body::Interface { methods: result, superclasses: superclasses };
+        self.add_node(node);
+    }
+
+    /// Returns the struct type and brand of a method's parameters or results, adding a node
+    /// for the struct if the list is not an existing struct type.
+    fn translate_param_list(&mut self, interface: usize, method_name: &str, ordinal: u16, is_results: bool,
+                            list: &ast::ParamList, implicit_params: &[String]) -> (u64, Brand)
+    {
+        let (params, span) = match *list {
+            ast::ParamList::Type(ref expression) => {
+                return match self.resolve_type(expression, interface, implicit_params) {
+                    Some(Type::Struct(id, brand)) => (id, brand),
+                    Some(_) => {
+                        self.error(expression.span(), "Parameter and result lists must be structs.".to_string());
+                        (0, Brand::default())
+                    }
+                    None => (0, Brand::default()),
+                }
+            }
+            ast::ParamList::Named(ref params, span) => (params, span),
+        };
+
+        let interface_id = self.decls[interface].id;
+        let id = ids::generate_method_params_id(interface_id, ordinal, is_results);
+        self.check_id(id, span);
+        let display_name = format!("{}.{}${}", self.decls[interface].display_name, method_name,
+                                   if is_results { "Results" } else { "Params" });
+        let is_generic = self.decls[interface].is_generic || !implicit_params.is_empty();
+        let node = Node {
+            id: id,
+            display_name: display_name.clone(),
+            display_name_prefix_length: self.decls[interface].display_name.len() as u32 + 1,
+            scope_id: 0,
+            nested_nodes: Vec::new(),
+            annotations: Vec::new(),
+            parameters: implicit_params.to_vec(),
+            is_generic: is_generic,
+            body: Body::File,
+            doc_comment: None,
+            member_doc_comments: Vec::new(),
+        };
+
+        let mut state = StructState::new(id, display_name);
+        for (i, param) in params.iter().enumerate() {
+            if !state.members[0].child_names.insert(param.name.value.clone()) {
+                self.error(param.name.span, "Duplicate parameter name.".to_string());
+            }
+            let m = state.add_member(0, i as u16, MemberSource::Param(param.clone()), param.name.value.clone(),
+                                     layout::Scope::Top, false);
+            state.members_by_ordinal.push((i as u16, param.name.span, OrdinalEntry::Field(m)));
+        }
+        self.finish_struct(state, node, interface, implicit_params, is_generic);
+
+        let mut scopes = Vec::new();
+        if !implicit_params.is_empty() {
+            let bindings = (0..implicit_params.len()).map(|i| {
+                Binding::Type(Type::AnyPointer(AnyPointerKind::ImplicitMethodParameter(i as u16)))
+            }).collect();
+            scopes.push(BrandScope::Bind(id, bindings));
+        }
+        let mut current = Some(interface);
+        while let Some(c) = current {
+            if !self.decls[c].params.is_empty() {
+                scopes.push(BrandScope::Inherit(self.decls[c].id));
+            }
+            current = self.decls[c].parent;
+        }
+        (id, Brand { scopes: scopes })
+    }
+
+    // ------------------------------------------------------------------------------------
+    // Evaluation of values.
+
+    fn evaluate_values(&mut self) {
+        let defaults = ::std::mem::replace(&mut self.pending_defaults, Vec::new());
+        for default in defaults {
+            let type_ = match self.nodes[&default.node_id].body {
+                Body::Struct(ref s) => match s.fields[default.field].kind {
+                    FieldKind::Slot { ref type_, .. } => type_.clone(),
+                    FieldKind::Group(_) => continue,
+                },
+                _ => continue,
+            };
+            if let Some(value) = self.evaluate(&default.value, &type_, default.scope) {
+                if let Some(&mut Node { body: Body::Struct(ref mut s), .. }) = self.nodes.get_mut(&default.node_id) {
+                    if let FieldKind::Slot { ref mut default_value, ref mut had_explicit_default, .. } =
+                        s.fields[default.field].kind
+                    {
+                        *default_value = value;
+                        *had_explicit_default = true;
+                    }
+                }
+            }
+        }
+
+        for decl in 0..self.decls.len() {
+            let is_const = match self.decls[decl].ast {
+                Some(ref ast) => match ast.kind { DeclarationKind::Const { .. } => true, _ => false },
+                None => false,
+            };
+            if is_const {
+                if let Some((_, value)) = self.evaluate_const(decl) {
+                    let id = self.decls[decl].id;
+                    if let Some(&mut Node { body: Body::Const { value: ref mut v, .. }, .. }) = self.nodes.get_mut(&id) {
+                        *v = value;
+                    }
+                }
+            }
+        }
+
+        let pending = ::std::mem::replace(&mut self.pending_annotations, Vec::new());
+        for p in pending {
+            let mut annotations = Vec::new();
+            for application in &p.annotations {
+                if let Some(annotation) = self.evaluate_annotation(application, p.target_kind, p.scope) {
+                    annotations.push(annotation);
+                }
+            }
+            // A node may have been replaced by another declaration with the same ID, which has
+            // already been reported, so targets that no longer exist are skipped.
+            let node = self.nodes.get_mut(&p.node_id).expect("annotated node");
+            let target = match (p.target, &mut node.body) {
+                (Target::Node, _) => Some(&mut node.annotations),
+                (Target::Field(i), &mut Body::Struct(ref mut s)) =>
+                    s.fields.get_mut(i).map(|f| &mut f.annotations),
+                (Target::Enumerant(i), &mut Body::Enum(ref mut e)) =>
+                    e.get_mut(i).map(|e| &mut e.annotations),
+                (Target::Method(i), &mut Body::Interface { ref mut methods, .. }) =>
+                    methods.get_mut(i).map(|m| &mut m.annotations),
+                _ => None,
+            };
+            if let Some(target) = target {
+                *target = annotations;
+            }
+        }
+    }
+
+    fn evaluate_annotation(&mut self, application: &ast::AnnotationApplication, target_kind: usize,
+                           scope: usize) -> Option<Annotation>
+    {
+        let span = application.name.span();
+        let name = expression_text(&application.name);
+        let (decl, brand) = match self.resolve(&application.name, scope, &[])? {
+            Resolved::Decl(decl, brand) => (decl, brand),
+            _ => {
+                self.error(span, format!("'{}' is not an annotation.", name));
+                return None
+            }
+        };
+        let id = self.decls[decl].id;
+        let (type_, targets) = match self.nodes.get(&id).map(|n| &n.body) {
+            Some(&Body::Annotation { ref type_, targets }) => (type_.clone(), targets),
+            _ => {
+                self.error(span, format!("'{}' is not an annotation.", name));
+                return None
+            }
+        };
+        if !targets[target_kind] {
+            self.error(span, format!("'${}' cannot be applied to this kind of declaration.", name));
+            return None
+        }
+        let type_ = substitute(&type_, &brand);
+        let value = match application.value {
+            Some(ref value) => self.evaluate(value, &type_, scope)?,
+            None if type_ == Type::Void => Value::Void,
+            None => {
+                self.error(span, format!("'${}' requires a value.", name));
+                return None
+            }
+        };
+        Some(Annotation { id: id, brand: self.order_brand(decl, brand), value: value })
+    }
+
+    fn evaluate_const(&mut self, decl: usize) -> Option<(Type, Value)> {
+        if let Some(result) = self.const_values.get(&decl) {
+            return result.clone()
+        }
+        let span = self.decl_span(decl);
+        if !self.consts_in_progress.insert(decl) {
+            self.error(span, "Constant definition is circular.".to_string());
+            return None
+        }
+        let value = match self.decls[decl].ast.as_ref().map(|a| &a.kind) {
+            Some(&DeclarationKind::Const { ref value, .. }) => value.clone(),
+            _ => unreachable!(),
+        };
+        let id = self.decls[decl].id;
+        let type_ = match self.nodes[&id].body {
+            Body::Const { ref type_, .. } => type_.clone(),
+            _ => unreachable!(),
+        };
+        let result = self.evaluate(&value, &type_, decl).map(|v| (type_, v));
+        self.consts_in_progress.remove(&decl);
+        self.const_values.insert(decl, result.clone());
+        result
+    }
+
+    fn type_name(&self, type_: &Type) -> String {
+        match *type_ {
+            Type::Void => "Void".to_string(),
+            Type::Bool => "Bool".to_string(),
+            Type::Int8 => "Int8".to_string(),
+            Type::Int16 => "Int16".to_string(),
+            Type::Int32 => "Int32".to_string(),
+            Type::Int64 => "Int64".to_string(),
+            Type::Uint8 => "UInt8".to_string(),
+            Type::Uint16 => "UInt16".to_string(),
+            Type::Uint32 => "UInt32".to_string(),
+            Type::Uint64 => "UInt64".to_string(),
+            Type::Float32 => "Float32".to_string(),
+            Type::Float64 => "Float64".to_string(),
+            Type::Text => "Text".to_string(),
+            Type::Data => "Data".to_string(),
+            Type::List(ref element) => format!("List({})", self.type_name(element)),
+            Type::Enum(id, _) | Type::Struct(id, _) | Type::Interface(id, _) => {
+                match self.nodes.get(&id) {
+                    Some(node) => node.display_name[node.display_name_prefix_length as usize..].to_string(),
+                    None => format!("@0x{:016x}", id),
+                }
+            }
+            Type::AnyPointer(_) => "AnyPointer".to_string(),
+        }
+    }
+
+    fn type_mismatch(&mut self, span: Span, type_: &Type) -> Option<Value> {
+        let message = format!("Type mismatch; expected {}.", self.type_name(type_));
+        self.error(span, message);
+        None
+    }
+
+    /// Evaluates `value` as a value of type `type_`, looking up names in `scope`.
+    fn evaluate(&mut self, value: &ast::Value, type_: &Type, scope: usize) -> Option<Value> {
+        let span = value.span;
+        match value.kind {
+            ast::ValueKind::Integer(magnitude, negative) => {
+                let result = match *type_ {
+                    Type::Int8 => signed_value(magnitude, negative, 8).map(|v| Value::Int8(v as i8)),
+                    Type::Int16 => signed_value(magnitude, negative, 16).map(|v| Value::Int16(v as i16)),
+                    Type::Int32 => signed_value(magnitude, negative, 32).map(|v| Value::Int32(v as i32)),
+                    Type::Int64 => signed_value(magnitude, negative, 64).map(Value::Int64),
+                    Type::Uint8 => unsigned_value(magnitude, negative, 8).map(|v| Value::Uint8(v as u8)),
+                    Type::Uint16 => unsigned_value(magnitude, negative, 16).map(|v| Value::Uint16(v as u16)),
+                    Type::Uint32 => unsigned_value(magnitude, negative, 32).map(|v| Value::Uint32(v as u32)),
+                    Type::Uint64 => unsigned_value(magnitude, negative, 64).map(Value::Uint64),
+                    Type::Float32 => {
+                        let v = magnitude as f32;
+                        return Some(Value::Float32(if negative { -v } else { v }))
+                    }
+                    Type::Float64 => {
+                        let v = magnitude as f64;
+                        return Some(Value::Float64(if negative { -v } else { v }))
+                    }
+                    _ => return self.type_mismatch(span, type_),
+                };
+                if result.is_none() {
+                    self.error(span, "Integer value out of range.".to_string());
+                }
+                result
+            }
+            ast::ValueKind::Float(v) => match *type_ {
+                Type::Float32 => Some(Value::Float32(v as f32)),
+                Type::Float64 => Some(Value::Float64(v)),
+                _ => self.type_mismatch(span, type_),
+            },
+            ast::ValueKind::String(ref s) => match *type_ {
+                Type::Text => Some(Value::Text(Some(s.clone()))),
+                Type::Data => Some(Value::Data(Some(s.as_bytes().to_vec()))),
+                _ => self.type_mismatch(span, type_),
+            },
+            ast::ValueKind::Binary(ref b) => match *type_ {
+                Type::Data => Some(Value::Data(Some(b.clone()))),
+                _ => self.type_mismatch(span, type_),
+            },
+            ast::ValueKind::List(ref elements) => match *type_ {
+                Type::List(ref element_type) => {
+                    let mut result = Vec::new();
+                    for element in elements {
+                        result.push(self.evaluate(element, element_type, scope)?);
+                    }
+                    Some(Value::List((**element_type).clone(), Some(result)))
+                }
+                _ => self.type_mismatch(span, type_),
+            },
+            ast::ValueKind::Struct(ref assignments) => match *type_ {
+                Type::Struct(id, ref brand) => self.evaluate_struct(span, id, brand, assignments, scope),
+                _ => self.type_mismatch(span, type_),
+            },
+            ast::ValueKind::Name(ref expression, negative) => {
+                self.evaluate_name(span, expression, negative, type_, scope)
+            }
+        }
+    }
+
+    fn evaluate_name(&mut self, span: Span, expression: &Expression, negative: bool, type_: &Type,
+                     scope: usize) -> Option<Value>
+    {
+        if let Expression::Relative(ref name) = *expression {
+            let keyword = match (&name.value[..], type_) {
+                ("void", &Type::Void) => Some(Value::Void),
+                ("true", &Type::Bool) => Some(Value::Bool(true)),
+                ("false", &Type::Bool) => Some(Value::Bool(false)),
+                ("inf", &Type::Float32) => Some(Value::Float32(::std::f32::INFINITY)),
+                ("inf", &Type::Float64) => Some(Value::Float64(::std::f64::INFINITY)),
+                ("nan", &Type::Float32) => Some(Value::Float32(::std::f32::NAN)),
+                ("nan", &Type::Float64) => Some(Value::Float64(::std::f64::NAN)),
+                _ => None,
+            };
+            if let Some(value) = keyword {
+                return if negative { negate(value) } else { Some(value) }
+            }
+            if let Type::Enum(id, _) = *type_ {
+                let index = match self.nodes.get(&id).map(|n| &n.body) {
+                    Some(&Body::Enum(ref enumerants)) =>
+                        enumerants.iter().position(|e| e.name == name.value),
+                    _ => None,
+                };
+                if let Some(index) = index {
+                    if negative {
+                        return self.type_mismatch(span, type_)
+                    }
+                    return Some(Value::Enum(index as u16))
+                }
+            }
+        }
+
+        let decl = match self.resolve(expression, scope, &[])? {
+            Resolved::Decl(decl, _) => decl,
+            _ => {
+                self.error(span, format!("'{}' is not a constant.", expression_text(expression)));
+                return None
+            }
+        };
+        let is_const = match self.decls[decl].ast.as_ref().map(|a| &a.kind) {
+            Some(&DeclarationKind::Const { .. }) => true,
+            _ => false,
+        };
+        if !is_const {
+            self.error(span, format!("'{}' is not a constant.", expression_text(expression)));
+            return None
+        }
+        let (const_type, mut value) = self.evaluate_const(decl)?;
+        if !type_.accepts(&const_type) {
+            return self.type_mismatch(span, type_)
+        }
+        if let (&Type::AnyPointer(_), &Type::Struct(..)) | (&Type::AnyPointer(_), &Type::List(_)) |
+               (&Type::AnyPointer(_), &Type::Text) | (&Type::AnyPointer(_), &Type::Data) = (type_, &const_type) {
+            value = Value::AnyPointer(Some(Box::new(value)));
+        }
+        if negative {
+            match negate(value) {
+                Some(v) => Some(v),
+                None => self.type_mismatch(span, type_),
+            }
+        } else {
+            Some(value)
+        }
+    }
+
+    fn evaluate_struct(&mut self, span: Span, id: u64, brand: &Brand,
+                       assignments: &[(ast::Name, ast::Value)], scope: usize) -> Option<Value>
+    {
+        let fields: Vec<(String, u16, FieldKind)> = match self.nodes.get(&id).map(|n| &n.body) {
+            Some(&Body::Struct(ref s)) => {
+                s.fields.iter().map(|f| (f.name.clone(), f.discriminant_value, f.kind.clone())).collect()
+            }
+            _ => {
+                self.error(span, "Struct literals require a struct type.".to_string());
+                return None
+            }
+        };
+        let mut result: Vec<(usize, Value)> = Vec::new();
+        let mut union_member_set = false;
+        for &(ref name, ref value) in assignments {
+            let index = match fields.iter().position(|f| f.0 == name.value) {
+                Some(index) => index,
+                None => {
+                    self.error(name.span, format!("Struct has no field named '{}'.", name.value));
+                    continue
+                }
+            };
+            if result.iter().any(|r| r.0 == index) {
+                self.error(name.span, format!("Field '{}' is assigned more than once.", name.value));
+                continue
+            }
+            if fields[index].1 != NO_DISCRIMINANT {
+                if union_member_set {
+                    self.error(name.span, "Multiple members of the same union are assigned.".to_string());
+                    continue
+                }
+                union_member_set = true;
+            }
+            let evaluated = match fields[index].2 {
+                FieldKind::Slot { ref type_, .. } => {
+                    match substitute(type_, &brand.scopes) {
+                        Type::Interface(..) => {
+                            self.error(value.span, "Sorry, values of this type are not supported.".to_string());
+                            None
+                        }
+                        type_ => self.evaluate(value, &type_, scope),
+                    }
+                }
+                FieldKind::Group(group_id) => match value.kind {
+                    ast::ValueKind::Struct(ref sub) => self.evaluate_struct(value.span, group_id, brand, sub, scope),
+                    _ => {
+                        self.error(value.span, "Groups must be assigned struct literals.".to_string());
+                        None
+                    }
+                },
+            };
+            if let Some(v) = evaluated {
+                result.push((index, v));
+            }
+        }
+        Some(Value::Struct(id, Some(result)))
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright (c) 2013-2018 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Generation of `Debug` implementations for struct readers and builders, which show
+//! the struct's fields by their schema names. Of a union, only the member that is set
+//! is shown.
+
+use capnp::Error;
+
+use codegen::{camel_to_snake_case, capitalize_first_letter, get_field_name, FormattedText, GeneratorContext};
+use codegen::FormattedText::{Indent, Line, Branch};
+use codegen_types::{RustTypeInfo, TypeParameterTexts};
+use schema_capnp::{field, node, type_};
+
+/// Returns a statement that adds `field` to the `DebugStruct` named `s`, where `value` is
+/// the expression returned by the field's getter, or by `which()` for a union member.
+fn debug_field(field: &field::Reader, value: &str) -> ::capnp::Result<FormattedText> {
+    let name = field.get_name()?;
+    let mut null_offset = None;
+    let returns_result = match field.which()? {
+        field::Group(_) => false,
+        field::Slot(slot) => {
+            let typ = slot.get_type()?;
+            match typ.which()? {
+                type_::Interface(_) => {
+                    // Reading a capability needs a capability table, so only its presence is shown.
+                    return Ok(Line(format!(
+                        "s.field(\"{}\", &if self.reader.get_pointer_field({}).is_null() {{ format_args!(\"null\") }} else {{ format_args!(\"<capability>\") }});",
+                        name, slot.get_offset())))
+                }
+                type_::AnyPointer(_) => typ.is_parameter()?,
+                type_::Enum(_) => true,
+                type_::Struct(_) => {
+                    // An unset struct field reads as an empty struct, whose own struct fields
+                    // would be shown in turn without end.
+                    if !slot.get_had_explicit_default() {
+                        null_offset = Some(slot.get_offset());
+                    }
+                    true
+                }
+                _ => !typ.is_prim()?,
+            }
+        }
+    };
+
+    if returns_result {
+        let statement = Branch(vec![
+            Line(format!("match {} {{", value)),
+            Indent(Box::new(Branch(vec![
+                Line(format!("::std::result::Result::Ok(v) => {{ s.field(\"{}\", &v); }}", name)),
+                Line(format!("::std::result::Result::Err(e) => {{ s.field(\"{}\", &format_args!(\"<{{}}>\", e)); }}", name)),
+            ]))),
+            Line("}".to_string())]);
+        match null_offset {
+            None => Ok(statement),
+            Some(offset) => Ok(Branch(vec![
+                Line(format!("if self.reader.get_pointer_field({}).is_null() {{", offset)),
+                Indent(Box::new(Line(format!("s.field(\"{}\", &format_args!(\"null\"));", name)))),
+                Line("} else {".to_string()),
+                Indent(Box::new(statement)),
+                Line("}".to_string())])),
+        }
+    } else {
+        Ok(Line(format!("s.field(\"{}\", &{});", name, value)))
+    }
+}
+
+/// Generates `Debug` implementations for the `Reader` and `Builder` of the struct with
+/// ID `node_id`. A generic struct's implementations require its parameters' readers to
+/// implement `Debug`.
+pub fn generate_debug_impls(gen: &GeneratorContext,
+                            node_id: u64,
+                            params: &TypeParameterTexts) -> ::capnp::Result<FormattedText> {
+    let node_reader = gen.get_node(node_id)?;
+    let struct_reader = match node_reader.which()? {
+        node::Struct(s) => s,
+        _ => return Err(Error::failed(format!("generate_debug_impls() expected a struct"))),
+    };
+    let display_name = node_reader.get_display_name()?;
+    let struct_name = &display_name[node_reader.get_display_name_prefix_length() as usize..];
+
+    let mut body = vec![Line(format!("let mut s = f.debug_struct(\"{}\");", struct_name))];
+    let mut union_arms = Vec::new();
+    for field in struct_reader.get_fields()?.iter() {
+        let field_name = get_field_name(field)?;
+        if field.get_discriminant_value() == field::NO_DISCRIMINANT {
+            let getter = format!("self.reborrow().get_{}()", camel_to_snake_case(field_name));
+            body.push(debug_field(&field, &getter)?);
+        } else {
+            union_arms.push(Line(format!("::std::result::Result::Ok(Which::{}(_v)) => {{",
+                                         capitalize_first_letter(field_name))));
+            union_arms.push(Indent(Box::new(debug_field(&field, "_v")?)));
+            union_arms.push(Line("}".to_string()));
+        }
+    }
+    if !union_arms.is_empty() {
+        union_arms.push(Line(
+            "::std::result::Result::Err(e) => { s.field(\"which\", &format_args!(\"<{}>\", e)); }".to_string()));
+        body.push(Line("match self.reborrow().which() {".to_string()));
+        body.push(Indent(Box::new(Branch(union_arms))));
+        body.push(Line("}".to_string()));
+    }
+    body.push(Line("s.finish()".to_string()));
+
+    let where_clause = if params.expanded_list.is_empty() {
+        String::new()
+    } else {
+        let bounds: Vec<String> = params.expanded_list.iter().map(|param| {
+            format!("{0}: for<'c> ::capnp::traits::Owned<'c>, for<'c> <{0} as ::capnp::traits::Owned<'c>>::Reader: ::std::fmt::Debug",
+                    param)
+        }).collect();
+        format!("where {} ", bounds.join(", "))
+    };
+
+    Ok(Branch(vec![
+        Line(format!("impl <'a,{0}> ::std::fmt::Debug for Reader<'a,{0}> {1} {{", params.params, where_clause)),
+        Indent(Box::new(Branch(vec![
+            Line("fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {".to_string()),
+            Indent(Box::new(Branch(body))),
+            Line("}".to_string())]))),
+        Line("}".to_string()),
+        Line(format!("impl <'a,{0}> ::std::fmt::Debug for Builder<'a,{0}> {1} {{", params.params, where_clause)),
+        Indent(Box::new(Branch(vec![
+            Line("fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {".to_string()),
+            Indent(Box::new(Line("::std::fmt::Debug::fmt(&self.reborrow_as_reader(), f)".to_string()))),
+            Line("}".to_string())]))),
+        Line("}".to_string()),
+    ]))
+}
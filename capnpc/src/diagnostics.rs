@@ -0,0 +1,230 @@
+// Copyright (c) 2013-2018 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Problems found while generating code, located by the schema node and member that they
+//! concern.
+//!
+//! Code generation does not stop at the first error: the node in which an error occurs is
+//! left out of the generated code and the other nodes are still generated, so that one run
+//! reports every node that fails.
+
+use std::fmt;
+
+use schema_capnp::{type_, value};
+
+/// How bad a [`Diagnostic`](struct.Diagnostic.html) is.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Severity {
+    /// The generated code is usable, but it does not do everything the schema asks for.
+    Warning,
+
+    /// No code could be generated for the node.
+    Error,
+}
+
+/// A problem found while generating the code for a node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+
+    /// Display name of the node, like `foo.capnp:Bar`, which starts with the name of the
+    /// schema file that declares it.
+    pub node_name: String,
+
+    /// Name of the field, enumerant or method that the problem concerns, if it concerns one.
+    pub member: Option<String>,
+
+    /// What went wrong.
+    pub description: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        match self.member {
+            Some(ref member) => write!(f, "{}.{}: {}: {}", self.node_name, member, severity, self.description),
+            None => write!(f, "{}: {}: {}", self.node_name, severity, self.description),
+        }
+    }
+}
+
+/// Returns an error that describes the errors among `diagnostics`, one per line, if there
+/// are any.
+pub fn check(diagnostics: &[Diagnostic]) -> ::capnp::Result<()> {
+    let errors: Vec<String> = diagnostics.iter()
+        .filter(|d| d.severity == Severity::Error)
+        .map(|d| d.to_string())
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(::capnp::Error::failed(errors.join("\n")))
+    }
+}
+
+/// Returns the kind of `typ` as written in a schema, like `Int32` or `List`.
+pub fn type_kind(typ: type_::Reader) -> ::capnp::Result<&'static str> {
+    Ok(match typ.which()? {
+        type_::Void(()) => "Void",
+        type_::Bool(()) => "Bool",
+        type_::Int8(()) => "Int8",
+        type_::Int16(()) => "Int16",
+        type_::Int32(()) => "Int32",
+        type_::Int64(()) => "Int64",
+        type_::Uint8(()) => "UInt8",
+        type_::Uint16(()) => "UInt16",
+        type_::Uint32(()) => "UInt32",
+        type_::Uint64(()) => "UInt64",
+        type_::Float32(()) => "Float32",
+        type_::Float64(()) => "Float64",
+        type_::Text(()) => "Text",
+        type_::Data(()) => "Data",
+        type_::List(_) => "List",
+        type_::Enum(_) => "enum",
+        type_::Struct(_) => "struct",
+        type_::Interface(_) => "interface",
+        type_::AnyPointer(_) => "AnyPointer",
+    })
+}
+
+/// Returns the kind of type that `value` is a value of, named like `type_kind()` names it.
+pub fn value_kind(value: value::Reader) -> ::capnp::Result<&'static str> {
+    Ok(match value.which()? {
+        value::Void(()) => "Void",
+        value::Bool(_) => "Bool",
+        value::Int8(_) => "Int8",
+        value::Int16(_) => "Int16",
+        value::Int32(_) => "Int32",
+        value::Int64(_) => "Int64",
+        value::Uint8(_) => "UInt8",
+        value::Uint16(_) => "UInt16",
+        value::Uint32(_) => "UInt32",
+        value::Uint64(_) => "UInt64",
+        value::Float32(_) => "Float32",
+        value::Float64(_) => "Float64",
+        value::Text(_) => "Text",
+        value::Data(_) => "Data",
+        value::List(_) => "List",
+        value::Enum(_) => "enum",
+        value::Struct(_) => "struct",
+        value::Interface(()) => "interface",
+        value::AnyPointer(_) => "AnyPointer",
+    })
+}
+
+/// Describes a value whose kind does not match the type it is declared with.
+pub fn mismatch(what: &str, typ: type_::Reader, value: value::Reader) -> ::capnp::Result<String> {
+    Ok(format!("{} is a {} value, but its type is {}", what, value_kind(value)?, type_kind(typ)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use codegen::{generate_code_in_memory, generate_code_with_diagnostics, GeneratorOptions};
+    use compiler::compile_source;
+    use schema_capnp::{code_generator_request, field, node};
+    use super::Severity;
+
+    #[test]
+    fn errors_in_several_nodes_are_all_reported() {
+        let mut message = compile_source("test.capnp", "
+            @0xd8a7b2d4c1e2f3a1;
+            const good :Int32 = 1;
+            const bad :Int32 = 2;
+            struct Foo {
+              bar @0 :UInt8 = 3;
+            }").unwrap();
+        {
+            let request = message.get_root::<code_generator_request::Builder>().unwrap();
+            let mut nodes = request.get_nodes().unwrap();
+            for i in 0..nodes.len() {
+                let mut node = nodes.reborrow().get(i);
+                let name = node.reborrow().get_display_name().unwrap().to_string();
+                match node.which().unwrap() {
+                    node::Const(c) => if name == "test.capnp:bad" {
+                        c.init_value().set_text("two");
+                    },
+                    node::Struct(s) => {
+                        if let field::Slot(slot) = s.get_fields().unwrap().get(0).which().unwrap() {
+                            slot.init_default_value().set_float64(3.0);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        let mut bytes = Vec::new();
+        ::capnp::serialize::write_message(&mut bytes, &message).unwrap();
+
+        let error = generate_code_in_memory(&bytes[..], GeneratorOptions::new()).err().expect("error");
+        let mut lines: Vec<&str> = error.description.lines().collect();
+        lines.sort();
+        assert_eq!(lines, vec![
+            "test.capnp:Foo.bar: error: the default value is a Float64 value, but its type is UInt8",
+            "test.capnp:bad: error: the constant is a Text value, but its type is Int32",
+        ]);
+    }
+    #[test]
+    fn warnings_are_returned_but_do_not_fail_generation() {
+        let message = compile_source("test.capnp", "
+            @0xd8a7b2d4c1e2f3a1;
+            struct Foo {
+              bar @0 :List(Int32) = [1];
+            }").unwrap();
+        let mut bytes = Vec::new();
+        ::capnp::serialize::write_message(&mut bytes, &message).unwrap();
+
+        let (files, diagnostics) = generate_code_with_diagnostics(&bytes[..], GeneratorOptions::new()).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert!(diagnostics[0].to_string().starts_with("test.capnp:Foo.bar: warning: [UNSUPPORTED]"),
+                "{}", diagnostics[0]);
+        assert_eq!(generate_code_in_memory(&bytes[..], GeneratorOptions::new()).unwrap(), files);
+    }
+
+    #[test]
+    fn types_missing_from_the_request_are_reported() {
+        let mut message = compile_source("test.capnp", "
+            @0xd8a7b2d4c1e2f3a1;
+            struct Foo {
+              bar @0 :Foo;
+            }").unwrap();
+        {
+            let request = message.get_root::<code_generator_request::Builder>().unwrap();
+            let mut nodes = request.get_nodes().unwrap();
+            for i in 0..nodes.len() {
+                if let node::Struct(s) = nodes.reborrow().get(i).which().unwrap() {
+                    if let field::Slot(slot) = s.get_fields().unwrap().get(0).which().unwrap() {
+                        slot.get_type().unwrap().init_struct().set_type_id(0xb8e3fbf5a1e2a7c3);
+                    }
+                }
+            }
+        }
+        let mut bytes = Vec::new();
+        ::capnp::serialize::write_message(&mut bytes, &message).unwrap();
+
+        let error = generate_code_in_memory(&bytes[..], GeneratorOptions::new()).err().expect("error");
+        assert_eq!(error.description, "test.capnp:Foo.bar: error: no scope for node @0xb8e3fbf5a1e2a7c3");
+    }
+}
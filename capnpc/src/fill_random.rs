@@ -0,0 +1,266 @@
+// Copyright (c) 2013-2018 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Generation of `fill_random()` functions, which populate struct builders with
+//! arbitrary contents drawn from a `quickcheck::Gen`.
+
+use capnp::Error;
+
+use codegen::{camel_to_snake_case, get_field_name, FormattedText, GeneratorContext};
+use codegen::FormattedText::{Indent, Line, Branch};
+use codegen_types::TypeParameterTexts;
+use schema_capnp::{field, node, type_};
+
+const RANDOM_LENGTH: &'static str =
+    "<u32 as ::capnp::Arbitrary>::arbitrary(g) % (::capnp::Gen::size(g) as u32 + 1)";
+
+fn enumerant_count(gen: &GeneratorContext, enum_id: u64) -> ::capnp::Result<u32> {
+    match gen.get_node(enum_id)?.which()? {
+        node::Enum(e) => Ok(e.get_enumerants()?.len()),
+        _ => Err(Error::failed(format!("expected node {} to be an enum", enum_id))),
+    }
+}
+
+/// Returns whether a list with elements of type `element_type` can be initialized.
+/// Lists whose elements are (or contain) generic parameters are left null.
+fn list_is_fillable(element_type: type_::Reader) -> ::capnp::Result<bool> {
+    match element_type.which()? {
+        type_::AnyPointer(_) => Ok(false),
+        type_::List(ot) => list_is_fillable(ot.get_element_type()?),
+        _ => Ok(true),
+    }
+}
+
+/// Returns statements that initialize the list `l{nesting}`, which has `n{nesting}`
+/// elements of type `element_type`, with random values.
+fn fill_list_elements(gen: &GeneratorContext,
+                      element_type: type_::Reader,
+                      nesting: usize) -> ::capnp::Result<Vec<FormattedText>> {
+    let list = format!("l{}", nesting);
+    let len = format!("n{}", nesting);
+    let index = format!("i{}", nesting);
+
+    let body = match element_type.which()? {
+        type_::Void(()) | type_::Interface(_) | type_::AnyPointer(_) => return Ok(Vec::new()),
+        type_::Bool(()) | type_::Int8(()) | type_::Int16(()) | type_::Int32(()) |
+        type_::Int64(()) | type_::Uint8(()) | type_::Uint16(()) | type_::Uint32(()) |
+        type_::Uint64(()) | type_::Float32(()) | type_::Float64(()) => {
+            vec![Line(format!("{}.set({}, ::capnp::Arbitrary::arbitrary(g));", list, index))]
+        }
+        type_::Enum(e) => {
+            let count = enumerant_count(gen, e.get_type_id())?;
+            if count == 0 { return Ok(Vec::new()) }
+            vec![
+                Line(format!(
+                    "if let ::std::result::Result::Ok(v) = ::capnp::traits::FromU16::from_u16(<u16 as ::capnp::Arbitrary>::arbitrary(g) % {}) {{",
+                    count)),
+                Indent(Box::new(Line(format!("{}.set({}, v);", list, index)))),
+                Line("}".to_string()),
+            ]
+        }
+        type_::Text(()) => {
+            vec![Line(format!("{}.set({}, &<String as ::capnp::Arbitrary>::arbitrary(g));", list, index))]
+        }
+        type_::Data(()) => {
+            vec![Line(format!("{}.set({}, &<Vec<u8> as ::capnp::Arbitrary>::arbitrary(g));", list, index))]
+        }
+        type_::Struct(st) => {
+            let the_mod = gen.get_scope(st.get_type_id())?.join("::");
+            vec![Line(format!("{}::fill_random({}.reborrow().get({}), g, depth - 1);", the_mod, list, index))]
+        }
+        type_::List(ot) => {
+            let inner_type = ot.get_element_type()?;
+            let mut result = vec![
+                Line(format!("let n{} = {};", nesting + 1, length_expression(inner_type))),
+                Line(format!("let mut l{} = {}.reborrow().init({}, n{});", nesting + 1, list, index, nesting + 1)),
+            ];
+            result.extend(fill_list_elements(gen, inner_type, nesting + 1)?);
+            result
+        }
+    };
+
+    Ok(vec![
+        Line(format!("for {} in 0..{} {{", index, len)),
+        Indent(Box::new(Branch(body))),
+        Line("}".to_string()),
+    ])
+}
+
+/// Lists of structs consume a level of `depth` and have at most `depth` elements, so that
+/// the size of the filled message does not grow exponentially with `depth`.
+fn length_expression(element_type: type_::Reader) -> String {
+    match element_type.which() {
+        Ok(type_::Struct(_)) =>
+            "<u32 as ::capnp::Arbitrary>::arbitrary(g) % (depth + 1)".to_string(),
+        _ => RANDOM_LENGTH.to_string(),
+    }
+}
+
+/// Returns statements that set `field` to a random value, or `None` if the field
+/// cannot be filled (interfaces and `AnyPointer`s), in which case it is left null.
+fn fill_field(gen: &GeneratorContext,
+              field: &field::Reader,
+              is_union_field: bool) -> ::capnp::Result<Option<Vec<FormattedText>>> {
+    let styled_name = camel_to_snake_case(get_field_name(*field)?);
+
+    match field.which()? {
+        field::Group(group) => {
+            let the_mod = gen.get_scope(group.get_type_id())?.join("::");
+            let accessor = if is_union_field { "init" } else { "get" };
+            Ok(Some(vec![
+                Line(format!("{}::fill_random(builder.reborrow().{}_{}(), g, depth);",
+                             the_mod, accessor, styled_name))]))
+        }
+        field::Slot(slot) => {
+            let typ = slot.get_type()?;
+            match typ.which()? {
+                type_::Void(()) => {
+                    if is_union_field {
+                        Ok(Some(vec![Line(format!("builder.set_{}(());", styled_name))]))
+                    } else {
+                        Ok(Some(Vec::new()))
+                    }
+                }
+                type_::Bool(()) | type_::Int8(()) | type_::Int16(()) | type_::Int32(()) |
+                type_::Int64(()) | type_::Uint8(()) | type_::Uint16(()) | type_::Uint32(()) |
+                type_::Uint64(()) | type_::Float32(()) | type_::Float64(()) => {
+                    Ok(Some(vec![
+                        Line(format!("builder.set_{}(::capnp::Arbitrary::arbitrary(g));",
+                                     styled_name))]))
+                }
+                type_::Enum(e) => {
+                    let count = enumerant_count(gen, e.get_type_id())?;
+                    if count == 0 { return Ok(None) }
+                    Ok(Some(vec![
+                        Line(format!(
+                            "if let ::std::result::Result::Ok(v) = ::capnp::traits::FromU16::from_u16(<u16 as ::capnp::Arbitrary>::arbitrary(g) % {}) {{",
+                            count)),
+                        Indent(Box::new(Line(format!("builder.set_{}(v);", styled_name)))),
+                        Line("}".to_string())]))
+                }
+                type_::Text(()) => {
+                    Ok(Some(vec![
+                        Line(format!("builder.set_{}(&<String as ::capnp::Arbitrary>::arbitrary(g));",
+                                     styled_name))]))
+                }
+                type_::Data(()) => {
+                    Ok(Some(vec![
+                        Line(format!("builder.set_{}(&<Vec<u8> as ::capnp::Arbitrary>::arbitrary(g));",
+                                     styled_name))]))
+                }
+                type_::Struct(st) => {
+                    let the_mod = gen.get_scope(st.get_type_id())?.join("::");
+                    Ok(Some(vec![
+                        Line("if depth > 0 {".to_string()),
+                        Indent(Box::new(Line(format!("{}::fill_random(builder.reborrow().init_{}(), g, depth - 1);",
+                                                     the_mod, styled_name)))),
+                        Line("} else {".to_string()),
+                        Indent(Box::new(Line(format!("builder.reborrow().init_{}();", styled_name)))),
+                        Line("}".to_string())]))
+                }
+                type_::List(ot) => {
+                    let element_type = ot.get_element_type()?;
+                    if !list_is_fillable(element_type)? {
+                        return Ok(None)
+                    }
+                    let mut result = vec![
+                        Line(format!("let n0 = {};", length_expression(element_type))),
+                        Line(format!("let mut l0 = builder.reborrow().init_{}(n0);", styled_name)),
+                    ];
+                    result.extend(fill_list_elements(gen, element_type, 0)?);
+                    Ok(Some(vec![
+                        Line("{".to_string()),
+                        Indent(Box::new(Branch(result))),
+                        Line("}".to_string())]))
+                }
+                type_::Interface(_) | type_::AnyPointer(_) => Ok(None),
+            }
+        }
+    }
+}
+
+/// Generates the `fill_random()` function for the struct with ID `node_id`.
+///
+/// Nested structs are only filled while `depth` is positive; each level of nesting
+/// decrements it. Union members are chosen uniformly at random.
+pub fn generate_fill_random(gen: &GeneratorContext,
+                            node_id: u64,
+                            params: &TypeParameterTexts) -> ::capnp::Result<FormattedText> {
+    let node_reader = gen.get_node(node_id)?;
+    let struct_reader = match node_reader.which()? {
+        node::Struct(s) => s,
+        _ => return Err(Error::failed(format!("generate_fill_random() expected a struct"))),
+    };
+
+    let mut body = Vec::new();
+    let mut union_arms = Vec::new();
+    let discriminant_offset = struct_reader.get_discriminant_offset();
+
+    for field in struct_reader.get_fields()?.iter() {
+        let discriminant_value = field.get_discriminant_value();
+        let is_union_field = discriminant_value != field::NO_DISCRIMINANT;
+        let statements = fill_field(gen, &field, is_union_field)?;
+
+        if !is_union_field {
+            if let Some(statements) = statements {
+                body.extend(statements);
+            }
+        } else {
+            let statements = match statements {
+                Some(statements) => statements,
+                None => {
+                    // We can't produce a value for this member, so we just set the
+                    // discriminant and leave the pointer null.
+                    let mut result = vec![
+                        Line(format!("builder.builder.set_data_field::<u16>({}, {});",
+                                     discriminant_offset, discriminant_value))];
+                    if let field::Slot(slot) = field.which()? {
+                        result.push(Line(format!("builder.builder.get_pointer_field({}).clear();",
+                                                 slot.get_offset())));
+                    }
+                    result
+                }
+            };
+            union_arms.push(Branch(vec![
+                Line(format!("{} => {{", discriminant_value)),
+                Indent(Box::new(Branch(statements))),
+                Line("}".to_string())]));
+        }
+    }
+
+    if !union_arms.is_empty() {
+        union_arms.push(Line("_ => {}".to_string()));
+        body.push(Branch(vec![
+            Line(format!("match <u16 as ::capnp::Arbitrary>::arbitrary(g) % {} {{",
+                         struct_reader.get_discriminant_count())),
+            Indent(Box::new(Branch(union_arms))),
+            Line("}".to_string())]));
+    }
+
+    Ok(Branch(vec![
+        Line("/// Fills `builder` with random contents. Nested structs are filled only while `depth` is positive.".to_string()),
+        Line("#[allow(unused_mut, unused_variables)]".to_string()),
+        Line(format!("pub fn fill_random<'a,RandGen: ::capnp::Gen,{0}>(mut builder: Builder<'a,{0}>, g: &mut RandGen, depth: u32) {1} {{",
+                     params.params, params.where_clause)),
+        Indent(Box::new(Branch(body))),
+        Line("}".to_string()),
+    ]))
+}
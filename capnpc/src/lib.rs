@@ -23,9 +23,17 @@
 //!
 //! This library allows you to do [Cap'n Proto code generation]
 //! (https://capnproto.org/otherlang.html#how-to-write-compiler-plugins) within
-//! a Cargo build. You still need the `capnp` binary (implemented in C++).
-//! (If you use a package manager, try looking for a package called
-//! `capnproto`.)
+//! a Cargo build. By default, schemas are compiled by running the `capnp` binary
+//! (implemented in C++). (If you use a package manager, try looking for a package called
+//! `capnproto`.) There are two ways to do without it:
+//!
+//!  * `.builtin_compiler(true)` compiles the schemas with the compiler in the `compiler`
+//!    module, which is written in Rust. It does not support `embed` expressions, nor
+//!    declarations (such as nested structs or constants) inside unions and groups; schemas
+//!    using them are rejected with an error.
+//!  * `.request_file(path)` generates code from a `CodeGeneratorRequest` that was saved
+//!    earlier, for example with `capnp compile -o- foo.capnp > foo.request`, and checked in
+//!    alongside the schemas.
 //!
 //! In your Cargo.toml:
 //!
@@ -57,6 +65,19 @@
 //!   capnp compile -orust:$OUT_DIR --src-prefix=schema schema/foo.capnp schema/bar.capnp
 //! ```
 //!
+//! Naming and module placement of the generated code can be adjusted from within
+//! schemas by the annotations declared in `rust.capnp`, which is distributed
+//! alongside this crate.
+//!
+//! Each generated file, like `$OUT_DIR/foo_capnp.rs`, must then be included in a module
+//! whose path matches that placement. With `.modules_file("capnp_modules.rs")`, a file
+//! declaring all of these modules is generated as well, so that a single line in the
+//! crate root suffices:
+//!
+//! ```ignore
+//! include!(concat!(env!("OUT_DIR"), "/capnp_modules.rs"));
+//! ```
+//!
 
 extern crate capnp;
 
@@ -66,9 +87,17 @@ pub mod schema_capnp;
 
 pub mod codegen;
 pub mod codegen_types;
+pub mod compatibility;
+pub mod compiler;
+mod debug_impls;
+pub mod diagnostics;
+mod fill_random;
+mod native_types;
 mod pointer_constants;
 pub mod schema;
+mod serde_impls;
 
+use std::io::Read;
 use std::path::{Path, PathBuf};
 
 #[derive(Copy, Clone)]
@@ -77,15 +106,30 @@ pub enum RustEdition {
     Rust2018
 }
 
-fn run_command(mut command: ::std::process::Command, edition: RustEdition) -> ::capnp::Result<()> {
+/// Where generated files are placed in the output directory.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OutputLayout {
+    /// Each generated file mirrors the path of its schema file, so `foo/bar.capnp` is
+    /// generated as `foo/bar_capnp.rs`.
+    Tree,
+
+    /// All generated files are placed directly in the output directory.
+    Flat,
+}
+
+fn run_command<F, R>(mut command: ::std::process::Command, generate: F) -> ::capnp::Result<R>
+    where F: FnOnce(Box<::std::io::Read>) -> ::capnp::Result<R>
+{
     let mut p = command.spawn()?;
-    ::codegen::generate_code(p.stdout.take().unwrap(),
-                         ::std::path::Path::new(&::std::env::var("OUT_DIR").unwrap()), edition)?;
+    // The child's stdout is closed once `generate` returns, so even if it fails early, the
+    // child does not block writing to it and can be waited on.
+    let result = generate(Box::new(p.stdout.take().unwrap()));
     let exit_status = p.wait()?;
+    let result = result?;
     if !exit_status.success() {
         Err(::capnp::Error::failed(format!("Non-success exit status: {}", exit_status)))
     } else {
-        Ok(())
+        Ok(result)
     }
 }
 
@@ -95,7 +139,10 @@ pub struct CompilerCommand {
     src_prefixes: Vec<PathBuf>,
     import_paths: Vec<PathBuf>,
     no_standard_import: bool,
-    rust_edition: RustEdition,
+    output_path: Option<PathBuf>,
+    request_file: Option<PathBuf>,
+    builtin_compiler: bool,
+    options: codegen::GeneratorOptions,
 }
 
 impl CompilerCommand {
@@ -106,7 +153,10 @@ impl CompilerCommand {
             src_prefixes: Vec::new(),
             import_paths: Vec::new(),
             no_standard_import: false,
-            rust_edition: RustEdition::Rust2015,
+            output_path: None,
+            request_file: None,
+            builtin_compiler: false,
+            options: codegen::GeneratorOptions::new(),
         }
     }
 
@@ -145,12 +195,127 @@ impl CompilerCommand {
 
     /// Sets the Rust edition of the generated code.
     pub fn edition(&mut self, rust_edition: RustEdition) -> &mut Self {
-        self.rust_edition = rust_edition;
+        self.options.edition = rust_edition;
         self
     }
 
-    /// Runs the command.
-    pub fn run(&mut self) -> ::capnp::Result<()> {
+    /// Declares that the code for the schema files with IDs `file_ids` is generated by the crate
+    /// `crate_name`, so that generated code refers to those files' types as
+    /// `::crate_name::foo_capnp::...` instead of generating a copy of them. In the 2015
+    /// edition, the crate must be declared with `extern crate` in the crate root.
+    ///
+    /// The ID of a schema file is the `@0x...` at its top.
+    pub fn crate_provides<S, I>(&mut self, crate_name: S, file_ids: I) -> &mut Self
+        where S: Into<String>, I: IntoIterator<Item=u64>
+    {
+        let crate_name = crate_name.into();
+        for id in file_ids {
+            self.options.crates_provide_map.insert(id, crate_name.clone());
+        }
+        self
+    }
+
+    /// Sets where generated files are placed in the output directory. The default is
+    /// `OutputLayout::Tree`.
+    pub fn output_layout(&mut self, layout: OutputLayout) -> &mut Self {
+        self.options.output_layout = layout;
+        self
+    }
+
+    /// Sets the module in which generated code is placed for schema files that have no
+    /// `$Rust.parentModule` annotation, given as a path like `"schemas::generated"`.
+    pub fn default_parent_module<S>(&mut self, module: S) -> &mut Self
+        where S: AsRef<str>
+    {
+        self.options.default_parent_module = codegen::parse_module_path(module.as_ref());
+        self
+    }
+
+    /// If `value` is true, generates a `fill_random()` function for each struct, which
+    /// populates a `Builder` with arbitrary contents from a `quickcheck::Gen`. The generated
+    /// code requires the `quickcheck` feature of the `capnp` crate to be enabled.
+    pub fn fill_random(&mut self, value: bool) -> &mut Self {
+        self.options.fill_random = value;
+        self
+    }
+
+    /// If `value` is true, generates a `Native` type for each non-generic struct. It is a
+    /// plain owned mirror of the struct's contents that does not borrow from a message,
+    /// and it can be converted with `Native::from_reader()` and `Native::write_to_builder()`.
+    /// All schemas that refer to each other should be generated with the same setting.
+    pub fn native_types(&mut self, value: bool) -> &mut Self {
+        self.options.native_types = value;
+        self
+    }
+
+    /// If `value` is true, generates `Debug` implementations for struct readers and builders,
+    /// which show the fields by their names in the schema. Only the member of a union that
+    /// is set is shown, and long lists are truncated. All schemas that refer to each other
+    /// should be generated with the same setting.
+    pub fn debug_impls(&mut self, value: bool) -> &mut Self {
+        self.options.debug_impls = value;
+        self
+    }
+
+    /// If `value` is true, generates a `Mock` type in each interface module: a `Server`
+    /// for tests that records the parameters of the calls to each method, and answers them
+    /// with responses scripted through its public fields, which are shared between clones.
+    pub fn mock_servers(&mut self, value: bool) -> &mut Self {
+        self.options.mock_servers = value;
+        self
+    }
+
+    /// If `value` is true, generates serde implementations: struct readers and enums
+    /// implement `Serialize`, struct builders implement `DeserializeSeed`, which sets the
+    /// fields present in the input, and enums implement `Deserialize`. The generated code
+    /// requires the `serde` feature of the `capnp` crate to be enabled. All schemas that
+    /// refer to each other should be generated with the same setting.
+    pub fn serde(&mut self, value: bool) -> &mut Self {
+        self.options.serde = value;
+        self
+    }
+
+    /// Sets the directory where generated files are written. If it is not set, the
+    /// `OUT_DIR` environment variable, which Cargo sets for build scripts, is used.
+    pub fn output_path<P>(&mut self, path: P) -> &mut Self
+        where P: AsRef<Path>,
+    {
+        self.output_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Also generates a file at `path`, relative to the output directory, that declares the
+    /// modules of all generated files, nested according to their parent modules. It is meant
+    /// to be included in the module set by `default_parent_module()`, the crate root by
+    /// default. Files of modules outside of that module cause an error.
+    pub fn modules_file<P>(&mut self, path: P) -> &mut Self
+        where P: AsRef<Path>,
+    {
+        self.options.modules_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Generates code from the serialized `CodeGeneratorRequest` stored at `path`, instead of
+    /// running `capnp compile`, so that the `capnp` executable does not need to be installed.
+    /// Such a file can be produced with `capnp compile -o- foo.capnp > foo.request`. When it
+    /// is set, `file()`, `src_prefix()`, `import_path()` and `no_standard_import()` have no
+    /// effect.
+    pub fn request_file<P>(&mut self, path: P) -> &mut Self
+        where P: AsRef<Path>,
+    {
+        self.request_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// If `value` is true, compiles the schema files with the compiler in the `compiler`
+    /// module instead of running `capnp compile`, so that the `capnp` executable does not
+    /// need to be installed.
+    pub fn builtin_compiler(&mut self, value: bool) -> &mut Self {
+        self.builtin_compiler = value;
+        self
+    }
+
+    fn command(&self) -> ::std::process::Command {
         let mut command = ::std::process::Command::new("capnp");
         command.arg("compile").arg("-o").arg("-");
 
@@ -172,12 +337,304 @@ impl CompilerCommand {
 
         command.stdout(::std::process::Stdio::piped());
         command.stderr(::std::process::Stdio::inherit());
+        command
+    }
 
-        run_command(command, self.rust_edition).map_err(|error| {
+    fn run_compiler<F, R>(&self, generate: F) -> ::capnp::Result<R>
+        where F: FnOnce(Box<::std::io::Read>) -> ::capnp::Result<R>
+    {
+        if let Some(ref path) = self.request_file {
+            let file = ::std::fs::File::open(path).map_err(|error| {
+                ::capnp::Error::failed(format!(
+                    "Could not open code generator request file {}: {}", path.display(), error))
+            })?;
+            return generate(Box::new(::std::io::BufReader::new(file)));
+        }
+
+        if self.builtin_compiler {
+            let message = compiler::compile(&self.files, &self.src_prefixes, &self.import_paths,
+                                            self.no_standard_import)?;
+            let mut bytes = Vec::new();
+            ::capnp::serialize::write_message(&mut bytes, &message)?;
+            return generate(Box::new(::std::io::Cursor::new(bytes)));
+        }
+
+        run_command(self.command(), generate).map_err(|error| {
             ::capnp::Error::failed(format!(
                 "Error while trying to execute `capnp compile`: {}.  \
                  Please verify that version 0.5.2 or higher of the capnp executable \
                  is installed on your system. See https://capnproto.org/install.html",
                 error))})
     }
+
+    /// Finds the schema files that the serialized `CodeGeneratorRequest` in `request` was
+    /// generated from, including transitive imports. A file is looked up by its display name
+    /// in the current directory, the source prefixes and the import paths, in that order,
+    /// and left out if it is not found.
+    fn schema_files(&self, request: &[u8]) -> ::capnp::Result<Vec<PathBuf>> {
+        let message = ::capnp::serialize::read_message(&mut &request[..],
+                                                       ::capnp::message::ReaderOptions::new())?;
+        let request: schema_capnp::code_generator_request::Reader = message.get_root()?;
+
+        let mut dirs = vec![PathBuf::new()];
+        dirs.extend(self.src_prefixes.iter().cloned());
+        dirs.extend(self.import_paths.iter().cloned());
+        if !self.no_standard_import {
+            dirs.push(PathBuf::from("/usr/local/include"));
+            dirs.push(PathBuf::from("/usr/include"));
+        }
+
+        let mut result = match self.request_file {
+            Some(ref path) => vec![path.clone()],
+            None => self.files.clone(),
+        };
+        for node in request.get_nodes()?.iter() {
+            if let schema_capnp::node::File(()) = node.which()? {
+                let name = node.get_display_name()?.trim_start_matches('/');
+                if let Some(path) = dirs.iter().map(|dir| dir.join(name)).find(|path| path.is_file()) {
+                    if !result.contains(&path) {
+                        result.push(path);
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Runs the command, writing the generated code to the directory set by `output_path()`,
+    /// or to `OUT_DIR` if no output path was set. Files whose contents would not change are
+    /// not rewritten, so that their modification times are kept.
+    ///
+    /// When run from a Cargo build script, also prints a `cargo:rerun-if-changed` line for
+    /// each schema file and each file that it imports, directly or not, so that the build
+    /// script is rerun exactly when one of them changes, and a `cargo:warning` line for each
+    /// warning about the schemas.
+    pub fn run(&mut self) -> ::capnp::Result<()> {
+        let output_path = match self.output_path {
+            Some(ref path) => path.clone(),
+            None => match ::std::env::var_os("OUT_DIR") {
+                Some(dir) => PathBuf::from(dir),
+                None => return Err(::capnp::Error::failed(
+                    "No output directory: the OUT_DIR environment variable is not set. \
+                     Either run from a Cargo build script or call \
+                     CompilerCommand::output_path().".to_string())),
+            }
+        };
+        let options = self.options.clone();
+        let in_build_script = ::std::env::var_os("OUT_DIR").is_some();
+        self.run_compiler(|mut stdout| {
+            let mut request = Vec::new();
+            stdout.read_to_end(&mut request)?;
+            if in_build_script {
+                for path in self.schema_files(&request)? {
+                    println!("cargo:rerun-if-changed={}", path.display());
+                }
+            }
+            let (files, diagnostics) = ::codegen::generate_code_with_diagnostics(&request[..], options)?;
+            if in_build_script {
+                for diagnostic in &diagnostics {
+                    if diagnostic.severity == ::diagnostics::Severity::Warning {
+                        println!("cargo:warning={}", diagnostic);
+                    }
+                }
+            }
+            ::diagnostics::check(&diagnostics)?;
+            ::codegen::write_generated_files(&output_path, &files)
+        })
+    }
+
+    /// Runs the command and returns the generated code instead of writing it to disk. Each
+    /// element of the result holds the path of a generated file, relative to the output
+    /// directory, and its contents.
+    pub fn run_in_memory(&mut self) -> ::capnp::Result<Vec<(PathBuf, String)>> {
+        let options = self.options.clone();
+        self.run_compiler(|stdout| ::codegen::generate_code_in_memory(stdout, options))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::path::PathBuf;
+    use super::CompilerCommand;
+
+    #[test]
+    fn schema_files_include_imports() {
+        let mut command = CompilerCommand::new();
+        command.file("test/test.capnp").builtin_compiler(true);
+        let request = command.run_compiler(|mut stdout| {
+            let mut request = Vec::new();
+            stdout.read_to_end(&mut request)?;
+            Ok(request)
+        }).unwrap();
+        let files = command.schema_files(&request).unwrap();
+        assert_eq!(files, vec![PathBuf::from("test/test.capnp"), PathBuf::from("rust.capnp")]);
+    }
+
+    #[test]
+    fn modules_file_declares_nested_modules() {
+        let files = CompilerCommand::new()
+            .file("test/test.capnp")
+            .file("test/schema/test-in-dir.capnp")
+            .file("test/schema/test-in-parent-module.capnp")
+            .builtin_compiler(true)
+            .default_parent_module("schemas")
+            .modules_file("modules/mod.rs")
+            .run_in_memory().unwrap();
+        let &(_, ref text) = files.iter().find(|f| f.0 == PathBuf::from("modules/mod.rs")).unwrap();
+        let declarations: Vec<&str> = text.lines().skip(3).collect();
+        assert_eq!(declarations, vec![
+            "pub mod nested {",
+            "  pub mod test_in_parent_module_capnp {",
+            "    include!(\"../test/schema/test_in_parent_module_capnp.rs\");",
+            "  }",
+            "}",
+            "pub mod test_capnp {",
+            "  include!(\"../test/test_capnp.rs\");",
+            "}",
+            "pub mod test_in_dir_capnp {",
+            "  include!(\"../test/schema/test_in_dir_capnp.rs\");",
+            "}",
+        ]);
+    }
+
+    #[test]
+    fn modules_outside_of_the_modules_file_root_are_errors() {
+        let result = CompilerCommand::new()
+            .file("test/schema/test-in-dir.capnp")
+            .file("test/schema/test-in-parent-module.capnp")
+            .builtin_compiler(true)
+            .default_parent_module("generated")
+            .modules_file("mod.rs")
+            .run_in_memory();
+        let error = result.err().unwrap();
+        assert!(error.description.contains(
+            "module schemas::nested::test_in_parent_module_capnp is not inside generated"));
+        assert!(!error.description.contains("test_in_dir_capnp"));
+    }
+
+    #[test]
+    fn modules_outside_of_the_default_parent_module_are_fine_without_a_modules_file() {
+        CompilerCommand::new()
+            .file("test/schema/test-in-parent-module.capnp")
+            .builtin_compiler(true)
+            .default_parent_module("generated")
+            .run_in_memory().unwrap();
+    }
+
+    #[test]
+    fn provided_files_are_referred_to_through_their_crate() {
+        let files = CompilerCommand::new()
+            .file("test/schema/test-in-parent-module.capnp")
+            .builtin_compiler(true)
+            .default_parent_module("generated")
+            .crate_provides("other_crate", vec![0x997ccd7814c156ca])
+            .run_in_memory().unwrap();
+        assert_eq!(files.len(), 1);
+        let text = &files[0].1;
+        assert!(text.contains("::other_crate::test_in_dir_capnp::foo::Reader"));
+        assert!(!text.contains("::other_crate::generated"));
+        assert!(!text.contains("::other_crate::schemas"));
+    }
+
+    #[test]
+    fn doc_comments_are_emitted() {
+        let files = CompilerCommand::new()
+            .file("test/schema/test-doc-comments.capnp")
+            .builtin_compiler(true)
+            .run_in_memory().unwrap();
+        let lines: Vec<&str> = files[0].1.lines().map(|line| line.trim()).collect();
+        let has_lines = |expected: &[&str]| lines.windows(expected.len()).any(|w| w == expected);
+        assert!(has_lines(&["/// A struct with a comment.", "///", "/// It has two paragraphs.",
+                            "pub mod documented {"]));
+        assert!(has_lines(&["/// The comment of a field.", "#[inline]", "pub fn get_field(self) -> i32 {"]));
+        assert!(has_lines(&["/// The comment of an interface.", "pub mod documented_interface {"]));
+        let method_docs: Vec<&str> = lines.windows(2)
+            .filter(|w| w[0] == "/// The comment of a method.")
+            .map(|w| w[1])
+            .collect();
+        assert_eq!(method_docs.len(), 2);
+        assert!(method_docs[0].starts_with("pub fn method_request(&self)"));
+        assert!(method_docs[1].starts_with("fn method(&mut self"));
+    }
+
+    #[test]
+    fn types_from_files_imported_through_other_files_are_found() {
+        let files = CompilerCommand::new()
+            .file("test/schema/test-nested-import.capnp")
+            .builtin_compiler(true)
+            .run_in_memory()
+            .unwrap();
+        assert_eq!(files.len(), 1);
+        for path in &["::test_nested_import_c_capnp::foo::Reader",
+                      "::test_nested_import_c_capnp::FooEnum",
+                      "::test_nested_import_c_capnp::foo_interface::Client"] {
+            assert!(files[0].1.contains(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn run_writes_the_files_of_run_in_memory_to_output_path() {
+        let dir = ::std::env::temp_dir().join(format!("capnpc-output-test-{}", ::std::process::id()));
+        let mut command = CompilerCommand::new();
+        command.file("test/schema/test-in-dir.capnp").builtin_compiler(true).output_path(&dir);
+        command.run().unwrap();
+        let files = command.run_in_memory().unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].0, PathBuf::from("test/schema/test_in_dir_capnp.rs"));
+        assert_eq!(::std::fs::read_to_string(dir.join(&files[0].0)).unwrap(), files[0].1);
+        ::std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn child_is_waited_on_after_generation_fails() {
+        // The child exits some time after generation has failed.
+        let marker = ::std::env::temp_dir().join(format!("capnpc-wait-test-{}", ::std::process::id()));
+        let mut command = ::std::process::Command::new("sh");
+        command.arg("-c").arg(format!("sleep 0.2; touch '{}'", marker.display()))
+            .stdout(::std::process::Stdio::piped());
+        let result: ::capnp::Result<()> = super::run_command(command, |_| {
+            Err(::capnp::Error::failed("generation failed".to_string()))
+        });
+        assert_eq!(result.err().unwrap().description, "generation failed");
+        assert!(marker.exists());
+        ::std::fs::remove_file(&marker).unwrap();
+    }
+
+    #[test]
+    fn request_file_generates_the_same_code_as_capnp_output() {
+        let mut command = CompilerCommand::new();
+        command.file("test/schema/test-in-dir.capnp").builtin_compiler(true);
+        let request = command.run_compiler(|mut stdout| {
+            let mut request = Vec::new();
+            stdout.read_to_end(&mut request)?;
+            Ok(request)
+        }).unwrap();
+        let path = ::std::env::temp_dir().join(format!("capnpc-request-test-{}.request", ::std::process::id()));
+        ::std::fs::write(&path, &request).unwrap();
+
+        let from_file = CompilerCommand::new().request_file(&path).run_in_memory().unwrap();
+        let from_compiler = command.run_in_memory().unwrap();
+        assert_eq!(from_file, from_compiler);
+        ::std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unchanged_files_are_not_rewritten() {
+        let dir = ::std::env::temp_dir().join(format!("capnpc-write-test-{}", ::std::process::id()));
+        let files = vec![(PathBuf::from("foo_capnp.rs"), "// foo".to_string())];
+        ::codegen::write_generated_files(&dir, &files).unwrap();
+        let path = dir.join("foo_capnp.rs");
+        let modified = ::std::fs::metadata(&path).unwrap().modified().unwrap();
+
+        ::std::thread::sleep(::std::time::Duration::from_millis(20));
+        ::codegen::write_generated_files(&dir, &files).unwrap();
+        assert_eq!(::std::fs::metadata(&path).unwrap().modified().unwrap(), modified);
+
+        let files = vec![(PathBuf::from("foo_capnp.rs"), "// bar".to_string())];
+        ::codegen::write_generated_files(&dir, &files).unwrap();
+        assert_eq!(::std::fs::read_to_string(&path).unwrap(), "// bar");
+        ::std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -23,14 +23,242 @@
 //!
 //! [See this.](https://capnproto.org/otherlang.html#how-to-write-compiler-plugins)
 //!
+//! When invoked with a path argument, reads a serialized `CodeGeneratorRequest` from that
+//! file instead of from stdin, so that code can be generated from a request produced
+//! elsewhere by `capnp compile -o-`.
+//!
+//! Warnings and errors about the schemas are printed to stderr. If there are errors, no
+//! files are written and the exit status is 1.
+//!
+//! Code generation can be configured by options that precede the path:
+//!
+//! ```text
+//! --edition=2015|2018         Rust edition of the generated code (default 2015).
+//! --layout=tree|flat          Whether generated files mirror the directories of their
+//!                             schema files (default tree) or are all placed directly in
+//!                             the output directory.
+//! --parent-module=PATH        Module in which code is generated for schema files without
+//!                             a $Rust.parentModule annotation, like `schemas::generated`.
+//! --modules-file=PATH         Also generate a file at PATH that declares the modules of all
+//!                             generated files, to be included in the parent module.
+//! --crate-provides=CRATE:IDS  The files with the comma-separated IDS are generated by CRATE.
+//! --fill-random               Generate fill_random() functions.
+//! --native-types              Generate Native types.
+//! --debug-impls               Generate Debug implementations for struct readers and builders.
+//! --mock-servers              Generate Mock servers for interfaces.
+//! --serde                     Generate serde implementations, which need the serde feature
+//!                             of the capnp crate.
+//! ```
+//!
+//! `capnp compile -orust:outdir` does not pass arguments to plugins, so options can also be
+//! given in the `CAPNPC_RUST_OPTIONS` environment variable, separated by whitespace. Options
+//! on the command line take precedence.
+//!
+//! `capnpc-rust check-compatibility OLD NEW` instead compares two serialized
+//! `CodeGeneratorRequest`s, for the old and new versions of a set of schemas, and prints
+//! the changes that break wire compatibility. It exits with status 1 if there are any, and
+//! with status 2 if the requests cannot be read.
 //!
 
 #![crate_type = "bin"]
 
+extern crate capnp;
 extern crate capnpc;
 
+use capnpc::codegen::{self, GeneratorOptions};
+use capnpc::diagnostics::Severity;
+use capnpc::{OutputLayout, RustEdition};
+
+fn parse_id(text: &str) -> Result<u64, String> {
+    let result = if text.starts_with("0x") {
+        u64::from_str_radix(&text[2..], 16)
+    } else {
+        text.parse()
+    };
+    result.map_err(|_| format!("invalid file ID: {}", text))
+}
+
+/// Applies the option `arg` to `options`.
+fn apply_option(options: &mut GeneratorOptions, arg: &str) -> Result<(), String> {
+    let (name, value) = match arg.find('=') {
+        Some(i) => (&arg[..i], Some(&arg[i + 1..])),
+        None => (arg, None),
+    };
+    match (name, value) {
+        ("--edition", Some("2015")) => options.edition = RustEdition::Rust2015,
+        ("--edition", Some("2018")) => options.edition = RustEdition::Rust2018,
+        ("--layout", Some("tree")) => options.output_layout = OutputLayout::Tree,
+        ("--layout", Some("flat")) => options.output_layout = OutputLayout::Flat,
+        ("--parent-module", Some(path)) => options.default_parent_module = codegen::parse_module_path(path),
+        ("--modules-file", Some(path)) => options.modules_file = Some(path.into()),
+        ("--crate-provides", Some(spec)) => {
+            let colon = spec.find(':').ok_or_else(|| format!("expected CRATE:IDS, got {}", spec))?;
+            for id in spec[colon + 1..].split(',') {
+                options.crates_provide_map.insert(parse_id(id)?, spec[..colon].to_string());
+            }
+        }
+        ("--fill-random", None) => options.fill_random = true,
+        ("--native-types", None) => options.native_types = true,
+        ("--debug-impls", None) => options.debug_impls = true,
+        ("--mock-servers", None) => options.mock_servers = true,
+        ("--serde", None) => options.serde = true,
+        _ => return Err(format!("unrecognized option: {}", arg)),
+    }
+    Ok(())
+}
+
+/// Reads a serialized `CodeGeneratorRequest` from the file at `path`.
+fn read_request(path: &::std::ffi::OsStr)
+                -> Result<capnp::message::Reader<capnp::serialize::OwnedSegments>, String>
+{
+    let file = ::std::fs::File::open(path).map_err(|e| format!("could not open {:?}: {}", path, e))?;
+    capnp::serialize::read_message(&mut ::std::io::BufReader::new(file), capnp::message::ReaderOptions::new())
+        .map_err(|e| format!("could not read {:?}: {}", path, e))
+}
+
+/// Implements `check-compatibility OLD NEW`, returning whether the schemas are compatible.
+fn check_compatibility(args: &[::std::ffi::OsString]) -> Result<bool, String> {
+    if args.len() != 2 {
+        return Err("usage: capnpc-rust check-compatibility OLD NEW".to_string());
+    }
+    let old = read_request(&args[0])?;
+    let new = read_request(&args[1])?;
+    let incompatibilities = (|| {
+        capnpc::compatibility::check_compatibility(old.get_root()?, new.get_root()?)
+    })().map_err(|e| format!("{}", e))?;
+    for incompatibility in &incompatibilities {
+        println!("{}", incompatibility);
+    }
+    Ok(incompatibilities.is_empty())
+}
+
+/// Opens the serialized `CodeGeneratorRequest` in the file at `path`, or stdin if there is
+/// no path.
+fn open_request(path: Option<&::std::ffi::OsStr>) -> Result<Box<::std::io::Read>, String> {
+    match path {
+        Some(path) => {
+            let file = ::std::fs::File::open(path).map_err(|e| format!("could not open {:?}: {}", path, e))?;
+            Ok(Box::new(::std::io::BufReader::new(file)))
+        }
+        None => Ok(Box::new(::std::io::stdin())),
+    }
+}
+
 pub fn main() {
-    //! Generate Rust code according to a `schema_capnp::code_generator_request` read from stdin.
+    //! Generate Rust code according to a `schema_capnp::code_generator_request` read from stdin,
+    //! or from the file named by the last argument.
+
+    let all_args: Vec<::std::ffi::OsString> = ::std::env::args_os().skip(1).collect();
+    if all_args.first().map_or(false, |arg| arg == "check-compatibility") {
+        match check_compatibility(&all_args[1..]) {
+            Ok(true) => return,
+            Ok(false) => ::std::process::exit(1),
+            Err(e) => {
+                eprintln!("{}", e);
+                ::std::process::exit(2);
+            }
+        }
+    }
+
+    let mut options = GeneratorOptions::new();
+    let mut args: Vec<String> = match ::std::env::var("CAPNPC_RUST_OPTIONS") {
+        Ok(value) => value.split_whitespace().map(|s| s.to_string()).collect(),
+        Err(_) => Vec::new(),
+    };
+    let mut path = None;
+    for arg in all_args {
+        match arg.into_string() {
+            Ok(ref arg) if arg.starts_with("--") => args.push(arg.clone()),
+            Ok(arg) => path = Some(::std::ffi::OsString::from(arg)),
+            Err(arg) => path = Some(arg),
+        }
+    }
+    for arg in &args {
+        if let Err(e) = apply_option(&mut options, arg) {
+            eprintln!("{}", e);
+            ::std::process::exit(1);
+        }
+    }
+
+    let out_dir = ::std::path::Path::new(".");
+    let input = match open_request(path.as_ref().map(|p| p.as_os_str())) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("{}", e);
+            ::std::process::exit(1);
+        }
+    };
+    let (files, diagnostics) = match codegen::generate_code_with_diagnostics(input, options) {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("{}", e.description);
+            ::std::process::exit(1);
+        }
+    };
+    for diagnostic in &diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        ::std::process::exit(1);
+    }
+    if let Err(e) = codegen::write_generated_files(out_dir, &files) {
+        eprintln!("{}", e.description);
+        ::std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use capnpc::codegen::{self, GeneratorOptions};
+
+    #[test]
+    fn request_from_path_argument_generates_the_same_code_as_stdin() {
+        let request = capnpc::compiler::compile(
+            &["test/schema/test-in-dir.capnp".into()], &[], &[], true).unwrap();
+        let mut bytes = Vec::new();
+        capnp::serialize::write_message(&mut bytes, &request).unwrap();
+        let path = ::std::env::temp_dir().join(format!("capnpc-main-test-{}.request", ::std::process::id()));
+        ::std::fs::write(&path, &bytes).unwrap();
+
+        let from_path = codegen::generate_code_in_memory(
+            super::open_request(Some(path.as_os_str())).unwrap(), GeneratorOptions::new()).unwrap();
+        let from_stdin = codegen::generate_code_in_memory(&bytes[..], GeneratorOptions::new()).unwrap();
+        assert_eq!(from_path, from_stdin);
+        ::std::fs::remove_file(&path).unwrap();
+
+        assert!(super::open_request(Some(path.as_os_str())).is_err());
+    }
+
+    #[test]
+    fn apply_option_sets_options() {
+        let mut options = GeneratorOptions::new();
+        for arg in &["--edition=2018", "--layout=flat", "--parent-module=schemas::generated",
+                     "--modules-file=schemas.rs", "--crate-provides=other:0xbd1d2a23b3ac0b93,0xd0b1bd6e3e11e2e5",
+                     "--fill-random", "--native-types", "--debug-impls", "--mock-servers", "--serde"] {
+            super::apply_option(&mut options, arg).unwrap();
+        }
+        match options.edition {
+            ::capnpc::RustEdition::Rust2018 => (),
+            _ => panic!("expected the 2018 edition"),
+        }
+        assert_eq!(options.output_layout, ::capnpc::OutputLayout::Flat);
+        assert_eq!(options.default_parent_module, vec!["schemas".to_string(), "generated".to_string()]);
+        assert_eq!(options.modules_file, Some("schemas.rs".into()));
+        assert_eq!(options.crates_provide_map.len(), 2);
+        assert_eq!(options.crates_provide_map[&0xd0b1bd6e3e11e2e5], "other");
+        assert!(options.fill_random && options.native_types && options.debug_impls
+                && options.mock_servers && options.serde);
+    }
 
-    ::capnpc::codegen::main(::std::io::stdin(), ::std::path::Path::new(".")).unwrap();
+    #[test]
+    fn apply_option_rejects_bad_options() {
+        let mut options = GeneratorOptions::new();
+        for arg in &["--unknown", "--unknown=value", "--edition=2017", "--layout", "--modules-file",
+                     "--serde=yes", "--crate-provides=0xbd1d2a23b3ac0b93",
+                     "--crate-provides=other:0xzz"] {
+            assert!(super::apply_option(&mut options, arg).is_err(), "{}", arg);
+        }
+        assert_eq!(super::apply_option(&mut options, "--unknown").unwrap_err(),
+                   "unrecognized option: --unknown");
+    }
 }
@@ -0,0 +1,317 @@
+// Copyright (c) 2013-2018 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Generation of `Native` types: plain owned Rust values that mirror a struct's
+//! contents and that can be converted to and from its `Reader` and `Builder`.
+//!
+//! Generic structs, and fields whose values cannot be owned (interfaces,
+//! `AnyPointer`s, and instances of generic structs), are not mirrored. A warning
+//! is reported for each such field.
+
+use capnp::Error;
+
+use codegen::{capitalize_first_letter, camel_to_snake_case, get_field_name, module_name, FormattedText, GeneratorContext};
+use codegen::FormattedText::{Indent, Line, Branch, BlankLine};
+use codegen_types::RustTypeInfo;
+use diagnostics::Severity;
+use schema_capnp::{field, node, type_};
+
+/// Returns whether the struct with ID `node_id` gets a `Native` type.
+pub fn has_native_type(gen: &GeneratorContext, node_id: u64) -> ::capnp::Result<bool> {
+    let node_reader = gen.get_node(node_id)?;
+    match node_reader.which()? {
+        node::Struct(_) => Ok(!node_reader.get_is_generic()),
+        _ => Ok(false),
+    }
+}
+
+/// Returns the owned type that mirrors a value of type `typ`, or `None` if there is none.
+fn native_type(gen: &GeneratorContext, typ: type_::Reader) -> ::capnp::Result<Option<String>> {
+    Ok(match typ.which()? {
+        type_::Void(()) => Some("()".to_string()),
+        type_::Bool(()) => Some("bool".to_string()),
+        type_::Int8(()) => Some("i8".to_string()),
+        type_::Int16(()) => Some("i16".to_string()),
+        type_::Int32(()) => Some("i32".to_string()),
+        type_::Int64(()) => Some("i64".to_string()),
+        type_::Uint8(()) => Some("u8".to_string()),
+        type_::Uint16(()) => Some("u16".to_string()),
+        type_::Uint32(()) => Some("u32".to_string()),
+        type_::Uint64(()) => Some("u64".to_string()),
+        type_::Float32(()) => Some("f32".to_string()),
+        type_::Float64(()) => Some("f64".to_string()),
+        type_::Text(()) => Some("::std::string::String".to_string()),
+        type_::Data(()) => Some("::std::vec::Vec<u8>".to_string()),
+        type_::Enum(e) => Some(gen.get_scope(e.get_type_id())?.join("::")),
+        type_::Struct(st) => {
+            if !typ.is_branded()? && has_native_type(gen, st.get_type_id())? {
+                Some(format!("{}::Native", gen.get_scope(st.get_type_id())?.join("::")))
+            } else {
+                None
+            }
+        }
+        type_::List(ot) => {
+            native_type(gen, ot.get_element_type()?)?.map(|t| format!("::std::vec::Vec<{}>", t))
+        }
+        type_::Interface(_) | type_::AnyPointer(_) => None,
+    })
+}
+
+/// Returns an expression converting `expr`, as returned by a reader getter or by
+/// a list reader's `get()`, into its native type. Struct values are expected to come
+/// from a struct list, i.e. not to be wrapped in a `Result`.
+fn read_value(gen: &GeneratorContext, typ: type_::Reader, expr: &str, nesting: usize)
+              -> ::capnp::Result<String>
+{
+    Ok(match typ.which()? {
+        type_::Enum(_) => format!("{}?", expr),
+        type_::Text(()) => format!("{}?.to_string()", expr),
+        type_::Data(()) => format!("{}?.to_vec()", expr),
+        type_::Struct(st) => {
+            format!("{}::Native::from_reader({})?", gen.get_scope(st.get_type_id())?.join("::"), expr)
+        }
+        type_::List(ot) => {
+            let element = read_value(gen, ot.get_element_type()?,
+                                     &format!("l{}.get(i{})", nesting, nesting), nesting + 1)?;
+            format!("{{ let l{0} = {1}?; let mut v{0} = ::std::vec::Vec::with_capacity(l{0}.len() as usize); \
+                     for i{0} in 0..l{0}.len() {{ v{0}.push({2}); }} v{0} }}",
+                    nesting, expr, element)
+        }
+        _ => expr.to_string(),
+    })
+}
+
+/// Returns statements that copy the elements of `v{nesting}` into the list builder `l{nesting}`.
+fn write_list(gen: &GeneratorContext, element_type: type_::Reader, nesting: usize)
+              -> ::capnp::Result<Vec<FormattedText>>
+{
+    let (list, index, value) = (format!("l{}", nesting), format!("i{}", nesting + 1), format!("v{}", nesting + 1));
+    let body = match element_type.which()? {
+        type_::Void(()) => return Ok(Vec::new()),
+        type_::Text(()) | type_::Data(()) => {
+            vec![Line(format!("{}.set({} as u32, {});", list, index, value))]
+        }
+        type_::Struct(_) => {
+            vec![Line(format!("{}.write_to_builder({}.reborrow().get({} as u32))?;", value, list, index))]
+        }
+        type_::List(ot) => {
+            let mut result = vec![
+                Line(format!("let mut l{} = {}.reborrow().init({} as u32, {}.len() as u32);",
+                             nesting + 1, list, index, value))];
+            result.extend(write_list(gen, ot.get_element_type()?, nesting + 1)?);
+            result
+        }
+        _ => vec![Line(format!("{}.set({} as u32, *{});", list, index, value))],
+    };
+    Ok(vec![
+        Line(format!("for ({}, {}) in v{}.iter().enumerate() {{", index, value, nesting)),
+        Indent(Box::new(Branch(body))),
+        Line("}".to_string()),
+    ])
+}
+
+/// Returns statements that write the native value of a slot field to `builder`. The value is
+/// available both as a reference `r` and as a place expression `place`.
+fn write_slot(gen: &GeneratorContext, typ: type_::Reader, styled_name: &str, r: &str, place: &str)
+              -> ::capnp::Result<Vec<FormattedText>>
+{
+    Ok(match typ.which()? {
+        type_::Void(()) => vec![Line(format!("builder.set_{}(());", styled_name))],
+        type_::Text(()) | type_::Data(()) => vec![Line(format!("builder.set_{}({});", styled_name, r))],
+        type_::List(ot) => {
+            let mut result = vec![
+                Line(format!("let v0 = {};", r)),
+                Line(format!("let mut l0 = builder.reborrow().init_{}(v0.len() as u32);", styled_name))];
+            result.extend(write_list(gen, ot.get_element_type()?, 0)?);
+            vec![Line("{".to_string()), Indent(Box::new(Branch(result))), Line("}".to_string())]
+        }
+        type_::Struct(_) => {
+            vec![Line(format!("{}.write_to_builder(builder.reborrow().init_{}())?;", r, styled_name))]
+        }
+        _ => vec![Line(format!("builder.set_{}({});", styled_name, place))],
+    })
+}
+
+/// Generates the `Native` type of the struct with ID `node_id`, along with its conversions.
+pub fn generate_native_type(gen: &GeneratorContext, node_id: u64) -> ::capnp::Result<FormattedText> {
+    let struct_reader = match gen.get_node(node_id)?.which()? {
+        node::Struct(s) => s,
+        _ => return Err(Error::failed(format!("generate_native_type() expected a struct"))),
+    };
+
+    let mut members = Vec::new();
+    let mut variants = Vec::new();
+    let mut read_members = Vec::new();
+    let mut read_arms = Vec::new();
+    let mut write_members = Vec::new();
+    let mut write_arms = Vec::new();
+    let mut member_names = Vec::new();
+
+    for field in struct_reader.get_fields()?.iter() {
+        let name = get_field_name(field)?;
+        let styled_name = camel_to_snake_case(name);
+        let member_name = module_name(name);
+        let variant = capitalize_first_letter(name);
+        let is_union_field = field.get_discriminant_value() != field::NO_DISCRIMINANT;
+
+        match field.which()? {
+            field::Group(group) => {
+                let the_mod = gen.get_scope(group.get_type_id())?.join("::");
+                if is_union_field {
+                    variants.push(Line(format!("{}({}::Native),", variant, the_mod)));
+                    read_arms.push(Line(format!("Which::{}(v) => NativeWhich::{}({}::Native::from_reader(v)?),",
+                                                variant, variant, the_mod)));
+                    write_arms.push(Line(format!("NativeWhich::{}(ref v) => v.write_to_builder(builder.reborrow().init_{}())?,",
+                                                 variant, styled_name)));
+                } else {
+                    members.push(Line(format!("pub {}: {}::Native,", member_name, the_mod)));
+                    member_names.push(member_name.clone());
+                    read_members.push(Line(format!("{}: {}::Native::from_reader(reader.get_{}())?,",
+                                                   member_name, the_mod, styled_name)));
+                    write_members.push(Line(format!("self.{}.write_to_builder(builder.reborrow().get_{}())?;",
+                                                    member_name, styled_name)));
+                }
+            }
+            field::Slot(slot) => {
+                let typ = slot.get_type()?;
+                let native = native_type(gen, typ)?;
+                let is_void = match typ.which()? { type_::Void(()) => true, _ => false };
+                let is_struct = match typ.which()? { type_::Struct(_) => true, _ => false };
+
+                if native.is_none() && !is_void {
+                    gen.set_member(Some(name));
+                    gen.report(Severity::Warning, if is_union_field {
+                        "the Native type records when this union member is set, but not its value".to_string()
+                    } else {
+                        "this field is left out of the Native type, because its value cannot be owned".to_string()
+                    });
+                    gen.set_member(None);
+                }
+
+                if is_union_field {
+                    match native {
+                        Some(ref native) if !is_void => {
+                            let (native, expr) = if is_struct {
+                                (format!("::std::boxed::Box<{}>", native),
+                                 format!("::std::boxed::Box::new({}::from_reader(v?)?)", native))
+                            } else {
+                                (native.clone(), read_value(gen, typ, "v", 0)?)
+                            };
+                            variants.push(Line(format!("{}({}),", variant, native)));
+                            read_arms.push(Line(format!("Which::{}(v) => NativeWhich::{}({}),", variant, variant, expr)));
+                            write_arms.push(Branch(vec![
+                                Line(format!("NativeWhich::{}(ref v) => {{", variant)),
+                                Indent(Box::new(Branch(write_slot(gen, typ, &styled_name, "v", "*v")?))),
+                                Line("}".to_string())]));
+                        }
+                        _ => {
+                            variants.push(Line(format!("{},", variant)));
+                            read_arms.push(Line(format!("Which::{}(_) => NativeWhich::{},", variant, variant)));
+                            let set_discriminant = Line(format!(
+                                "builder.builder.set_data_field::<u16>({}, {});",
+                                struct_reader.get_discriminant_offset(), field.get_discriminant_value()));
+                            let write = if is_void {
+                                vec![Line(format!("builder.set_{}(());", styled_name))]
+                            } else {
+                                // The value is not mirrored, so all we can do is select the member.
+                                vec![set_discriminant,
+                                     Line(format!("builder.builder.get_pointer_field({}).clear();", slot.get_offset()))]
+                            };
+                            write_arms.push(Branch(vec![
+                                Line(format!("NativeWhich::{} => {{", variant)),
+                                Indent(Box::new(Branch(write))),
+                                Line("}".to_string())]));
+                        }
+                    }
+                } else if let Some(native) = native {
+                    if is_void { continue }
+                    let getter = format!("reader.get_{}()", styled_name);
+                    let (native, expr) = if is_struct {
+                        (format!("::std::option::Option<::std::boxed::Box<{}>>", native),
+                         format!("if reader.has_{}() {{ ::std::option::Option::Some(::std::boxed::Box::new({}::from_reader({}?)?)) }} \
+                                  else {{ ::std::option::Option::None }}",
+                                 styled_name, native, getter))
+                    } else {
+                        (native, read_value(gen, typ, &getter, 0)?)
+                    };
+                    members.push(Line(format!("pub {}: {},", member_name, native)));
+                    read_members.push(Line(format!("{}: {},", member_name, expr)));
+                    member_names.push(member_name.clone());
+                    let write = if is_struct {
+                        vec![Line(format!("if let ::std::option::Option::Some(ref v) = self.{} {{", member_name)),
+                             Indent(Box::new(Branch(write_slot(gen, typ, &styled_name, "v", "*v")?))),
+                             Line("}".to_string())]
+                    } else {
+                        write_slot(gen, typ, &styled_name,
+                                   &format!("&self.{}", member_name), &format!("self.{}", member_name))?
+                    };
+                    write_members.extend(write);
+                }
+            }
+        }
+    }
+
+    let mut result = Vec::new();
+    if !variants.is_empty() {
+        // A field named `which` takes precedence over the union.
+        let which = if member_names.iter().any(|n| n == "which") { "which_" } else { "which" };
+        members.push(Line(format!("pub {}: NativeWhich,", which)));
+        read_members.push(Line(format!("{}: match reader.which()? {{", which)));
+        read_members.push(Indent(Box::new(Branch(read_arms))));
+        read_members.push(Line("},".to_string()));
+        write_members.push(Line(format!("match self.{} {{", which)));
+        write_members.push(Indent(Box::new(Branch(write_arms))));
+        write_members.push(Line("}".to_string()));
+
+        result.push(Branch(vec![
+            Line("#[derive(Clone, Debug, PartialEq)]".to_string()),
+            Line("pub enum NativeWhich {".to_string()),
+            Indent(Box::new(Branch(variants))),
+            Line("}".to_string()),
+            BlankLine]));
+    }
+
+    result.push(Branch(vec![
+        Line("/// An owned copy of the contents of a `Reader`.".to_string()),
+        Line("#[derive(Clone, Debug, PartialEq)]".to_string()),
+        Line("pub struct Native {".to_string()),
+        Indent(Box::new(Branch(members))),
+        Line("}".to_string()),
+        BlankLine,
+        Line("impl Native {".to_string()),
+        Indent(Box::new(Branch(vec![
+            Line("#[allow(unused_variables)]".to_string()),
+            Line("pub fn from_reader(reader: Reader) -> ::capnp::Result<Native> {".to_string()),
+            Indent(Box::new(Branch(vec![
+                Line("::std::result::Result::Ok(Native {".to_string()),
+                Indent(Box::new(Branch(read_members))),
+                Line("})".to_string())]))),
+            Line("}".to_string()),
+            BlankLine,
+            Line("#[allow(unused_mut, unused_variables)]".to_string()),
+            Line("pub fn write_to_builder(&self, mut builder: Builder) -> ::capnp::Result<()> {".to_string()),
+            Indent(Box::new(Branch(write_members))),
+            Indent(Box::new(Line("::std::result::Result::Ok(())".to_string()))),
+            Line("}".to_string())]))),
+        Line("}".to_string())]));
+
+    Ok(Branch(result))
+}
@@ -46,9 +46,15 @@ pub fn generate_pointer_constant(
                     bytes[0], bytes[1], bytes[2], bytes[3],
                     bytes[4], bytes[5], bytes[6], bytes[7])));
     }
+    let type_string = if try!(typ.is_parameter()) {
+        // Generic parameters are not in scope at the constant's definition.
+        "::capnp::any_pointer::Owned".to_string()
+    } else {
+        try!(typ.type_string(gen, Leaf::Owned))
+    };
     Ok(Branch(vec![
         Line(format!("pub static {}: ::capnp::constant::Reader<{}> = {{",
-                     styled_name, try!(typ.type_string(gen, Leaf::Owned)))),
+                     styled_name, type_string)),
         Indent(Box::new(Branch(vec![
             Line(format!("static WORDS: [::capnp::Word; {}] = [", words.len())),
             Indent(Box::new(Branch(words_lines))),
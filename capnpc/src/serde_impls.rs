@@ -0,0 +1,319 @@
+// Copyright (c) 2013-2018 Sandstorm Development Group, Inc. and contributors
+// Licensed under the MIT License:
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Generation of serde implementations. Struct readers implement `Serialize`, with fields
+//! named as in the schema. A struct that consists of nothing but a union serializes as an
+//! externally tagged enum; otherwise the member of the union that is set appears among the
+//! other fields. Struct builders implement `DeserializeSeed`, which sets the fields that
+//! are present. Interface fields are left out in both directions.
+
+use capnp::Error;
+
+use codegen::{camel_to_snake_case, capitalize_first_letter, get_field_name, FormattedText, GeneratorContext};
+use codegen::FormattedText::{Indent, Line, Branch};
+use codegen_types::{Leaf, RustTypeInfo, TypeParameterTexts};
+use schema_capnp::{field, node, type_};
+
+const SER_ERROR: &'static str = "::capnp::serde::ser::Error::custom";
+const DE_ERROR: &'static str = "::capnp::serde::de::Error::custom";
+
+/// Returns statements that bind `v` to the value of `field`, and an expression that refers
+/// to it as a `Serialize` value. `value` is the expression returned by the field's getter,
+/// or by `which()` for a union member. Returns `None` for an interface field.
+fn serialize_value(field: &field::Reader, value: &str)
+                   -> ::capnp::Result<Option<(Vec<FormattedText>, String)>> {
+    let slot = match field.which()? {
+        field::Group(_) => return Ok(Some((Vec::new(), format!("&{}", value)))),
+        field::Slot(slot) => slot,
+    };
+    let typ = slot.get_type()?;
+    let bind = vec![Line(format!("let v = {}.map_err({})?;", value, SER_ERROR))];
+    Ok(Some(match typ.which()? {
+        type_::Void(()) => (Vec::new(), "&()".to_string()),
+        type_::Interface(_) => return Ok(None),
+        type_::AnyPointer(_) if !typ.is_parameter()? => (Vec::new(), format!("&{}", value)),
+        type_::Data(()) => (bind, "&::capnp::serde_support::Bytes(v)".to_string()),
+        type_::Struct(_) if !slot.get_had_explicit_default() => {
+            // An unset struct field reads as an empty struct, whose own struct fields would
+            // be serialized in turn without end.
+            (vec![Line(format!(
+                "let v = if self.reader.get_pointer_field({}).is_null() {{ None }} else {{ Some({}.map_err({})?) }};",
+                slot.get_offset(), value, SER_ERROR))],
+             "&v".to_string())
+        }
+        _ if typ.is_prim()? => (Vec::new(), format!("&{}", value)),
+        _ => (bind, "&v".to_string()),
+    }))
+}
+
+/// Whether the elements of a list of type `typ` can be deserialized, which rules out
+/// capabilities and pointers of unknown type.
+fn is_deserializable_list(typ: &type_::Reader) -> ::capnp::Result<bool> {
+    match typ.which()? {
+        type_::List(ot) => {
+            let element_type = ot.get_element_type()?;
+            match element_type.which()? {
+                type_::Interface(_) | type_::AnyPointer(_) => Ok(false),
+                type_::List(_) => is_deserializable_list(&element_type),
+                _ => Ok(true),
+            }
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Returns the statements that set `field` from the next value of the `MapAccess` named
+/// `map`, or `None` for an interface field.
+fn deserialize_field(gen: &GeneratorContext, field: &field::Reader)
+                     -> ::capnp::Result<Option<FormattedText>> {
+    let styled_name = camel_to_snake_case(get_field_name(*field)?);
+    let is_union_member = field.get_discriminant_value() != field::NO_DISCRIMINANT;
+    let slot = match field.which()? {
+        field::Group(_) => {
+            let getter = if is_union_member { "init" } else { "get" };
+            return Ok(Some(Branch(vec![
+                Line("let builder = self.reborrow();".to_string()),
+                Line(format!("map.next_value_seed(::capnp::serde_support::StructSeed(move || builder.{}_{}()))?;",
+                             getter, styled_name))])))
+        }
+        field::Slot(slot) => slot,
+    };
+    let typ = slot.get_type()?;
+    Ok(Some(match typ.which()? {
+        type_::Interface(_) => return Ok(None),
+        type_::Void(()) => Branch(vec![
+            Line("map.next_value::<()>()?;".to_string()),
+            Line(format!("self.set_{}(());", styled_name))]),
+        type_::Text(()) => Branch(vec![
+            Line("let v: String = map.next_value()?;".to_string()),
+            Line(format!("self.set_{}(&v);", styled_name))]),
+        type_::Data(()) => Branch(vec![
+            Line("let v: ::capnp::serde_support::ByteBuf = map.next_value()?;".to_string()),
+            Line(format!("self.set_{}(&v.0);", styled_name))]),
+        type_::Struct(_) => Branch(vec![
+            Line("let builder = self.reborrow();".to_string()),
+            Line(format!("map.next_value_seed(::capnp::serde_support::StructSeed(move || builder.init_{}()))?;",
+                         styled_name))]),
+        type_::List(_) if is_deserializable_list(&typ)? => Branch(vec![
+            Line(format!("let v: ::capnp::serde_support::ListValue<{}> = map.next_value()?;",
+                         typ.type_string(gen, Leaf::Owned)?)),
+            Line(format!("v.write(|len| self.reborrow().init_{}(len)).map_err({})?;",
+                         styled_name, DE_ERROR))]),
+        type_::List(_) | type_::AnyPointer(_) =>
+            Line("map.next_value::<::capnp::serde_support::NullPointer>()?;".to_string()),
+        _ => Branch(vec![
+            Line("let v = map.next_value()?;".to_string()),
+            Line(format!("self.set_{}(v);", styled_name))]),
+    }))
+}
+
+/// Generates the serde implementations for the `Reader` and `Builder` of the struct with
+/// ID `node_id`. A generic struct's reader serializes only if its parameters' readers do.
+pub fn generate_serde_impls(gen: &GeneratorContext,
+                            node_id: u64,
+                            params: &TypeParameterTexts) -> ::capnp::Result<FormattedText> {
+    let node_reader = gen.get_node(node_id)?;
+    let struct_reader = match node_reader.which()? {
+        node::Struct(s) => s,
+        _ => return Err(Error::failed(format!("generate_serde_impls() expected a struct"))),
+    };
+    let display_name = node_reader.get_display_name()?;
+    let struct_name = &display_name[node_reader.get_display_name_prefix_length() as usize..];
+
+    let fields = struct_reader.get_fields()?;
+    let is_pure_union = fields.iter().all(|f| f.get_discriminant_value() != field::NO_DISCRIMINANT);
+    let has_union = struct_reader.get_discriminant_count() > 0;
+
+    let mut serialize_body = Vec::new();
+    let mut union_arms = Vec::new();
+    let mut field_arms = Vec::new();
+    let mut void_member_arms = Vec::new();
+    let mut field_count = if has_union { 1 } else { 0 };
+    for field in fields.iter() {
+        let field_name = get_field_name(field)?;
+        let name = field.get_name()?;
+        if let Some(statements) = deserialize_field(gen, &field)? {
+            field_arms.push(Line(format!("\"{}\" => {{", name)));
+            field_arms.push(Indent(Box::new(statements)));
+            field_arms.push(Line("}".to_string()));
+        }
+
+        if field.get_discriminant_value() == field::NO_DISCRIMINANT {
+            let getter = format!("self.reborrow().get_{}()", camel_to_snake_case(field_name));
+            if let Some((mut statements, v)) = serialize_value(&field, &getter)? {
+                statements.push(Line(format!("s.serialize_field(\"{}\", {})?;", name, v)));
+                serialize_body.push(Branch(statements));
+                field_count += 1;
+            }
+            continue;
+        }
+
+        if let field::Slot(slot) = field.which()? {
+            if let type_::Void(()) = slot.get_type()?.which()? {
+                void_member_arms.push(Line(format!("\"{}\" => {{ self.set_{}(()); Ok(()) }}",
+                                                   name, camel_to_snake_case(field_name))));
+            }
+        }
+        let arm = format!("::std::result::Result::Ok(Which::{}(_v)) => {{", capitalize_first_letter(field_name));
+        let statements = match serialize_value(&field, "_v")? {
+            None => vec![Line(format!("return Err({}(\"cannot serialize a capability\"));", SER_ERROR))],
+            Some((mut statements, v)) => {
+                if !is_pure_union {
+                    statements.push(Line(format!("s.serialize_field(\"{}\", {})?;", name, v)));
+                } else if v == "&()" {
+                    statements.push(Line(format!(
+                        "serializer.serialize_unit_variant(\"{}\", {}, \"{}\")",
+                        struct_name, field.get_discriminant_value(), name)));
+                } else {
+                    statements.push(Line(format!(
+                        "serializer.serialize_newtype_variant(\"{}\", {}, \"{}\", {})",
+                        struct_name, field.get_discriminant_value(), name, v)));
+                }
+                statements
+            }
+        };
+        union_arms.push(Line(arm));
+        union_arms.push(Indent(Box::new(Branch(statements))));
+        union_arms.push(Line("}".to_string()));
+    }
+
+    if has_union {
+        union_arms.push(Line(format!("::std::result::Result::Err(e) => return Err({}(e)),", SER_ERROR)));
+    }
+    let serialize_body = if is_pure_union && has_union {
+        vec![Line("match self.reborrow().which() {".to_string()),
+             Indent(Box::new(Branch(union_arms))),
+             Line("}".to_string())]
+    } else if field_count == 0 {
+        vec![Line("use ::capnp::serde::ser::SerializeStruct;".to_string()),
+             Line(format!("serializer.serialize_struct(\"{}\", 0)?.end()", struct_name))]
+    } else {
+        let mut body = vec![
+            Line("use ::capnp::serde::ser::SerializeStruct;".to_string()),
+            Line(format!("let mut s = serializer.serialize_struct(\"{}\", {})?;", struct_name, field_count))];
+        body.extend(serialize_body);
+        if has_union {
+            body.push(Line("match self.reborrow().which() {".to_string()));
+            body.push(Indent(Box::new(Branch(union_arms))));
+            body.push(Line("}".to_string()));
+        }
+        body.push(Line("s.end()".to_string()));
+        body
+    };
+
+    field_arms.push(Line("_ => { map.next_value::<::capnp::serde::de::IgnoredAny>()?; }".to_string()));
+    void_member_arms.push(Line(format!(
+        "_ => Err({}(format!(\"{} has no Void union member named {{:?}}\", name))),",
+        DE_ERROR, struct_name)));
+
+    let serialize_where_clause = if params.expanded_list.is_empty() {
+        String::new()
+    } else {
+        let bounds: Vec<String> = params.expanded_list.iter().map(|param| {
+            format!("{0}: for<'c> ::capnp::traits::Owned<'c>, for<'c> <{0} as ::capnp::traits::Owned<'c>>::Reader: ::capnp::serde::Serialize",
+                    param)
+        }).collect();
+        format!("where {} ", bounds.join(", "))
+    };
+
+    Ok(Branch(vec![
+        Line(format!("impl <'a,{0}> ::capnp::serde::Serialize for Reader<'a,{0}> {1} {{",
+                     params.params, serialize_where_clause)),
+        Indent(Box::new(Branch(vec![
+            Line("fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>".to_string()),
+            Indent(Box::new(Line("where S: ::capnp::serde::Serializer".to_string()))),
+            Line("{".to_string()),
+            Indent(Box::new(Branch(serialize_body))),
+            Line("}".to_string())]))),
+        Line("}".to_string()),
+        Line(format!("impl <'a,{0}> ::capnp::serde_support::DeserializeFields for Builder<'a,{0}> {1} {{",
+                     params.params, params.where_clause)),
+        Indent(Box::new(Branch(vec![
+            Line("fn deserialize_field<'de, A>(&mut self, name: &str, map: &mut A) -> ::std::result::Result<(), A::Error>".to_string()),
+            Indent(Box::new(Line("where A: ::capnp::serde::de::MapAccess<'de>".to_string()))),
+            Line("{".to_string()),
+            Indent(Box::new(Branch(vec![
+                Line("match name {".to_string()),
+                Indent(Box::new(Branch(field_arms))),
+                Line("}".to_string()),
+                Line("Ok(())".to_string())]))),
+            Line("}".to_string()),
+            Line("fn set_void_member<E>(&mut self, name: &str) -> ::std::result::Result<(), E>".to_string()),
+            Indent(Box::new(Line("where E: ::capnp::serde::de::Error".to_string()))),
+            Line("{".to_string()),
+            Indent(Box::new(Branch(vec![
+                Line("match name {".to_string()),
+                Indent(Box::new(Branch(void_member_arms))),
+                Line("}".to_string())]))),
+            Line("}".to_string())]))),
+        Line("}".to_string()),
+        Line(format!("impl <'a,'de,{0}> ::capnp::serde::de::DeserializeSeed<'de> for Builder<'a,{0}> {1} {{",
+                     params.params, params.where_clause)),
+        Indent(Box::new(Branch(vec![
+            Line("type Value = ();".to_string()),
+            Line("fn deserialize<D>(self, deserializer: D) -> ::std::result::Result<(), D::Error>".to_string()),
+            Indent(Box::new(Line("where D: ::capnp::serde::Deserializer<'de>".to_string()))),
+            Line("{".to_string()),
+            Indent(Box::new(Line("::capnp::serde_support::deserialize_struct(deserializer, || self)".to_string()))),
+            Line("}".to_string())]))),
+        Line("}".to_string()),
+    ]))
+}
+
+/// Generates the serde implementations for the enum named `name`, which goes by the
+/// names of its enumerants.
+pub fn generate_enum_serde_impls(name: &str) -> FormattedText {
+    Branch(vec![
+        Line(format!("impl ::capnp::serde::Serialize for {} {{", name)),
+        Indent(Box::new(Branch(vec![
+            Line("fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>".to_string()),
+            Indent(Box::new(Line("where S: ::capnp::serde::Serializer".to_string()))),
+            Line("{".to_string()),
+            Indent(Box::new(Line("serializer.serialize_str(self.name())".to_string()))),
+            Line("}".to_string())]))),
+        Line("}".to_string()),
+        Line(format!("impl <'de> ::capnp::serde::Deserialize<'de> for {} {{", name)),
+        Indent(Box::new(Branch(vec![
+            Line(format!("fn deserialize<D>(deserializer: D) -> ::std::result::Result<{}, D::Error>", name)),
+            Indent(Box::new(Line("where D: ::capnp::serde::Deserializer<'de>".to_string()))),
+            Line("{".to_string()),
+            Indent(Box::new(Branch(vec![
+                Line("let name: String = ::capnp::serde::Deserialize::deserialize(deserializer)?;".to_string()),
+                Line(format!("name.parse().map_err({})", DE_ERROR))]))),
+            Line("}".to_string())]))),
+        Line("}".to_string()),
+    ])
+}
+
+/// Generates a `Serialize` implementation for the `Client` of an interface, so that generic
+/// structs can be serialized when a parameter is bound to it. Serializing a capability fails.
+pub fn generate_client_serde_impl(bracketed_params: &str) -> FormattedText {
+    Branch(vec![
+        Line(format!("impl {0} ::capnp::serde::Serialize for Client{0} {{", bracketed_params)),
+        Indent(Box::new(Branch(vec![
+            Line("fn serialize<S>(&self, _serializer: S) -> ::std::result::Result<S::Ok, S::Error>".to_string()),
+            Indent(Box::new(Line("where S: ::capnp::serde::Serializer".to_string()))),
+            Line("{".to_string()),
+            Indent(Box::new(Line(format!("Err({}(\"cannot serialize a capability\"))", SER_ERROR)))),
+            Line("}".to_string())]))),
+        Line("}".to_string()),
+    ])
+}
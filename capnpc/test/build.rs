@@ -1,11 +1,55 @@
+extern crate capnp;
 extern crate capnpc;
 
+use capnpc::schema_capnp::{code_generator_request, node};
+
+/// Writes to `path` a request for a file with an interface constant. The only value of such a
+/// constant is null, which the schema language cannot express, so a Bool constant is compiled
+/// and then given the interface's type.
+fn write_interface_constant_request(path: &::std::path::Path) {
+    let mut message = capnpc::compiler::compile_source(
+        "interface-constant.capnp",
+        "@0xc19e3753fdb82fcb;\ninterface Foo {}\nconst nullFoo :Bool = true;\n")
+        .expect("compiling interface-constant.capnp");
+    let interface_id = {
+        let request: code_generator_request::Reader = message.get_root_as_reader().unwrap();
+        let nodes = request.get_nodes().unwrap();
+        nodes.iter().find(|n| n.get_display_name().unwrap().ends_with(":Foo")).unwrap().get_id()
+    };
+    {
+        let request: code_generator_request::Builder = message.get_root().unwrap();
+        let mut nodes = request.get_nodes().unwrap();
+        for i in 0..nodes.len() {
+            if let Ok(node::Const(mut c)) = nodes.reborrow().get(i).which() {
+                c.reborrow().init_type().init_interface().set_type_id(interface_id);
+                c.init_value().set_interface(());
+            }
+        }
+    }
+    let mut file = ::std::fs::File::create(path).unwrap();
+    capnp::serialize::write_message(&mut file, &message).unwrap();
+}
+
 fn main() {
      capnpc::CompilerCommand::new()
         .file("test.capnp")
         .file("schema/test-in-dir.capnp")
         .file("schema-with-src-prefix/test-in-src-prefix-dir.capnp")
         .src_prefix("schema-with-src-prefix")
+        .fill_random(true)
+        .native_types(true)
+        .debug_impls(true)
+        .serde(true)
+        .mock_servers(true)
+        .modules_file("capnp_modules.rs")
         .run()
         .expect("compiling schema");
+
+    let out_dir = ::std::path::PathBuf::from(::std::env::var_os("OUT_DIR").unwrap());
+    let request_path = out_dir.join("interface-constant.request");
+    write_interface_constant_request(&request_path);
+    capnpc::CompilerCommand::new()
+        .request_file(&request_path)
+        .run()
+        .expect("generating code for interface-constant.capnp");
 }
@@ -21,18 +21,17 @@
 
 #[macro_use]
 extern crate capnp;
+#[cfg(test)]
+extern crate quickcheck;
+#[cfg(test)]
+extern crate serde_json;
 
-pub mod test_capnp {
-    include!(concat!(env!("OUT_DIR"), "/test_capnp.rs"));
-}
-
-pub mod test_in_dir_capnp {
-    include!(concat!(env!("OUT_DIR"), "/schema/test_in_dir_capnp.rs"));
-}
+// Declares test_capnp, test_in_dir_capnp and test_in_src_prefix_dir_capnp.
+include!(concat!(env!("OUT_DIR"), "/capnp_modules.rs"));
 
-pub mod test_in_src_prefix_dir_capnp {
-  // The src_prefix gets stripped away, so the generated code ends up directly in OUT_DIR.
-  include!(concat!(env!("OUT_DIR"), "/test_in_src_prefix_dir_capnp.rs"));
+// Generated by build.rs from a request patched to hold an interface constant.
+pub mod interface_constant_capnp {
+    include!(concat!(env!("OUT_DIR"), "/interface_constant_capnp.rs"));
 }
 
 #[cfg(test)]
@@ -663,9 +662,253 @@ mod tests {
         assert_eq!(union_struct.get_union0().has_u0f0sp(), true);
     }
 
+    #[test]
+    fn test_union_switch_clears_pointers() {
+        use test_capnp::test_union;
+
+        let mut message = message::Builder::new_default();
+        let mut union_struct = message.init_root::<test_union::Builder>();
+        let empty_size = union_struct.total_size().unwrap().word_count;
+
+        union_struct.reborrow().get_union0().set_u0f0sp("abcdef");
+        assert!(union_struct.total_size().unwrap().word_count > empty_size);
+
+        // Switching to another member clears the text.
+        union_struct.reborrow().get_union0().set_u0f0s0(());
+        assert_eq!(union_struct.total_size().unwrap().word_count, empty_size);
+        match union_struct.reborrow().get_union0().which() {
+            Ok(test_union::union0::U0f0s0(())) => {}
+            _ => panic!()
+        }
+
+        // Setting the same member again replaces its value.
+        union_struct.reborrow().get_union0().set_u0f0sp("abc");
+        union_struct.reborrow().get_union0().set_u0f0sp("abcdef");
+        match union_struct.reborrow().get_union0().which() {
+            Ok(test_union::union0::U0f0sp(Ok(text))) => assert_eq!(&*text, "abcdef"),
+            _ => panic!()
+        }
+    }
+
+    #[test]
+    fn test_debug_impls() {
+        use test_capnp::{test_all_types, test_groups, test_union, TestEnum};
+
+        let mut message = message::Builder::new_default();
+        {
+            let mut all_types = message.init_root::<test_all_types::Builder>();
+            all_types.set_int32_field(-123);
+            all_types.set_text_field("foo");
+            all_types.set_enum_field(TestEnum::Bar);
+            {
+                let mut list = all_types.reborrow().init_u_int32_list(40);
+                for ii in 0..40 {
+                    list.set(ii, ii);
+                }
+            }
+            all_types.reborrow().init_struct_field().set_u_int8_field(7);
+
+            let text = format!("{:?}", all_types.reborrow_as_reader());
+            assert!(text.starts_with("TestAllTypes { voidField: (), boolField: false, int8Field: 0, "));
+            assert!(text.contains("int32Field: -123, "));
+            assert!(text.contains("textField: \"foo\", "));
+            assert!(text.contains("enumField: Bar, "));
+            assert!(text.contains("uInt32List: [0, 1, 2, "));
+            assert!(text.contains("31, ... and 8 more], "));
+            assert!(text.contains("structField: TestAllTypes { voidField: (), "));
+            assert!(text.contains("uInt8Field: 7, "));
+            assert!(text.contains("structField: null, "));
+            assert_eq!(format!("{:?}", all_types), text);
+        }
+
+        let mut message = message::Builder::new_default();
+        let mut union_struct = message.init_root::<test_union::Builder>();
+        union_struct.reborrow().get_union0().set_u0f0s32(5);
+        assert_eq!(format!("{:?}", union_struct), "TestUnion { union0: union0 { u0f0s32: 5 } }");
+
+        let mut message = message::Builder::new_default();
+        let mut groups = message.init_root::<test_groups::Builder>();
+        {
+            let mut baz = groups.reborrow().get_groups().init_baz();
+            baz.set_corge(1);
+            baz.set_grault("abc");
+        }
+        assert_eq!(format!("{:?}", groups),
+                   "TestGroups { groups: groups { baz: baz { corge: 1, grault: \"abc\", garply: \"\", \
+                    quz: 0.0, anEnum: Foo } } }");
+    }
+
+    #[test]
+    fn test_serde() {
+        use capnp::serde::de::DeserializeSeed;
+        use test_capnp::{test_all_types, test_groups, test_union, TestEnum};
+
+        let mut message = message::Builder::new_default();
+        {
+            let mut all_types = message.init_root::<test_all_types::Builder>();
+            all_types.set_int32_field(-123);
+            all_types.set_float64_field(1.5);
+            all_types.set_text_field("foo");
+            all_types.set_data_field(&[1, 2, 3]);
+            all_types.set_enum_field(TestEnum::Bar);
+            all_types.reborrow().init_struct_field().set_u_int8_field(7);
+            {
+                let mut list = all_types.reborrow().init_text_list(2);
+                list.set(0, "a");
+                list.set(1, "b");
+            }
+            {
+                let mut list = all_types.reborrow().init_enum_list(2);
+                list.set(0, TestEnum::Qux);
+                list.set(1, TestEnum::Foo);
+            }
+            all_types.reborrow().init_struct_list(2).get(1).set_int16_field(-4);
+        }
+        let json = serde_json::to_string(
+            &message.get_root_as_reader::<test_all_types::Reader>().unwrap()).unwrap();
+        assert!(json.starts_with("{\"voidField\":null,\"boolField\":false,\"int8Field\":0,"));
+        assert!(json.contains("\"int32Field\":-123,"));
+        assert!(json.contains("\"float64Field\":1.5,"));
+        assert!(json.contains("\"textField\":\"foo\",\"dataField\":[1,2,3],\"structField\":{\"voidField\":null,"));
+        assert!(json.contains("\"uInt8Field\":7,"));
+        assert!(json.contains("\"structField\":null,"));
+        assert!(json.contains("\"enumField\":\"bar\","));
+        assert!(json.contains("\"textList\":[\"a\",\"b\"],"));
+        assert!(json.contains("\"enumList\":[\"qux\",\"foo\"]"));
+
+        // Reading the JSON back into a builder reproduces the message.
+        let mut message2 = message::Builder::new_default();
+        message2.init_root::<test_all_types::Builder>()
+            .deserialize(&mut serde_json::Deserializer::from_str(&json)).unwrap();
+        let json2 = serde_json::to_string(
+            &message2.get_root_as_reader::<test_all_types::Reader>().unwrap()).unwrap();
+        assert_eq!(json, json2);
+
+        // Fields that are absent are left unset, and unknown fields are skipped.
+        let mut message = message::Builder::new_default();
+        {
+            let mut all_types = message.init_root::<test_all_types::Builder>();
+            all_types.reborrow()
+                .deserialize(&mut serde_json::Deserializer::from_str(
+                    "{\"uInt16Field\": 9, \"noSuchField\": [1, {}], \"int64List\": [-1, 2]}"))
+                .unwrap();
+            let reader = all_types.reborrow_as_reader();
+            assert_eq!(reader.get_u_int16_field(), 9);
+            assert_eq!(reader.get_int64_list().unwrap().len(), 2);
+            assert_eq!(reader.get_int64_list().unwrap().get(0), -1);
+            assert!(!reader.has_text_field());
+
+            let error = all_types.reborrow()
+                .deserialize(&mut serde_json::Deserializer::from_str("{\"enumField\": \"nope\"}"));
+            assert!(error.is_err());
+        }
+
+        // A union on its own is an externally tagged enum.
+        let mut message = message::Builder::new_default();
+        {
+            let mut union_struct = message.init_root::<test_union::Builder>();
+            union_struct.reborrow().get_union0().set_u0f0s32(5);
+            assert_eq!(serde_json::to_string(&union_struct.reborrow_as_reader()).unwrap(),
+                       "{\"union0\":{\"u0f0s32\":5}}");
+            union_struct.reborrow().get_union0().set_u0f0s0(());
+            assert_eq!(serde_json::to_string(&union_struct.reborrow_as_reader()).unwrap(),
+                       "{\"union0\":\"u0f0s0\"}");
+
+            union_struct.reborrow()
+                .deserialize(&mut serde_json::Deserializer::from_str("{\"union0\": {\"u0f0sp\": \"abc\"}}"))
+                .unwrap();
+            match union_struct.reborrow_as_reader().get_union0().which().unwrap() {
+                test_union::union0::U0f0sp(v) => assert_eq!(v.unwrap(), "abc"),
+                _ => panic!("expected u0f0sp"),
+            }
+            union_struct.reborrow()
+                .deserialize(&mut serde_json::Deserializer::from_str("{\"union0\": \"u0f0s0\"}"))
+                .unwrap();
+            match union_struct.reborrow_as_reader().get_union0().which().unwrap() {
+                test_union::union0::U0f0s0(()) => (),
+                _ => panic!("expected u0f0s0"),
+            }
+        }
+
+        let mut message = message::Builder::new_default();
+        {
+            let mut groups = message.init_root::<test_groups::Builder>();
+            groups.reborrow()
+                .deserialize(&mut serde_json::Deserializer::from_str(
+                    "{\"groups\": {\"baz\": {\"corge\": 1, \"grault\": \"abc\", \"anEnum\": \"qux\"}}}"))
+                .unwrap();
+            assert_eq!(serde_json::to_string(&groups.reborrow_as_reader()).unwrap(),
+                       "{\"groups\":{\"baz\":{\"corge\":1,\"grault\":\"abc\",\"garply\":\"\",\
+                        \"quz\":0.0,\"anEnum\":\"qux\"}}}");
+        }
+    }
+
+    #[test]
+    fn test_implicit_method_params() {
+        use capnp::{data, text};
+        use capnp::capability::Promise;
+        use test_capnp::{test_generics, test_implicit_method_params};
+
+        // The params struct is generic over the method's implicit parameters.
+        let mut message = message::Builder::new_default();
+        {
+            let mut params = message.init_root::<test_implicit_method_params::call_params::Builder<text::Owned, data::Owned>>();
+            params.set_foo("foo").unwrap();
+            params.set_bar(&[1u8, 2, 3][..]).unwrap();
+        }
+        let params = message.get_root_as_reader::<test_implicit_method_params::call_params::Reader<text::Owned, data::Owned>>().unwrap();
+        assert_eq!(params.get_foo().unwrap(), "foo");
+        assert_eq!(params.get_bar().unwrap(), &[1, 2, 3]);
+
+        // Clients and servers get generic methods.
+        #[allow(dead_code)]
+        fn request(client: &test_implicit_method_params::Client)
+            -> ::capnp::capability::Request<test_implicit_method_params::call_params::Owned<text::Owned, data::Owned>,
+                                            test_generics::Owned<text::Owned, data::Owned>>
+        {
+            client.call_request::<text::Owned, data::Owned>()
+        }
+
+        struct ServerImpl;
+        impl test_implicit_method_params::Server for ServerImpl {
+            fn call<T, U>(&mut self,
+                          _: test_implicit_method_params::CallParams<T, U>,
+                          _: test_implicit_method_params::CallResults<T, U>)
+                          -> Promise<(), ::capnp::Error>
+                where T: for<'c> ::capnp::traits::Owned<'c>, U: for<'c> ::capnp::traits::Owned<'c>
+            {
+                Promise::ok(())
+            }
+        }
+        let _ = test_implicit_method_params::ToClient::new(ServerImpl);
+    }
+
+    #[test]
+    fn test_enum_names() {
+        use test_capnp::{test_generics, renamed_struct, TestEnum};
+
+        assert_eq!(TestEnum::ALL_VARIANTS.len(), 8);
+        assert_eq!(TestEnum::ALL_VARIANTS[2], TestEnum::Baz);
+        assert_eq!(TestEnum::Garply.name(), "garply");
+        assert_eq!(TestEnum::Qux.to_string(), "qux");
+        assert_eq!("corge".parse::<TestEnum>().unwrap(), TestEnum::Corge);
+        assert!("Corge".parse::<TestEnum>().is_err());
+
+        // Names are those in the schema, even if the Rust names differ.
+        assert_eq!(renamed_struct::RenamedEnum::Qux.name(), "baz");
+        assert_eq!("baz".parse::<renamed_struct::RenamedEnum>().unwrap(),
+                   renamed_struct::RenamedEnum::Qux);
+
+        // Enums nested in generic structs.
+        let modes: Vec<String> = test_generics::inner2::Mode::ALL_VARIANTS.iter().map(|m| m.to_string()).collect();
+        assert_eq!(modes, ["firstMode", "secondMode"]);
+        assert_eq!("secondMode".parse::<test_generics::inner2::Mode>().unwrap(),
+                   test_generics::inner2::Mode::SecondMode);
+    }
+
     #[test]
     fn test_constants() {
-        use test_capnp::{test_constants, TestEnum};
+        use test_capnp::{test_all_types, test_constants, TestEnum};
         assert_eq!(test_constants::VOID_CONST, ());
         assert_eq!(test_constants::BOOL_CONST, true);
         assert_eq!(test_constants::INT8_CONST, -123);
@@ -734,6 +977,26 @@ mod tests {
         assert_eq!(struct_list.get(0).get_text_field().unwrap(), "structlist 1");
         assert_eq!(struct_list.get(1).get_text_field().unwrap(), "structlist 2");
         assert_eq!(struct_list.get(2).get_text_field().unwrap(), "structlist 3");
+
+        let any_pointer = test_constants::ANY_POINTER_CONST.get().unwrap();
+        let any_struct: test_all_types::Reader = any_pointer.get_as().unwrap();
+        assert_eq!(any_struct.get_text_field().unwrap(), "baz");
+        assert_eq!(any_struct.get_struct_field().unwrap().get_text_field().unwrap(), "nested");
+
+        let any_struct = test_constants::ANY_STRUCT_CONST.get().unwrap();
+        let any_struct: test_all_types::Reader = any_struct.get_as().unwrap();
+        assert_eq!(any_struct.get_int32_field(), -78901234);
+
+        let any_list = test_constants::ANY_LIST_CONST.get().unwrap();
+        let any_list: ::capnp::primitive_list::Reader<i32> = any_list.get_as().unwrap();
+        assert_eq!(any_list.iter().collect::<Vec<_>>(), vec![111111111, -111111111]);
+    }
+
+    #[test]
+    fn interface_constant() {
+        // A null capability, on which every call fails.
+        let foo: ::interface_constant_capnp::foo::Client = ::interface_constant_capnp::null_foo();
+        assert_eq!(foo.client.hook.get_brand(), 0);
     }
 
     #[test]
@@ -904,6 +1167,33 @@ mod tests {
             message.get_root::<test_all_types::Builder>().unwrap().into_reader());
     }
 
+    #[test]
+    fn canonical_digest_and_builder_from_reader_within_traversal_limit() {
+        use test_capnp::{test_all_types};
+
+        let builder_options = message::HeapAllocator::new()
+            .first_segment_words(1).allocation_strategy(::capnp::message::AllocationStrategy::FixedSize);
+        let mut message = message::Builder::new(builder_options);
+        ::test_util::init_test_message(message.init_root());
+        let segments = message.get_segments_for_output();
+        let expected = message::Reader::new(message::SegmentArray::new(&segments), ReaderOptions::new())
+            .canonicalize().unwrap();
+
+        // Enough to walk the whole message once, but not twice. Reading the message, digesting
+        // it and copying it into a builder each walk all of it.
+        let limit = segments.iter().map(|s| s.len() as u64).sum::<u64>() * 3 / 2;
+        let reader = message::Reader::new(message::SegmentArray::new(&segments),
+                                          *ReaderOptions::new().traversal_limit_in_words(limit));
+        ::test_util::CheckTestMessage::check_test_message(
+            reader.get_root::<test_all_types::Reader>().unwrap());
+        let mut digest = Vec::new();
+        reader.canonical_digest(&mut digest).unwrap();
+        assert_eq!(&digest[..], ::capnp::Word::words_to_bytes(&expected));
+        let copy = message::Builder::from_reader(message::HeapAllocator::new(), &reader).unwrap();
+        ::test_util::CheckTestMessage::check_test_message(
+            copy.get_root_as_reader::<test_all_types::Reader>().unwrap());
+    }
+
     #[test]
     fn setters() {
         use test_capnp::{test_all_types};
@@ -945,6 +1235,55 @@ mod tests {
         }
     }
 
+    #[test]
+    fn list_setters_from_slices_and_iterators() {
+        use test_capnp::{test_all_types, TestEnum};
+
+        let mut message = message::Builder::new_default();
+        let mut all_types = message.init_root::<test_all_types::Builder>();
+
+        all_types.set_u_int32_list(&[1, 2, 3][..]).unwrap();
+        all_types.set_bool_list_from(vec![true, false]).unwrap();
+        all_types.set_float64_list_from((0..3).map(|i| i as f64 * 0.5)).unwrap();
+        all_types.set_enum_list(&[TestEnum::Foo, TestEnum::Garply][..]).unwrap();
+        all_types.set_enum_list_from(&[TestEnum::Bar]).unwrap();
+        all_types.set_text_list(&["foo", "bar"][..]).unwrap();
+        all_types.set_data_list_from(vec![vec![1u8, 2], vec![]]).unwrap();
+
+        let mut message2 = message::Builder::new_default();
+        {
+            let mut other = message2.init_root::<test_all_types::Builder>();
+            let mut structs = other.reborrow().init_struct_list(2);
+            structs.reborrow().get(0).set_int32_field(-7);
+            structs.reborrow().get(1).set_text_field("baz");
+        }
+        let other = message2.get_root_as_reader::<test_all_types::Reader>().unwrap();
+        all_types.set_struct_list_from(other.get_struct_list().unwrap().iter()).unwrap();
+
+        let reader = all_types.into_reader();
+        let u_int32_list = reader.get_u_int32_list().unwrap();
+        assert_eq!(u_int32_list.iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+        let bool_list = reader.get_bool_list().unwrap();
+        assert_eq!(bool_list.iter().collect::<Vec<_>>(), vec![true, false]);
+        let float64_list = reader.get_float64_list().unwrap();
+        assert_eq!(float64_list.iter().collect::<Vec<_>>(), vec![0.0, 0.5, 1.0]);
+        let enum_list = reader.get_enum_list().unwrap();
+        assert_eq!(enum_list.len(), 1);
+        assert!(enum_list.get(0).unwrap() == TestEnum::Bar);
+        let text_list = reader.get_text_list().unwrap();
+        assert_eq!(text_list.len(), 2);
+        assert_eq!(text_list.get(0).unwrap(), "foo");
+        assert_eq!(text_list.get(1).unwrap(), "bar");
+        let data_list = reader.get_data_list().unwrap();
+        assert_eq!(data_list.len(), 2);
+        assert_eq!(data_list.get(0).unwrap(), &[1, 2]);
+        assert!(data_list.get(1).unwrap().is_empty());
+        let struct_list = reader.get_struct_list().unwrap();
+        assert_eq!(struct_list.len(), 2);
+        assert_eq!(struct_list.get(0).get_int32_field(), -7);
+        assert_eq!(struct_list.get(1).get_text_field().unwrap(), "baz");
+    }
+
     #[test]
     fn double_far_pointer() {
         let segment0: &[::capnp::Word] = &[
@@ -1345,4 +1684,173 @@ mod tests {
         assert_eq!(11, list_reader.get(0).get_int8_field());
         ::test_util::CheckTestMessage::check_test_message(list_reader.get(1));
     }
+
+    #[test]
+    fn fill_random() {
+        use quickcheck::{Gen, Rng};
+        use test_capnp::{test_all_types, test_union};
+
+        // An xorshift generator, so that each seed gives a reproducible message.
+        struct SeededGen(u32);
+
+        impl Rng for SeededGen {
+            fn next_u32(&mut self) -> u32 {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 17;
+                self.0 ^= self.0 << 5;
+                self.0
+            }
+        }
+
+        impl Gen for SeededGen {
+            fn size(&self) -> usize { 8 }
+        }
+
+        // Checks that structs nest at most `depth` levels below `reader`, and that its lists
+        // of structs have at most `depth` elements.
+        fn check_depth(reader: test_all_types::Reader, depth: u32) {
+            let struct_list = reader.get_struct_list().unwrap();
+            assert!(struct_list.len() < depth + 1);
+            for element in struct_list.iter() {
+                check_depth(element, depth - 1);
+            }
+            let struct_field = reader.get_struct_field().unwrap();
+            if depth == 0 {
+                assert!(!struct_field.has_struct_field());
+                assert!(!struct_field.has_struct_list());
+            } else {
+                check_depth(struct_field, depth - 1);
+            }
+        }
+
+        let mut discriminants = ::std::collections::BTreeSet::new();
+        for seed in 1..50 {
+            let mut g = SeededGen(seed);
+            let mut message = message::Builder::new_default();
+            test_all_types::fill_random(message.init_root(), &mut g, 3);
+            check_depth(message.get_root_as_reader().unwrap(), 3);
+
+            let mut message = message::Builder::new_default();
+            test_union::fill_random(message.init_root(), &mut g, 3);
+            let root: test_union::Reader = message.get_root_as_reader().unwrap();
+            discriminants.insert(match root.get_union0().which().unwrap() {
+                test_union::union0::U0f0s0(()) => 0,
+                test_union::union0::U0f0s1(_) => 1,
+                test_union::union0::U0f0s8(_) => 2,
+                test_union::union0::U0f0s16(_) => 3,
+                test_union::union0::U0f0s32(_) => 4,
+                test_union::union0::U0f0s64(_) => 5,
+                test_union::union0::U0f0sp(_) => 6,
+            });
+        }
+        assert!(discriminants.len() > 1);
+    }
+
+    #[test]
+    fn native_types() {
+        use test_capnp::{test_all_types, test_union};
+
+        let mut message = message::Builder::new_default();
+        ::test_util::init_test_message(message.init_root());
+        let native = test_all_types::Native::from_reader(message.get_root_as_reader().unwrap()).unwrap();
+        assert_eq!(native.text_field, "foo");
+        assert!(native.struct_field.is_some());
+        drop(message);
+
+        let mut message2 = message::Builder::new_default();
+        native.write_to_builder(message2.init_root()).unwrap();
+        ::test_util::CheckTestMessage::check_test_message(
+            message2.get_root_as_reader::<test_all_types::Reader>().unwrap());
+        let copy = test_all_types::Native::from_reader(message2.get_root_as_reader().unwrap()).unwrap();
+        assert!(copy == native);
+
+        let mut message3 = message::Builder::new_default();
+        {
+            let root: test_union::Builder = message3.init_root();
+            root.get_union0().set_u0f0sp("abcdef");
+        }
+        let native = test_union::Native::from_reader(message3.get_root_as_reader().unwrap()).unwrap();
+        match native.union0.which {
+            test_union::union0::NativeWhich::U0f0sp(ref s) => assert_eq!(s, "abcdef"),
+            _ => panic!("expected U0f0sp"),
+        }
+
+        let mut message4 = message::Builder::new_default();
+        native.write_to_builder(message4.init_root()).unwrap();
+        let root: test_union::Reader = message4.get_root_as_reader().unwrap();
+        match root.get_union0().which().unwrap() {
+            test_union::union0::U0f0sp(s) => assert_eq!(s.unwrap(), "abcdef"),
+            _ => panic!("expected U0f0sp"),
+        }
+    }
+
+    #[test]
+    fn native_type_of_struct_with_which_field() {
+        use test_capnp::test_native_which;
+
+        let mut message = message::Builder::new_default();
+        {
+            let mut root: test_native_which::Builder = message.init_root();
+            root.set_which("bar");
+            root.set_foo(123);
+        }
+        let native = test_native_which::Native::from_reader(message.get_root_as_reader().unwrap()).unwrap();
+        assert_eq!(native.which, "bar");
+        assert_eq!(native.which_, test_native_which::NativeWhich::Foo(123));
+        assert_eq!(format!("{:?}", native), "Native { which: \"bar\", which_: Foo(123) }");
+    }
+
+    #[test]
+    fn name_annotation() {
+        use test_capnp::renamed_struct;
+
+        let mut message = message::Builder::new_default();
+        {
+            let mut root: renamed_struct::Builder = message.init_root();
+            root.set_good_field_name(true);
+            root.set_another_good_field_name(renamed_struct::RenamedEnum::Qux);
+            let renamed_union = root.get_renamed_union();
+            let mut qux = renamed_union.init_qux();
+            qux.set_good_nested_field_name(true);
+        }
+
+        let root: renamed_struct::Reader = message.get_root_as_reader().unwrap();
+        match root.which().unwrap() {
+            renamed_struct::GoodFieldName(true) => (),
+            _ => panic!("expected GoodFieldName(true)"),
+        }
+        assert!(root.get_another_good_field_name().unwrap() == renamed_struct::RenamedEnum::Qux);
+        match root.get_renamed_union().which().unwrap() {
+            renamed_struct::renamed_union::Qux(qux) => {
+                let qux: renamed_struct::renamed_nested_struct::Reader = qux.unwrap();
+                assert!(qux.get_good_nested_field_name());
+            }
+            _ => panic!("expected Qux"),
+        }
+    }
+
+    #[test]
+    fn registries() {
+        use capnp::traits::HasTypeId;
+        use test_capnp::{renamed_struct, test_extends, test_groups, test_interface, test_union};
+
+        assert_eq!(::test_capnp::type_name(test_union::Builder::type_id()),
+                   Some("test.capnp:TestUnion"));
+        assert_eq!(::test_capnp::type_name(test_groups::groups::foo::Builder::type_id()),
+                   Some("test.capnp:TestGroups.groups.foo"));
+        assert_eq!(::test_capnp::type_name(::test_capnp::TestEnum::type_id()),
+                   Some("test.capnp:TestEnum"));
+        assert_eq!(::test_capnp::type_name(test_interface::Client::type_id()),
+                   Some("test.capnp:TestInterface"));
+        assert_eq!(::test_capnp::type_name(0), None);
+
+        assert_eq!(test_union::FIELD_NAMES, &["union0"]);
+        // Fields are listed in the order of their ordinals, which for groups are their first ones.
+        assert_eq!(test_groups::groups::FIELD_NAMES, &["foo", "baz", "bar"]);
+        assert_eq!(renamed_struct::FIELD_NAMES[0], "badFieldName");
+
+        assert_eq!(test_interface::METHOD_NAMES, &["foo", "bar", "baz", "bazz"]);
+        assert_eq!(test_extends::method_name(2), Some("grault"));
+        assert_eq!(test_extends::method_name(3), None);
+    }
 }